@@ -2,8 +2,14 @@
 //! evaluation of expressions.
 
 use crate::expression::Expression;
+use crate::intern::{self, Sym};
 use crate::util::Str;
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, stderr, stdout, BufRead, BufWriter, Write},
+    rc::Rc,
+};
 
 #[cfg(feature = "enable_rand")]
 use rand::prelude::*;
@@ -14,7 +20,7 @@ type StructId = usize;
 /// An individual scope in the evaluation context.
 #[derive(Debug)]
 struct Scope {
-    bindings: HashMap<String, Expression>,
+    bindings: HashMap<Sym, Expression>,
     structs: HashMap<String, StructId>,
 }
 
@@ -27,6 +33,81 @@ impl Default for Scope {
     }
 }
 
+/// The source from which scripts read input.
+pub enum Input {
+    /// The standard input of the process.
+    Stdin,
+
+    /// A custom reader, such as a queue of input provided by an embedder.
+    Reader(Box<dyn BufRead>),
+}
+
+impl fmt::Debug for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Stdin => write!(f, "Stdin"),
+            Input::Reader(_) => write!(f, "Reader"),
+        }
+    }
+}
+
+/// The sink to which scripts write output.
+pub enum Output {
+    /// The standard output of the process.
+    Stdout,
+
+    /// The standard error of the process.
+    Stderr,
+
+    /// A custom writer, such as a buffer provided by an embedder.
+    Writer(Box<dyn Write>),
+
+    /// Another sink whose output is buffered until it is flushed.
+    Buffered(BufWriter<Box<Output>>),
+}
+
+impl Output {
+    /// Flushes the output, unless it is buffered, in which case the output is
+    /// left to be flushed when the buffer is full or its owner flushes it.
+    pub fn flush_unbuffered(&mut self) -> io::Result<()> {
+        match self {
+            Output::Buffered(_) => Ok(()),
+            other => other.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => stdout().write(buf),
+            Output::Stderr => stderr().write(buf),
+            Output::Writer(writer) => writer.write(buf),
+            Output::Buffered(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => stdout().flush(),
+            Output::Stderr => stderr().flush(),
+            Output::Writer(writer) => writer.flush(),
+            Output::Buffered(writer) => writer.flush(),
+        }
+    }
+}
+
+impl fmt::Debug for Output {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Output::Stdout => write!(f, "Stdout"),
+            Output::Stderr => write!(f, "Stderr"),
+            Output::Writer(_) => write!(f, "Writer"),
+            Output::Buffered(_) => write!(f, "Buffered"),
+        }
+    }
+}
+
 /// Represents the evaluation context for use during the evaluation of rlisp
 /// expressions. It provides a means of accessing stored variables and
 /// information about custom struct types.
@@ -34,11 +115,20 @@ impl Default for Scope {
 pub struct Context {
     scopes: Vec<Scope>,
     struct_count: usize,
+    struct_fields: HashMap<StructId, Rc<[Str]>>,
+    gensym_count: usize,
 
     #[cfg(feature = "enable_rand")]
     rng: ThreadRng,
 
     read_files: HashSet<Str>,
+    exit_on_error: bool,
+    zero_division_error: bool,
+    program_args: Vec<Str>,
+    input: Input,
+    peeked_char: Option<char>,
+    output: Output,
+    error_output: Output,
 }
 
 impl Default for Context {
@@ -53,11 +143,20 @@ impl Context {
         Context {
             scopes: vec![Scope::default()],
             struct_count: 0,
+            struct_fields: HashMap::new(),
+            gensym_count: 0,
 
             #[cfg(feature = "enable_rand")]
             rng: thread_rng(),
 
             read_files: HashSet::new(),
+            exit_on_error: false,
+            zero_division_error: true,
+            program_args: Vec::new(),
+            input: Input::Stdin,
+            peeked_char: None,
+            output: Output::Stdout,
+            error_output: Output::Stderr,
         }
     }
 
@@ -69,20 +168,45 @@ impl Context {
     /// Attempts to retrieve the value stored at the specified key in the
     /// `Context`.
     pub fn get(&self, key: impl AsRef<str>) -> Option<&Expression> {
+        intern::lookup(key.as_ref()).and_then(|id| self.get_by_id(id))
+    }
+
+    /// Attempts to retrieve the value bound to the specified symbol in the
+    /// `Context`. This avoids looking up the name of the symbol.
+    pub fn get_symbol(&self, sym: &Sym) -> Option<&Expression> {
+        self.get_by_id(sym.id())
+    }
+
+    fn get_by_id(&self, id: u32) -> Option<&Expression> {
         self.scopes
             .iter()
             .rev()
-            .filter_map(|scope| scope.bindings.get(key.as_ref()))
+            .filter_map(|scope| scope.bindings.get(&id))
             .next()
     }
 
+    /// Produces the names of all bindings visible from the current scope,
+    /// sorted alphabetically. Names bound in several scopes appear only once.
+    pub fn binding_names(&self) -> Vec<Str> {
+        let names: HashSet<&Sym> = self
+            .scopes
+            .iter()
+            .flat_map(|scope| scope.bindings.keys())
+            .collect();
+        let mut names: Vec<Str> =
+            names.into_iter().map(|name| name.name().clone()).collect();
+        names.sort();
+        names
+    }
+
     /// Attempts to retrieve a mutable reference to the value stored at the
     /// specified key in the `Context`.
     pub fn get_mut(&mut self, key: impl AsRef<str>) -> Option<&mut Expression> {
+        let id = intern::lookup(key.as_ref())?;
         self.scopes
             .iter_mut()
             .rev()
-            .filter_map(|scope| scope.bindings.get_mut(key.as_ref()))
+            .filter_map(|scope| scope.bindings.get_mut(&id))
             .next()
     }
 
@@ -92,33 +216,67 @@ impl Context {
         ident: impl ToString,
         value: impl Into<Expression>,
     ) {
-        let ident = ident.to_string();
+        self.insert_symbol(intern::intern(&ident.to_string()), value);
+    }
+
+    /// Inserts the specified value into the `Context` at the current scope,
+    /// bound to the specified symbol.
+    pub fn insert_symbol(&mut self, sym: Sym, value: impl Into<Expression>) {
         self.scopes
             .last_mut()
-            .map(|scope| scope.bindings.insert(ident, value.into()));
+            .map(|scope| scope.bindings.insert(sym, value.into()));
     }
 
     pub fn remove(&mut self, ident: impl AsRef<str>) {
-        let ident = ident.as_ref();
+        if let Some(id) = intern::lookup(ident.as_ref()) {
+            self.scopes
+                .last_mut()
+                .map(|scope| scope.bindings.remove(&id));
+        }
+    }
+
+    /// Removes the binding of the specified name from the innermost scope that
+    /// defines it, producing the removed value. If no scope defines the name,
+    /// `None` is returned.
+    pub fn undefine(&mut self, ident: impl AsRef<str>) -> Option<Expression> {
+        let id = intern::lookup(ident.as_ref())?;
         self.scopes
-            .last_mut()
-            .map(|scope| scope.bindings.remove(ident));
+            .iter_mut()
+            .rev()
+            .find(|scope| scope.bindings.contains_key(&id))
+            .and_then(|scope| scope.bindings.remove(&id))
     }
 
-    /// Defines a struct with the specified name in the `Context`. If the
-    /// scopes of the `Context` are empty, `None` is returned. Otherwise, a
-    /// `StructId` is returned.
-    pub fn define_struct(&mut self, name: impl ToString) -> Option<StructId> {
+    /// Defines a struct with the specified name and field names in the
+    /// `Context`. If the scopes of the `Context` are empty, `None` is
+    /// returned. Otherwise, a `StructId` is returned.
+    pub fn define_struct(
+        &mut self,
+        name: impl ToString,
+        fields: impl Into<Rc<[Str]>>,
+    ) -> Option<StructId> {
         if let Some(scope) = self.scopes.last_mut() {
             self.struct_count += 1;
             let id = self.struct_count;
             scope.structs.insert(name.to_string(), id);
+            self.struct_fields.insert(id, fields.into());
             Some(id)
         } else {
             None
         }
     }
 
+    /// Looks up the field names of the struct with the specified name in the
+    /// `Context`.
+    pub fn get_struct_fields(
+        &self,
+        name: impl AsRef<str>,
+    ) -> Option<Rc<[Str]>> {
+        self.get_struct_id(name)
+            .and_then(|id| self.struct_fields.get(&id))
+            .cloned()
+    }
+
     /// Looks up the `StructId` of the struct with the specified name in the
     /// `Context`.
     pub fn get_struct_id(&self, name: impl AsRef<str>) -> Option<StructId> {
@@ -130,6 +288,14 @@ impl Context {
             .map(Clone::clone)
     }
 
+    /// Produces the next number in the `Context`'s sequence of generated
+    /// symbols.
+    pub fn next_gensym(&mut self) -> usize {
+        let count = self.gensym_count;
+        self.gensym_count += 1;
+        count
+    }
+
     /// Ascends one level of scope.
     pub fn ascend_scope(&mut self) {
         self.scopes.push(Scope::default());
@@ -158,4 +324,84 @@ impl Context {
     pub fn has_read_file(&self, file_name: &Str) -> bool {
         self.read_files.contains(file_name)
     }
+
+    /// Determines whether or not an uncaught exception at the top level should
+    /// terminate the program.
+    pub fn exit_on_error(&self) -> bool {
+        self.exit_on_error
+    }
+
+    /// Sets whether or not an uncaught exception at the top level should
+    /// terminate the program.
+    pub fn set_exit_on_error(&mut self, exit_on_error: bool) {
+        self.exit_on_error = exit_on_error;
+    }
+
+    /// Determines whether or not dividing by zero throws an exception, rather
+    /// than producing an infinite or `NaN` quotient.
+    pub fn zero_division_error(&self) -> bool {
+        self.zero_division_error
+    }
+
+    /// Sets whether or not dividing by zero throws an exception, rather than
+    /// producing an infinite or `NaN` quotient.
+    pub fn set_zero_division_error(&mut self, zero_division_error: bool) {
+        self.zero_division_error = zero_division_error;
+    }
+
+    /// Produces the command-line arguments passed to the running script.
+    pub fn program_args(&self) -> &[Str] {
+        &self.program_args
+    }
+
+    /// Sets the command-line arguments passed to the running script.
+    pub fn set_program_args(&mut self, args: Vec<Str>) {
+        self.program_args = args;
+    }
+
+    /// Produces the source from which scripts read input.
+    pub fn input(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// Sets the source from which scripts read input, replacing standard
+    /// input.
+    pub fn set_input(&mut self, reader: impl BufRead + 'static) {
+        self.input = Input::Reader(Box::new(reader));
+        self.peeked_char = None;
+    }
+
+    /// Produces the character that has been read from the input in order to
+    /// peek at it, but which has not yet been returned by a read. Reads from
+    /// the input should take this character before reading any further.
+    pub fn peeked_char(&mut self) -> &mut Option<char> {
+        &mut self.peeked_char
+    }
+
+    /// Produces the sink to which scripts write output.
+    pub fn output(&mut self) -> &mut Output {
+        &mut self.output
+    }
+
+    /// Sets the sink to which scripts write output, replacing standard output.
+    pub fn set_output(&mut self, writer: impl Write + 'static) {
+        self.output = Output::Writer(Box::new(writer));
+    }
+
+    /// Replaces the sink to which scripts write output with the specified
+    /// sink, producing the previous sink.
+    pub fn replace_output(&mut self, output: Output) -> Output {
+        std::mem::replace(&mut self.output, output)
+    }
+
+    /// Produces the sink to which scripts write diagnostics, such as errors.
+    pub fn error_output(&mut self) -> &mut Output {
+        &mut self.error_output
+    }
+
+    /// Sets the sink to which scripts write diagnostics, replacing standard
+    /// error.
+    pub fn set_error_output(&mut self, writer: impl Write + 'static) {
+        self.error_output = Output::Writer(Box::new(writer));
+    }
 }
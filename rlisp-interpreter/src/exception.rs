@@ -6,7 +6,11 @@
 //! * `Syntax`
 //! * `Custom`
 
-use crate::{expression::Expression, util::Str};
+use crate::{
+    expression::Expression,
+    location::{position_of, symbol_position_of, Position},
+    util::Str,
+};
 use im::ConsList;
 use std::fmt;
 
@@ -18,12 +22,17 @@ pub type ErrorCode = u16;
 pub struct Exception {
     pub stack: ConsList<Expression>,
     pub data: ExceptionData,
+
+    /// The source position of the undefined symbol, or otherwise of the
+    /// innermost form in the stack whose position is known.
+    pub position: Option<Position>,
 }
 
 impl Exception {
     pub fn arity(expected: usize, found: usize) -> Exception {
         Exception {
             stack: ConsList::new(),
+            position: None,
             data: Arity(expected, found),
         }
     }
@@ -34,6 +43,7 @@ impl Exception {
     ) -> Exception {
         Exception {
             stack: ConsList::new(),
+            position: None,
             data: Signature(expected.into(), found.into()),
         }
     }
@@ -41,6 +51,7 @@ impl Exception {
     pub fn custom(code: ErrorCode, description: impl Into<Str>) -> Exception {
         Exception {
             stack: ConsList::new(),
+            position: None,
             data: Custom(code, description.into()),
         }
     }
@@ -48,6 +59,7 @@ impl Exception {
     pub fn undefined(symbol: impl Into<Str>) -> Exception {
         Exception {
             stack: ConsList::new(),
+            position: None,
             data: Undefined(symbol.into()),
         }
     }
@@ -55,15 +67,31 @@ impl Exception {
     pub fn syntax(code: ErrorCode, description: impl Into<Str>) -> Exception {
         Exception {
             stack: ConsList::new(),
+            position: None,
             data: Syntax(code, description.into()),
         }
     }
 
+    /// Produces the exception with the specified form pushed onto its stack.
+    /// If the exception does not yet have a position, it takes that of the
+    /// undefined symbol within the form, or otherwise that of the form, if
+    /// known.
     pub fn extend(&self, expr: &Expression) -> Exception {
-        let Exception { stack, data } = self.clone();
+        let Exception {
+            stack,
+            data,
+            position,
+        } = self.clone();
+        let position = position.or_else(|| match expr {
+            Expression::Cons(list) => {
+                data.undefined_position(list).or_else(|| position_of(list))
+            }
+            _ => None,
+        });
         Exception {
             stack: stack.cons(expr.clone()),
             data,
+            position,
         }
     }
 
@@ -75,6 +103,12 @@ impl Exception {
         self.stack.clone()
     }
 
+    /// Produces the source position at which the exception was thrown, if
+    /// known.
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
     pub fn print_stack_trace(&self) {}
 }
 
@@ -84,6 +118,8 @@ impl fmt::Display for Exception {
     }
 }
 
+impl std::error::Error for Exception {}
+
 /// The `Exception` type represents all possible exceptions in the rlisp
 /// language.
 #[derive(Clone, Debug)]
@@ -139,4 +175,24 @@ impl ExceptionData {
             Syntax(code, ..) => *code,
         }
     }
+
+    /// Produces the position of the undefined symbol within the specified
+    /// list, if this is an `Undefined` exception and the list directly holds
+    /// the symbol at a recorded position. Should the symbol occur more than
+    /// once, the position of its first occurrence is produced.
+    fn undefined_position(
+        &self,
+        list: &ConsList<Expression>,
+    ) -> Option<Position> {
+        let name = match self {
+            Undefined(name) => name,
+            _ => return None,
+        };
+        list.iter()
+            .filter(|elem| match elem.as_ref() {
+                Expression::Symbol(sym) => **sym.name() == **name,
+                _ => false,
+            })
+            .find_map(|elem| symbol_position_of(&elem))
+    }
 }
@@ -2,14 +2,28 @@
 //! relating to expressions within the rlisp language. The function
 //! `Expression::eval` is the heart of the interpreter.
 
-use crate::{context::Context, exception::Exception, quat::Quat, util::Str};
+use crate::{
+    context::Context, exception::Exception, foreign::Foreign, intern::Sym,
+    quat::Quat, util::Str,
+};
 use im::ConsList;
-use std::{fmt, rc::Rc};
+use std::{
+    cell::RefCell,
+    convert::TryFrom,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    rc::Rc,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// The expressions captured by a closure.
-pub type Capture = HashMap<Str, Expression>;
+pub type Capture = HashMap<Sym, Expression>;
 
 /// The data stored by an instance of a custom struct type.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct StructData {
     /// The name of the struct type.
     pub name: Str,
@@ -19,14 +33,28 @@ pub struct StructData {
 }
 
 #[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LambdaData {
-    pub params: ConsList<Str>,
+    #[cfg_attr(feature = "serde", serde(with = "cons_list"))]
+    pub params: ConsList<Sym>,
     pub body: Rc<Expression>,
     pub capture: Option<Rc<Capture>>,
 }
 
-/// Any value that may be called as a function.
+/// The state of a promise created by `delay`.
+pub enum PromiseState {
+    /// The promise has not been forced. It holds a callable that produces its
+    /// value when called with no arguments.
+    Delayed(Expression),
+
+    /// The promise has been forced, producing the held value.
+    Forced(Expression),
+}
+
+/// Any value that may be called as a function. Intrinsic functions and macros
+/// cannot be serialized.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Callable {
     /// A quote, i.e. `(quote hello)`. When a quote expression is evaluated,
     /// the inner expression is returned, unevaluated.
@@ -51,14 +79,17 @@ pub enum Callable {
 
     /// An intrinsic function, taking a slice of expressions and
     /// returning another expression.
-    Intrinsic(Rc<Fn(&[Expression], &mut Context) -> Expression>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Intrinsic(Rc<dyn Fn(&[Expression], &mut Context) -> Expression>),
 
     /// A macro that transforms the expression into a new expression.
-    Macro(Rc<Fn(ConsList<Expression>, &mut Context) -> Expression>),
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Macro(Rc<dyn Fn(ConsList<Expression>, &mut Context) -> Expression>),
 }
 
-/// An expression in the rlisp language.
+/// An expression in the rlisp language. Exceptions cannot be serialized.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Expression {
     /// A boolean expression.
     Bool(bool),
@@ -74,19 +105,38 @@ pub enum Expression {
 
     /// A symbol expression. When a symbol is evaluated, a lookup for its value
     /// is performed in the given evaluation context.
-    Symbol(Str),
+    Symbol(Sym),
 
     /// A singly-linked list of expressions.
+    #[cfg_attr(feature = "serde", serde(with = "cons_list"))]
     Cons(ConsList<Expression>),
 
+    /// A fixed-size vector of expressions. Vectors evaluate to themselves, so
+    /// their elements are not evaluated.
+    Vector(Rc<Vec<Expression>>),
+
     /// A callable expression.
     Callable(Callable),
 
     /// An exception.
+    #[cfg_attr(feature = "serde", serde(skip))]
     Error(Rc<Exception>),
 
     /// A custom struct.
     Struct(Rc<StructData>),
+
+    /// A mutable cell holding a single value.
+    Boxed(Rc<RefCell<Expression>>),
+
+    /// A promise to evaluate an expression when it is first forced, caching
+    /// its value thereafter.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Promise(Rc<RefCell<PromiseState>>),
+
+    /// A value implemented in Rust, such as a mutable resource. Foreign values
+    /// evaluate to themselves and cannot be serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Foreign(Rc<dyn Foreign>),
 }
 
 use self::Callable::*;
@@ -102,10 +152,14 @@ impl Expression {
             Bool(..) => "bool".into(),
             Str(..) => "string".into(),
             Cons(..) => "cons".into(),
+            Vector(..) => "vector".into(),
             Error(..) => "error".into(),
             Symbol(..) => "symbol".into(),
             Callable(..) => "procedure".into(),
             Struct(data) => data.name.clone(),
+            Boxed(..) => "box".into(),
+            Promise(..) => "promise".into(),
+            Foreign(value) => value.type_name(),
         }
     }
 
@@ -133,12 +187,36 @@ impl Expression {
         }
     }
 
+    /// Produces the number of strong references to the shared allocation
+    /// underlying the expression. Values without a shared allocation produce
+    /// 1, as do lists: cloning a list shares its first node, but `ConsList`
+    /// does not expose the reference count of its nodes, and the count of its
+    /// first element is not raised by cloning the list.
+    pub fn rc_count(&self) -> usize {
+        match self {
+            Cons(_) => 1,
+            Quaternion(q) => Rc::strong_count(q),
+            Str(s) => Rc::strong_count(s),
+            Symbol(s) => Rc::strong_count(s.name()),
+            Vector(xs) => Rc::strong_count(xs),
+            Callable(Lambda(data)) => Rc::strong_count(data),
+            Callable(Intrinsic(f)) => Rc::strong_count(f),
+            Callable(Macro(f)) => Rc::strong_count(f),
+            Error(ex) => Rc::strong_count(ex),
+            Struct(data) => Rc::strong_count(data),
+            Boxed(value) => Rc::strong_count(value),
+            Promise(promise) => Rc::strong_count(promise),
+            Foreign(value) => Rc::strong_count(value),
+            _ => 1,
+        }
+    }
+
     /// Extracts the values of all symbols in the specified context into the
     /// specified capture.
     fn extract_symbols_to_capture(&self, capture: &mut Capture, ctx: &Context) {
         match self {
             Symbol(ident) => {
-                if let Some(value) = ctx.get(ident) {
+                if let Some(value) = ctx.get_symbol(ident) {
                     capture.insert(ident.clone(), value.clone());
                 }
             }
@@ -215,44 +293,14 @@ impl Expression {
                 ))),
 
                 Macro(f) => f(list.clone(), ctx),
-                Intrinsic(f) => {
-                    let args: Result<Vec<_>, _> = list
-                        .tail()
-                        .unwrap_or_else(|| ConsList::new())
-                        .iter()
-                        .map(|expr| match expr.eval(ctx) {
-                            Error(e) => Err(e),
-                            expr => Ok(expr),
-                        })
-                        .collect();
-                    args.map(|args| f(&args, ctx)).unwrap_or_else(|e| Error(e))
-                }
-                Lambda(data) => {
-                    let LambdaData {
-                        params,
-                        body,
-                        capture,
-                    } = data.as_ref();
-                    let args: Result<ConsList<_>, _> = list
-                        .tail()
-                        .unwrap_or_default()
-                        .iter()
-                        .map(|expr| match expr.eval(ctx) {
-                            Error(e) => Err(e),
-                            expr => Ok(expr),
-                        })
-                        .collect();
-                    args.map(|args| {
-                        eval_lambda(
-                            params.clone(),
-                            &body,
-                            args,
-                            ctx,
-                            capture.as_ref().map(|cap| cap.as_ref()),
-                        )
-                    })
-                    .unwrap_or_else(|e| Error(e))
-                }
+                Intrinsic(f) => match eval_args(list, ctx) {
+                    Ok(args) => f(&args, ctx),
+                    Err(e) => Error(e),
+                },
+                Lambda(data) => match eval_args(list, ctx) {
+                    Ok(args) => eval_lambda(data, args, ctx),
+                    Err(e) => Error(e),
+                },
             },
             _ => Error(Rc::new(Exception::custom(
                 3,
@@ -266,8 +314,8 @@ impl Expression {
         match self {
             // Look up variable
             Symbol(ident) => {
-                ctx.get(ident).map(|expr| expr.clone()).unwrap_or_else(|| {
-                    Error(Rc::new(Exception::undefined(ident.clone())))
+                ctx.get_symbol(ident).cloned().unwrap_or_else(|| {
+                    Error(Rc::new(Exception::undefined(ident.name().clone())))
                 })
             }
 
@@ -294,16 +342,36 @@ impl Expression {
     }
 }
 
+/// Evaluates the arguments of the specified function call, stopping at the
+/// first exception.
+fn eval_args(
+    list: &ConsList<Expression>,
+    ctx: &mut Context,
+) -> Result<Vec<Expression>, Rc<Exception>> {
+    let mut args = Vec::with_capacity(list.len().saturating_sub(1));
+    for expr in list.iter().skip(1) {
+        match expr.eval(ctx) {
+            Error(e) => return Err(e),
+            expr => args.push(expr),
+        }
+    }
+    Ok(args)
+}
+
 /// Evaluates the specified `Lambda`. A new scope is created and the parameter
 /// names are bound to the supplied arguments, after which the body is
 /// evaluated in this new context.
 fn eval_lambda(
-    params: ConsList<Str>,
-    body: &Expression,
-    args: ConsList<Expression>,
+    data: &LambdaData,
+    args: Vec<Expression>,
     ctx: &mut Context,
-    capture: Option<&Capture>,
 ) -> Expression {
+    let LambdaData {
+        params,
+        body,
+        capture,
+    } = data;
+
     // Check arity
     match (params.len(), args.len()) {
         (expected, found) if expected == found => {
@@ -312,13 +380,13 @@ fn eval_lambda(
             // Apply values from capture
             if let Some(capture) = capture {
                 for (key, value) in capture.iter() {
-                    ctx.insert(key, value.clone());
+                    ctx.insert_symbol(key.clone(), value.clone());
                 }
             }
 
             // Apply arguments to parameters
-            for (param, arg) in params.iter().zip(args.iter()) {
-                ctx.insert(param.to_string(), (*arg).clone());
+            for (param, arg) in params.iter().zip(args) {
+                ctx.insert_symbol((*param).clone(), arg);
             }
             let res = body.eval(ctx);
             ctx.descend_scope();
@@ -328,10 +396,55 @@ fn eval_lambda(
     }
 }
 
+thread_local! {
+    /// The pairs of boxes whose contents are being displayed, compared, or
+    /// hashed, such that a box that contains itself is not visited forever.
+    /// Boxes that are not being compared are paired with themselves.
+    static VISITING: RefCell<Vec<(usize, usize)>> =
+        const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a pair of boxes as being visited until it is dropped.
+struct Visit;
+
+impl Drop for Visit {
+    fn drop(&mut self) {
+        VISITING.with(|visiting| visiting.borrow_mut().pop());
+    }
+}
+
+/// Calls the specified function while the specified pair of boxes is marked
+/// as being visited, producing its result, or `None` if the pair is already
+/// being visited.
+fn visit_boxes<T>(
+    a: &Rc<RefCell<Expression>>,
+    b: &Rc<RefCell<Expression>>,
+    f: impl FnOnce() -> T,
+) -> Option<T> {
+    let key = (Rc::as_ptr(a) as usize, Rc::as_ptr(b) as usize);
+    let visiting = VISITING.with(|visiting| {
+        let mut visiting = visiting.borrow_mut();
+        let found = visiting.contains(&key);
+        if !found {
+            visiting.push(key);
+        }
+        found
+    });
+    if visiting {
+        return None;
+    }
+    let _visit = Visit;
+    Some(f())
+}
+
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Bool(b) => write!(f, "{}", b),
+            // Integral numbers are already displayed without a fractional
+            // part, such as `3`. Other numbers are displayed exactly rather
+            // than rounded, so that they parse back to the same value, and
+            // `round-to` may be used to hide floating point noise.
             Num(n) => write!(f, "{}", n),
             Quaternion(n) => write!(f, "{}", n),
             Str(s) => write!(f, "{}", s),
@@ -363,6 +476,11 @@ impl fmt::Display for Expression {
                 let inner = strs.join(" ");
                 write!(f, "({})", inner)
             }
+            Vector(items) => {
+                let strs: Vec<_> =
+                    items.iter().map(|expr| expr.to_string()).collect();
+                write!(f, "#({})", strs.join(" "))
+            }
             Callable(callable) => match callable {
                 Quote => write!(f, "quote"),
                 Quasiquote => write!(f, "quasiquote"),
@@ -379,6 +497,12 @@ impl fmt::Display for Expression {
                 }
                 write!(f, ")")
             }
+            Boxed(value) => visit_boxes(value, value, || {
+                write!(f, "(box {})", value.borrow())
+            })
+            .unwrap_or_else(|| write!(f, "(box ...)")),
+            Promise(_) => write!(f, "<promise>"),
+            Foreign(value) => write!(f, "<{}>", value.type_name()),
         }
     }
 }
@@ -396,6 +520,11 @@ impl fmt::Debug for Expression {
                 let inner = strs.join(", ");
                 write!(f, "<Cons:[{}]>", inner)
             }
+            Vector(items) => write!(f, "<Vector:{:?}>", items),
+            Boxed(value) => visit_boxes(value, value, || {
+                write!(f, "<Box:{:?}>", value.borrow())
+            })
+            .unwrap_or_else(|| write!(f, "<Box:...>")),
             Struct(data) => {
                 let StructData { name, data } = data.as_ref();
                 write!(f, "<{}:{:?}>", name, data)?;
@@ -406,6 +535,10 @@ impl fmt::Debug for Expression {
     }
 }
 
+/// Expressions are compared structurally, such that lists, vectors, structs,
+/// and boxes are equal when their contents are equal at any depth. Numbers are
+/// equal to the equivalent quaternions, while callables other than lambdas,
+/// promises, and foreign values are only equal to themselves.
 impl PartialEq for Expression {
     fn eq(&self, other: &Expression) -> bool {
         match (self, other) {
@@ -417,10 +550,36 @@ impl PartialEq for Expression {
             (Bool(a), Bool(b)) => a == b,
             (Symbol(a), Symbol(b)) => a == b,
             (Callable(a), Callable(b)) => match (a, b) {
+                (Quote, Quote)
+                | (Quasiquote, Quasiquote)
+                | (Unquote, Unquote) => true,
                 (Lambda(l1), Lambda(l2)) => l1 == l2,
+
+                // Intrinsics and macros are only equal to themselves
+                (Intrinsic(f1), Intrinsic(f2)) => {
+                    Rc::as_ptr(f1) as *const u8 == Rc::as_ptr(f2) as *const u8
+                }
+                (Macro(f1), Macro(f2)) => {
+                    Rc::as_ptr(f1) as *const u8 == Rc::as_ptr(f2) as *const u8
+                }
                 _ => false,
             },
             (Cons(a), Cons(b)) => a == b,
+            (Vector(a), Vector(b)) => a == b,
+            // Boxes that are already being compared are assumed to be equal,
+            // such that boxes which contain themselves may be compared
+            (Boxed(a), Boxed(b)) => {
+                Rc::ptr_eq(a, b)
+                    || visit_boxes(a, b, || *a.borrow() == *b.borrow())
+                        .unwrap_or(true)
+            }
+            (Promise(a), Promise(b)) => Rc::ptr_eq(a, b),
+
+            // Foreign values are only equal to themselves
+            (Foreign(a), Foreign(b)) => std::ptr::eq(
+                Rc::as_ptr(a) as *const u8,
+                Rc::as_ptr(b) as *const u8,
+            ),
             (Struct(d1), Struct(d2)) => {
                 let StructData {
                     name: name1,
@@ -438,6 +597,84 @@ impl PartialEq for Expression {
     }
 }
 
+/// Expressions may be used as keys, such as of a `HashMap`. However, `NaN` is
+/// not equal to itself, nor is an exception, so neither will ever be found as
+/// a key. Such keys should not be inserted, as each insertion adds another
+/// entry that can never be found.
+impl Eq for Expression {}
+
+impl Hash for Expression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        /// Hashes a quaternion, such that `0` and `-0`, which are equal, are
+        /// hashed equally.
+        fn hash_quat<H: Hasher>(&Quat(a, b, c, d): &Quat, state: &mut H) {
+            for n in [a, b, c, d].iter() {
+                let n = if *n == 0.0 { 0.0 } else { *n };
+                n.to_bits().hash(state);
+            }
+        }
+
+        /// Hashes the address of the specified value.
+        fn hash_ptr<T: ?Sized, H: Hasher>(value: &Rc<T>, state: &mut H) {
+            (Rc::as_ptr(value) as *const u8 as usize).hash(state);
+        }
+
+        match self {
+            // Numbers are equal to the equivalent quaternions, and so both
+            // are hashed as quaternions under the same discriminant
+            Num(n) => {
+                mem::discriminant(&Num(0.0)).hash(state);
+                hash_quat(&Quat::from(*n), state);
+                return;
+            }
+            Quaternion(q) => {
+                mem::discriminant(&Num(0.0)).hash(state);
+                hash_quat(q, state);
+                return;
+            }
+            _ => mem::discriminant(self).hash(state),
+        }
+        match self {
+            Bool(b) => b.hash(state),
+            Str(s) => s.hash(state),
+            Symbol(sym) => sym.hash(state),
+            Cons(list) => {
+                list.len().hash(state);
+                for x in list.iter() {
+                    x.hash(state);
+                }
+            }
+            Vector(xs) => xs.hash(state),
+            Callable(f) => {
+                mem::discriminant(f).hash(state);
+                match f {
+                    Lambda(data) => {
+                        // Captures are not hashed, as they are unordered
+                        for param in data.params.iter() {
+                            param.hash(state);
+                        }
+                        data.body.hash(state);
+                    }
+                    Intrinsic(f) => hash_ptr(f, state),
+                    Macro(f) => hash_ptr(f, state),
+                    Quote | Quasiquote | Unquote => (),
+                }
+            }
+            Struct(data) => {
+                data.name.hash(state);
+                data.data.hash(state);
+            }
+            Boxed(value) => {
+                // A box within itself is hashed as though it were empty
+                visit_boxes(value, value, || value.borrow().hash(state));
+            }
+            Promise(promise) => hash_ptr(promise, state),
+            Foreign(value) => hash_ptr(value, state),
+            Num(_) | Quaternion(_) | Error(_) => (),
+        }
+    }
+}
+
 impl Default for Expression {
     fn default() -> Self {
         crate::util::nil()
@@ -460,6 +697,12 @@ impl ValidIdentifier for Str {
     }
 }
 
+impl ValidIdentifier for Sym {
+    fn is_valid_identifier(&self) -> bool {
+        self.name().is_valid_identifier()
+    }
+}
+
 impl ValidIdentifier for Expression {
     fn is_valid_identifier(&self) -> bool {
         match self {
@@ -518,3 +761,234 @@ impl Into<Result<Expression, Exception>> for Expression {
         }
     }
 }
+
+/// Serializes `ConsList`s as sequences of their elements.
+#[cfg(feature = "serde")]
+mod cons_list {
+    use im::ConsList;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T, S>(
+        list: &ConsList<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(list.iter())
+    }
+
+    pub fn deserialize<'de, T, D>(
+        deserializer: D,
+    ) -> Result<ConsList<T>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Vec::<T>::deserialize(deserializer).map(ConsList::from)
+    }
+}
+
+/// Implements `TryFrom<Expression>` for the specified types, extracting the
+/// value from the specified variant. Any other expression produces a signature
+/// exception, except for errors, which produce their own exception.
+macro_rules! impl_try_from_expr {
+    ($($type:ty => $name:expr, $pat:pat => $value:expr;)*) => {
+        $(
+            impl TryFrom<Expression> for $type {
+                type Error = Exception;
+
+                fn try_from(expr: Expression) -> Result<$type, Exception> {
+                    match expr {
+                        $pat => Ok($value),
+                        Error(ex) => Err(ex.as_ref().clone()),
+                        other => {
+                            Err(Exception::signature($name, other.type_of()))
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_expr! {
+    f64 => "num", Num(n) => n;
+    bool => "bool", Bool(b) => b;
+    String => "string", Str(s) => s.to_string();
+    Vec<Expression> => "list", Cons(list) => {
+        list.iter().map(|expr| expr.as_ref().clone()).collect()
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_from() {
+        assert_eq!(f64::try_from(Num(1.5)).ok(), Some(1.5));
+        assert_eq!(bool::try_from(Bool(true)).ok(), Some(true));
+        assert_eq!(
+            String::try_from(Str("abc".into())).ok(),
+            Some("abc".to_string())
+        );
+        let list = ConsList::from(vec![Num(1.0), Num(2.0)]);
+        assert_eq!(
+            Vec::<Expression>::try_from(Cons(list)).ok(),
+            Some(vec![Num(1.0), Num(2.0)])
+        );
+    }
+
+    #[test]
+    fn test_deep_eq() {
+        let quat = |a, b, c, d| Quaternion(Rc::new(Quat(a, b, c, d)));
+        let point = |x, y| {
+            Struct(Rc::new(StructData {
+                name: "point".into(),
+                data: vec![x, y],
+            }))
+        };
+        let nested = |x| {
+            Cons(ConsList::from(vec![
+                point(x, Vector(Rc::new(vec![quat(1.0, 2.0, 3.0, 4.0)]))),
+                Boxed(Rc::new(RefCell::new(Cons(ConsList::from(vec![quat(
+                    0.0, 1.0, 0.0, 0.0,
+                )]))))),
+            ]))
+        };
+
+        assert_eq!(nested(Num(2.0)), nested(quat(2.0, 0.0, 0.0, 0.0)));
+        assert_ne!(nested(Num(2.0)), nested(quat(2.0, 0.0, 0.0, 1.0)));
+        assert_ne!(
+            point(Num(1.0), Num(2.0)),
+            Struct(Rc::new(StructData {
+                name: "other".into(),
+                data: vec![Num(1.0), Num(2.0)],
+            }))
+        );
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(expr: &Expression) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            expr.hash(&mut hasher);
+            hasher.finish()
+        }
+        fn assert_hash_eq(a: Expression, b: Expression) {
+            assert_eq!(a, b);
+            assert_eq!(hash(&a), hash(&b), "{} and {}", a, b);
+        }
+        fn assert_hash_ne(a: Expression, b: Expression) {
+            assert_ne!(a, b);
+            assert_ne!(hash(&a), hash(&b), "{} and {}", a, b);
+        }
+        let list = |xs: Vec<Expression>| Cons(ConsList::from(xs));
+
+        assert_hash_eq(Num(1.5), Num(1.5));
+        assert_hash_eq(Num(0.0), Num(-0.0));
+        assert_hash_eq(Num(2.0), Quaternion(Rc::new(Quat::from(2.0))));
+        assert_hash_eq(Str("a".into()), Str("a".into()));
+        assert_hash_eq(Symbol("a".into()), Symbol("a".into()));
+        assert_hash_eq(
+            list(vec![Num(1.0), list(vec![Bool(true)])]),
+            list(vec![Num(1.0), list(vec![Bool(true)])]),
+        );
+        let f = Callable(Intrinsic(Rc::new(|_, _| Expression::default())));
+        assert_hash_eq(f.clone(), f.clone());
+
+        assert_hash_ne(Num(1.0), Num(2.0));
+        assert_hash_ne(Str("a".into()), Symbol("a".into()));
+        assert_hash_ne(list(vec![Num(1.0), Num(2.0)]), list(vec![Num(2.0)]));
+        assert_hash_ne(
+            f,
+            Callable(Intrinsic(Rc::new(|_, _| Expression::default()))),
+        );
+
+        let mut counts = HashMap::new();
+        *counts.entry(list(vec![Num(1.0)])).or_insert(0) += 1;
+        *counts.entry(list(vec![Num(1.0)])).or_insert(0) += 1;
+        assert_eq!(counts.get(&list(vec![Num(1.0)])), Some(&2));
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)]
+    fn test_cyclic_box() {
+        let cyclic = || {
+            let value = Rc::new(RefCell::new(Expression::default()));
+            *value.borrow_mut() = Boxed(value.clone());
+            Boxed(value)
+        };
+        let (a, b) = (cyclic(), cyclic());
+        assert_eq!(a.to_string(), "(box (box ...))");
+        assert_eq!(a, a.clone());
+        assert_eq!(a, b);
+        assert_ne!(a, Boxed(Rc::new(RefCell::new(Num(1.0)))));
+        let mut set = std::collections::HashSet::new();
+        set.insert(a);
+        assert!(set.contains(&b));
+    }
+
+    #[test]
+    fn test_try_from_mismatch() {
+        let ex = f64::try_from(Str("abc".into())).unwrap_err();
+        assert_eq!(ex.error_code(), 9);
+        assert_eq!(
+            ex.to_string(),
+            Exception::signature("num", "string").to_string()
+        );
+        assert_eq!(bool::try_from(Num(1.0)).unwrap_err().error_code(), 9);
+        assert_eq!(String::try_from(Bool(false)).unwrap_err().error_code(), 9);
+        assert_eq!(
+            Vec::<Expression>::try_from(Num(1.0))
+                .unwrap_err()
+                .error_code(),
+            9
+        );
+
+        // Errors produce their own exception
+        let ex = Exception::custom(42, "failure");
+        assert_eq!(
+            f64::try_from(Error(Rc::new(ex))).unwrap_err().error_code(),
+            42
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    fn round_trip(expr: &Expression) -> Expression {
+        let json = serde_json::to_string(expr).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_round_trip() {
+        assert_eq!(round_trip(&Num(1.5)), Num(1.5));
+        assert_eq!(round_trip(&Str("abc".into())), Str("abc".into()));
+        let list = Cons(ConsList::from(vec![
+            Num(1.0),
+            Symbol("a".into()),
+            Cons(ConsList::from(vec![Bool(true)])),
+        ]));
+        assert_eq!(round_trip(&list), list);
+        let point = Struct(Rc::new(StructData {
+            name: "point".into(),
+            data: vec![Num(1.0), Num(2.0)],
+        }));
+        assert_eq!(round_trip(&point), point);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_serde_unserializable() {
+        let f = Callable(Intrinsic(Rc::new(|_, _| Expression::default())));
+        assert!(serde_json::to_string(&f).is_err());
+        let ex = Error(Rc::new(Exception::custom(42, "failure")));
+        assert!(serde_json::to_string(&ex).is_err());
+    }
+}
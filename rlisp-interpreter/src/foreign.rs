@@ -0,0 +1,37 @@
+//! This module provides foreign values: values implemented in Rust that rlisp
+//! programs may only manipulate through intrinsic functions, such as mutable
+//! resources.
+
+use crate::util::Str;
+use std::{any::Any, rc::Rc};
+
+/// A value implemented in Rust and stored in an expression.
+pub trait Foreign: Any {
+    /// Produces the name of the type of the value, as reported by `type-of`.
+    fn type_name(&self) -> Str;
+
+    /// Produces an independent copy of the value. Mutating the copy must not
+    /// affect the original. Immutable values may produce themselves.
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign>;
+
+    /// Produces the value as `Any`, so that it may be downcast to its
+    /// concrete type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Produces a handle that may be sent to another thread, where it produces
+    /// an equivalent value, or `None` if the value cannot leave its thread.
+    fn share(&self) -> Option<Shared> {
+        None
+    }
+}
+
+/// A foreign value on its way to another thread, which produces the value
+/// once called there.
+pub type Shared = Box<dyn FnOnce() -> Rc<dyn Foreign> + Send>;
+
+impl dyn Foreign {
+    /// Attempts to downcast the value to the specified concrete type.
+    pub fn downcast_ref<T: Foreign>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+}
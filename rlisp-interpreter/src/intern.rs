@@ -0,0 +1,209 @@
+//! This module provides the symbol intern table. Interning a string produces
+//! a `Sym`, which shares a single copy of the string with every other
+//! occurrence of the same symbol, and carries a numeric ID so that symbols
+//! may be compared and hashed without inspecting their names.
+
+use crate::util::Str;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    borrow::Borrow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+thread_local! {
+    static TABLE: RefCell<HashMap<Str, u32>> = RefCell::new(HashMap::new());
+}
+
+/// An interned symbol. Two symbols are equal exactly when their names are
+/// equal, which is determined by comparing their IDs.
+#[derive(Clone)]
+pub struct Sym {
+    id: u32,
+    name: Str,
+}
+
+impl Sym {
+    /// Produces the ID of the symbol, which is unique to its name within the
+    /// current thread.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Produces the name of the symbol.
+    pub fn name(&self) -> &Str {
+        &self.name
+    }
+}
+
+impl PartialEq for Sym {
+    fn eq(&self, other: &Sym) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Sym {}
+
+impl Hash for Sym {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Borrow<u32> for Sym {
+    fn borrow(&self) -> &u32 {
+        &self.id
+    }
+}
+
+impl Deref for Sym {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl AsRef<str> for Sym {
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl fmt::Debug for Sym {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.name)
+    }
+}
+
+impl From<&str> for Sym {
+    fn from(s: &str) -> Sym {
+        intern(s)
+    }
+}
+
+impl From<String> for Sym {
+    fn from(s: String) -> Sym {
+        intern(&s)
+    }
+}
+
+impl From<Str> for Sym {
+    fn from(s: Str) -> Sym {
+        intern(&s)
+    }
+}
+
+impl From<Sym> for Str {
+    fn from(sym: Sym) -> Str {
+        sym.name
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Sym {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Sym {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Sym, D::Error> {
+        String::deserialize(deserializer).map(Sym::from)
+    }
+}
+
+/// Statistics describing the contents of the intern table.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InternStats {
+    /// The number of distinct strings in the table.
+    pub symbols: usize,
+
+    /// The total length in bytes of the strings in the table.
+    pub bytes: usize,
+
+    /// The number of live references to strings in the table, excluding those
+    /// held by the table itself.
+    pub references: usize,
+}
+
+/// Produces the symbol with the specified name, adding the name to the intern
+/// table if it is not already present.
+pub fn intern(s: &str) -> Sym {
+    TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        match table.get_key_value(s) {
+            Some((name, &id)) => Sym {
+                id,
+                name: name.clone(),
+            },
+            None => {
+                let id = table.len() as u32;
+                let name: Str = s.into();
+                table.insert(name.clone(), id);
+                Sym { id, name }
+            }
+        }
+    })
+}
+
+/// Produces the ID of the symbol with the specified name, if it has been
+/// interned, without adding it to the intern table.
+pub fn lookup(s: &str) -> Option<u32> {
+    TABLE.with(|table| table.borrow().get(s).copied())
+}
+
+/// Produces statistics describing the current contents of the intern table.
+pub fn stats() -> InternStats {
+    TABLE.with(|table| {
+        table
+            .borrow()
+            .keys()
+            .fold(InternStats::default(), |stats, s| InternStats {
+                symbols: stats.symbols + 1,
+                bytes: stats.bytes + s.len(),
+                references: stats.references + Rc::strong_count(s) - 1,
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = intern("interned-symbol");
+        let b = intern("interned-symbol");
+        assert!(Rc::ptr_eq(a.name(), b.name()));
+        assert_eq!(a.id(), b.id());
+        assert_eq!(a, b);
+        assert_eq!(lookup("interned-symbol"), Some(a.id()));
+        assert_eq!(lookup("never-interned-symbol"), None);
+
+        let before = stats();
+        let c = intern("another-interned-symbol");
+        let after = stats();
+        assert_ne!(a, c);
+        assert_eq!(after.symbols, before.symbols + 1);
+        assert_eq!(after.bytes, before.bytes + c.len());
+        assert_eq!(after.references, before.references + 1);
+    }
+}
@@ -1,9 +1,9 @@
-#[macro_use]
-extern crate lazy_static;
-
 pub mod context;
 pub mod exception;
 pub mod expression;
+pub mod foreign;
+pub mod intern;
+pub mod location;
 pub mod pattern;
 pub mod quat;
 pub mod util;
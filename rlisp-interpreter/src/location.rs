@@ -0,0 +1,152 @@
+//! This module records where in the source each parsed list and symbol began,
+//! such that exceptions thrown while evaluating them may report their
+//! location. Rather than storing positions within expressions themselves,
+//! they are recorded in a side table. Lists are keyed by their first element,
+//! which is shared by every clone of the list, and symbols by the element of
+//! the list that holds them.
+//!
+//! Because a list is keyed by the address of its first element, any list
+//! built upon that same element, such as by `ConsList::cons` with the `Arc`
+//! of another list's head, is attributed the other list's position.
+
+use crate::expression::Expression;
+use im::ConsList;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Weak},
+};
+
+/// A position within the source being parsed, given as a 1-based line and
+/// column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position { line: 1, col: 1 }
+    }
+}
+
+/// Recorded positions, keyed by the address of an element. Each entry holds a
+/// weak reference to its element, which both prevents the address from being
+/// reused by another element and reveals when the element has been dropped.
+type Positions = HashMap<usize, (Weak<Expression>, Position)>;
+
+/// The table of recorded lists and symbols.
+struct Table {
+    /// The positions of lists, keyed by their first element.
+    lists: Positions,
+
+    /// The positions of symbols, keyed by the element holding them.
+    symbols: Positions,
+
+    /// The number of entries above which those of dropped elements are
+    /// removed.
+    prune_at: usize,
+}
+
+impl Table {
+    /// Removes the entries of dropped elements once there are enough of them.
+    fn prune(&mut self) {
+        if self.lists.len() + self.symbols.len() >= self.prune_at {
+            self.lists.retain(|_, (elem, _)| elem.strong_count() > 0);
+            self.symbols.retain(|_, (elem, _)| elem.strong_count() > 0);
+            self.prune_at =
+                ((self.lists.len() + self.symbols.len()) * 2).max(1024);
+        }
+    }
+}
+
+/// Produces the entry recording the specified element at the specified
+/// position.
+fn entry(
+    elem: &Arc<Expression>,
+    pos: Position,
+) -> (usize, (Weak<Expression>, Position)) {
+    (Arc::as_ptr(elem) as usize, (Arc::downgrade(elem), pos))
+}
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table {
+        lists: HashMap::new(),
+        symbols: HashMap::new(),
+        prune_at: 1024,
+    });
+}
+
+/// Records that the specified list begins at the specified position. Empty
+/// lists cannot be recorded.
+pub fn set_position(list: &ConsList<Expression>, pos: Position) {
+    if let Some(head) = list.head() {
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            table.prune();
+            let (key, value) = entry(&head, pos);
+            table.lists.insert(key, value);
+        });
+    }
+}
+
+/// Produces the position at which the specified list began, if it was
+/// recorded by `set_position`.
+pub fn position_of(list: &ConsList<Expression>) -> Option<Position> {
+    let key = Arc::as_ptr(&list.head()?) as usize;
+    TABLE.with(|table| table.borrow().lists.get(&key).map(|(_, pos)| *pos))
+}
+
+/// Records that the symbol held by the specified element of a list begins at
+/// the specified position.
+pub fn set_symbol_position(elem: &Arc<Expression>, pos: Position) {
+    TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        table.prune();
+        let (key, value) = entry(elem, pos);
+        table.symbols.insert(key, value);
+    });
+}
+
+/// Produces the position at which the symbol held by the specified element of
+/// a list began, if it was recorded by `set_symbol_position`.
+pub fn symbol_position_of(elem: &Arc<Expression>) -> Option<Position> {
+    let key = Arc::as_ptr(elem) as usize;
+    TABLE.with(|table| table.borrow().symbols.get(&key).map(|(_, pos)| *pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::Expression::*;
+
+    #[test]
+    fn test_position_of() {
+        let list = ConsList::from(vec![Num(1.0), Num(2.0)]);
+        let pos = Position { line: 3, col: 5 };
+        set_position(&list, pos);
+
+        // Clones share the same position, while other lists have none
+        assert_eq!(position_of(&list.clone()), Some(pos));
+        assert_eq!(position_of(&ConsList::from(vec![Num(1.0)])), None);
+        assert_eq!(position_of(&list.tail().unwrap()), None);
+        assert_eq!(position_of(&ConsList::new()), None);
+    }
+
+    #[test]
+    fn test_symbol_position_of() {
+        let list = ConsList::from(vec![Num(1.0), Num(2.0)]);
+        let head = list.head().unwrap();
+        let pos = Position { line: 2, col: 4 };
+        set_symbol_position(&head, pos);
+
+        // Symbols and lists are recorded separately, even under one element
+        assert_eq!(symbol_position_of(&head), Some(pos));
+        assert_eq!(position_of(&list), None);
+        assert_eq!(
+            symbol_position_of(&list.tail().unwrap().head().unwrap()),
+            None
+        );
+    }
+}
@@ -1,14 +1,15 @@
 use crate::{
     exception::Exception,
     expression::Expression::{self, *},
-    util::Str,
+    im::ConsList,
+    intern::Sym,
 };
 use std::collections::HashMap;
 
-type Matches = HashMap<Str, Expression>;
+type Matches = HashMap<Sym, Expression>;
 
 pub fn pattern_match(
-    syntax: &[Str],
+    syntax: &[Sym],
     pattern: &Expression,
     input: &Expression,
 ) -> Result<Matches, Exception> {
@@ -32,13 +33,13 @@ pub fn replace_symbols(expr: &Expression, matches: &Matches) -> Expression {
     }
 }
 
-pub fn extract_symbols(syntax: &[Str], expr: &Expression) -> Vec<Str> {
+pub fn extract_symbols(syntax: &[Sym], expr: &Expression) -> Vec<Sym> {
     let mut buf = Vec::new();
     extract_symbols_to(syntax, expr, &mut buf);
     buf
 }
 
-fn extract_symbols_to(syntax: &[Str], expr: &Expression, to: &mut Vec<Str>) {
+fn extract_symbols_to(syntax: &[Sym], expr: &Expression, to: &mut Vec<Sym>) {
     match expr {
         Symbol(s) if !syntax.contains(s) => to.push(s.clone()),
         Cons(xs) => {
@@ -50,21 +51,53 @@ fn extract_symbols_to(syntax: &[Str], expr: &Expression, to: &mut Vec<Str>) {
     }
 }
 
+/// Determines whether or not the specified list pattern begins with the
+/// constructor of the struct with the specified name.
+fn is_constructor_of(pattern: &ConsList<Expression>, name: &str) -> bool {
+    match pattern.head().as_ref().map(|head| head.as_ref()) {
+        Some(Symbol(s)) => s.starts_with("make-") && &s[5..] == name,
+        _ => false,
+    }
+}
+
 fn extract_matches(
-    syntax: &[Str],
+    syntax: &[Sym],
     pattern: &Expression,
     input: &Expression,
     to: &mut Matches,
 ) -> Result<(), Exception> {
     match (pattern, input) {
-        // Check if it's a syntax symbol
+        // Check if it's a syntax symbol, which must match itself rather than
+        // being bound
         (Symbol(s1), Symbol(s2)) if syntax.contains(s1) && s1 == s2 => {}
+        (Symbol(s), found) if syntax.contains(s) => {
+            return Err(Exception::custom(
+                42,
+                format!(
+                    "pattern match failure: expected `{}`, found `{}`",
+                    s, found
+                ),
+            ));
+        }
+
+        // Wildcards match anything without binding it
+        (Symbol(s), _) if s.as_ref() == "_" => {}
 
         // Bind value to symbol
         (Symbol(s), expr) => {
             to.insert(s.clone(), expr.clone());
         }
 
+        // Destructure structs by their constructor
+        (Cons(l1), Struct(data))
+            if is_constructor_of(l1, &data.name)
+                && l1.len() == data.data.len() + 1 =>
+        {
+            for (pat, found) in l1.iter().skip(1).zip(data.data.iter()) {
+                extract_matches(syntax, pat.as_ref(), found, to)?;
+            }
+        }
+
         // Handle lists
         (Cons(l1), Cons(l2)) if l1.len() == l2.len() => {
             // Handle lists
@@ -1,7 +1,9 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{fmt, ops, str::FromStr};
-use regex::Regex;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Quat(pub f64, pub f64, pub f64, pub f64);
 
 impl fmt::Display for Quat {
@@ -190,90 +192,44 @@ mod tests {
         println!("ln(i) = {:?}", i.ln());
         assert!(false);
     }
-}
 
-/// Represents a quaternion in the form a + b*i + c*j + d*k.
-const QUAT_REGEX_STR_ABCD: &str = 
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)i([+-][0-9]+(\.[0-9]*)?)j([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form a + b*i.
-const QUAT_REGEX_STR_AB: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)i";
-
-/// Represents a quaternion in the form a + c*j.
-const QUAT_REGEX_STR_AC: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)j";
-
-/// Represents a quaternion in the form a + d*k.
-const QUAT_REGEX_STR_AD: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form b*i + c*j.
-const QUAT_REGEX_STR_BC: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)i([+-][0-9]+(\.[0-9]*)?)j";
-
-/// Represents a quaternion in the form b*i + d*k.
-const QUAT_REGEX_STR_BD: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)i([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form c*j + d*k.
-const QUAT_REGEX_STR_CD: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)j([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form a + b*i + c*j.
-const QUAT_REGEX_STR_ABC: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)i([+-][0-9]*(\.[0-9]*)?)j";
-
-/// Represents a quaternion in the form a + b*i + d*k.
-const QUAT_REGEX_STR_ABD: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)i([+-][0-9]*(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form a + c*j + d*k.
-const QUAT_REGEX_STR_ACD: &str =
-    r"([+-]?[0-9]+(\.[0-9]*)?)([+-][0-9]+(\.[0-9]*)?)j([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form b*i + c*j + d*k.
-const QUAT_REGEX_STR_BCD: &str =
-  r"([+-]?[0-9]+(\.[0-9]*)?)i([+-][0-9]+(\.[0-9]*)?)j([+-][0-9]+(\.[0-9]*)?)k";
-
-/// Represents a quaternion in the form b*i.
-const QUAT_REGEX_STR_B: &str = r"([+-]?[0-9]+(\.[0-9]*)?)i";
-
-/// Represents a quaternion in the form c*j.
-const QUAT_REGEX_STR_C: &str = r"([+-]?[0-9]+(\.[0-9]*)?)j";
-
-/// Represents a quaternion in the form d*k.
-const QUAT_REGEX_STR_D: &str = r"([+-]?[0-9]+(\.[0-9]*)?)k";
-
-lazy_static! {
-    static ref QUAT_REGEX_ABCD: Regex = Regex::new(QUAT_REGEX_STR_ABCD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_AB: Regex = Regex::new(QUAT_REGEX_STR_AB)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_AC: Regex = Regex::new(QUAT_REGEX_STR_AC)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_AD: Regex = Regex::new(QUAT_REGEX_STR_AD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_BC: Regex = Regex::new(QUAT_REGEX_STR_BC)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_BD: Regex = Regex::new(QUAT_REGEX_STR_BD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_CD: Regex = Regex::new(QUAT_REGEX_STR_CD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_ABC: Regex = Regex::new(QUAT_REGEX_STR_ABC)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_ABD: Regex = Regex::new(QUAT_REGEX_STR_ABD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_ACD: Regex = Regex::new(QUAT_REGEX_STR_ACD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_BCD: Regex = Regex::new(QUAT_REGEX_STR_BCD)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_B: Regex = Regex::new(QUAT_REGEX_STR_B)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_C: Regex = Regex::new(QUAT_REGEX_STR_C)
-        .expect("quaternion regex failed to compile");
-    static ref QUAT_REGEX_D: Regex = Regex::new(QUAT_REGEX_STR_D)
-        .expect("quaternion regex failed to compile");
+    #[test]
+    fn parse() {
+        let cases = [
+            ("1+2i+3j+4k", Quat(1.0, 2.0, 3.0, 4.0)),
+            ("1+2i", Quat(1.0, 2.0, 0.0, 0.0)),
+            ("1-2j", Quat(1.0, 0.0, -2.0, 0.0)),
+            ("1+2.5k", Quat(1.0, 0.0, 0.0, 2.5)),
+            ("2i+3j", Quat(0.0, 2.0, 3.0, 0.0)),
+            ("2i-3k", Quat(0.0, 2.0, 0.0, -3.0)),
+            ("3j+4k", Quat(0.0, 0.0, 3.0, 4.0)),
+            ("1+2i+3j", Quat(1.0, 2.0, 3.0, 0.0)),
+            ("1+2i+4k", Quat(1.0, 2.0, 0.0, 4.0)),
+            ("1+3j+4k", Quat(1.0, 0.0, 3.0, 4.0)),
+            ("-2i+3j+4k", Quat(0.0, -2.0, 3.0, 4.0)),
+            ("2i", Quat(0.0, 2.0, 0.0, 0.0)),
+            ("-3.j", Quat(0.0, 0.0, -3.0, 0.0)),
+            ("+4k", Quat(0.0, 0.0, 0.0, 4.0)),
+            ("5", Quat(5.0, 0.0, 0.0, 0.0)),
+            ("i", Quat(0.0, 1.0, 0.0, 0.0)),
+            ("1-i+k", Quat(1.0, -1.0, 0.0, 1.0)),
+            ("1e2+.5i", Quat(100.0, 0.5, 0.0, 0.0)),
+        ];
+        for (s, expected) in cases.iter() {
+            assert_eq!(s.parse::<Quat>().ok(), Some(*expected), "{}", s);
+        }
+    }
+
+    #[test]
+    fn parse_malformed() {
+        let cases = [
+            "", "+", "-", "1i2i", "1i+2i", "1+2", "2i3", "1.2.3i", "1+2x",
+            "1++2i", "1e", "ii", "1i j",
+        ];
+        for s in cases.iter() {
+            assert!(s.parse::<Quat>().is_err(), "{}", s);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -282,152 +238,70 @@ pub struct ParseQuatError;
 impl FromStr for Quat {
     type Err = ParseQuatError;
 
+    /// Parses a quaternion written as a sum of terms, such as `1+2i-3.5k`.
+    /// Each term is a real magnitude optionally followed by one of the units
+    /// `i`, `j`, or `k`, and every term after the first must begin with a
+    /// sign. The magnitude of a term with a unit may be omitted, such that `i`
+    /// means `1i`, but no component may be given more than once.
     fn from_str(s: &str) -> Result<Quat, Self::Err> {
-        if QUAT_REGEX_ABCD.is_match(s) {
-            let caps = QUAT_REGEX_ABCD.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let b_str = caps.get(3).map_or("1", |m| m.as_str());
-            let c_str = caps.get(5).map_or("1", |m| m.as_str());
-            let d_str = caps.get(7).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_BCD.is_match(s) {
-            let caps = QUAT_REGEX_BCD.captures(s).unwrap();
-            let b_str = caps.get(1).map_or("1", |m| m.as_str());
-            let c_str = caps.get(3).map_or("1", |m| m.as_str());
-            let d_str = caps.get(5).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_BC.is_match(s) {
-            let caps = QUAT_REGEX_BC.captures(s).unwrap();
-            let b_str = caps.get(1).map_or("1", |m| m.as_str());
-            let c_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_BD.is_match(s) {
-            let caps = QUAT_REGEX_BD.captures(s).unwrap();
-            let b_str = caps.get(1).map_or("1", |m| m.as_str());
-            let d_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = 0.0;
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_CD.is_match(s) {
-            let caps = QUAT_REGEX_CD.captures(s).unwrap();
-            let c_str = caps.get(1).map_or("1", |m| m.as_str());
-            let d_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = 0.0;
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_ABC.is_match(s) {
-            let caps = QUAT_REGEX_ABC.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let b_str = caps.get(3).map_or("1", |m| m.as_str());
-            let c_str = caps.get(5).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_ABD.is_match(s) {
-            let caps = QUAT_REGEX_ABD.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let b_str = caps.get(3).map_or("1", |m| m.as_str());
-            let d_str = caps.get(5).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = 0.0;
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_ACD.is_match(s) {
-            let caps = QUAT_REGEX_ACD.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let c_str = caps.get(3).map_or("1", |m| m.as_str());
-            let d_str = caps.get(5).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = 0.0;
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_AD.is_match(s) {
-            let caps = QUAT_REGEX_AD.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let d_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = 0.0;
-            let c = 0.0;
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_AC.is_match(s) {
-            let caps = QUAT_REGEX_AC.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let c_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = 0.0;
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_AB.is_match(s) {
-            let caps = QUAT_REGEX_AB.captures(s).unwrap();
-            let a_str = caps.get(1).map_or("", |m| m.as_str());
-            let b_str = caps.get(3).map_or("1", |m| m.as_str());
-
-            let a = a_str.parse::<f64>().unwrap_or_default();
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = 0.0;
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_B.is_match(s) {
-            let caps = QUAT_REGEX_B.captures(s).unwrap();
-            let b_str = caps.get(1).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = b_str.parse::<f64>().unwrap_or_default();
-            let c = 0.0;
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_C.is_match(s) {
-            let caps = QUAT_REGEX_C.captures(s).unwrap();
-            let c_str = caps.get(1).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = 0.0;
-            let c = c_str.parse::<f64>().unwrap_or_default();
-            let d = 0.0;
-            Ok(Quat(a, b, c, d))
-        } else if QUAT_REGEX_D.is_match(s) {
-            let caps = QUAT_REGEX_D.captures(s).unwrap();
-            let d_str = caps.get(1).map_or("1", |m| m.as_str());
-
-            let a = 0.0;
-            let b = 0.0;
-            let c = 0.0;
-            let d = d_str.parse::<f64>().unwrap_or_default();
-            Ok(Quat(a, b, c, d))
-        } else {
-            Err(ParseQuatError)
+        let bytes = s.as_bytes();
+        let mut components = [None; 4];
+        let mut pos = 0;
+        while pos < bytes.len() {
+            let start = pos;
+            match bytes[pos] {
+                b'+' | b'-' => pos += 1,
+                _ if start > 0 => return Err(ParseQuatError),
+                _ => (),
+            }
+
+            // Scan the magnitude, leaving its validation to `f64::from_str`
+            let digits = pos;
+            while pos < bytes.len()
+                && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.')
+            {
+                pos += 1;
+            }
+            if pos > digits && matches!(bytes.get(pos), Some(b'e') | Some(b'E'))
+            {
+                pos += 1;
+                if let Some(b'+') | Some(b'-') = bytes.get(pos) {
+                    pos += 1;
+                }
+                while pos < bytes.len() && bytes[pos].is_ascii_digit() {
+                    pos += 1;
+                }
+            }
+            let magnitude = &s[start..pos];
+
+            let index = match bytes.get(pos) {
+                Some(b'i') => 1,
+                Some(b'j') => 2,
+                Some(b'k') => 3,
+                _ => 0,
+            };
+            if index > 0 {
+                pos += 1;
+            }
+            let value = match magnitude {
+                "" | "+" if index > 0 => 1.0,
+                "-" if index > 0 => -1.0,
+                magnitude => magnitude.parse().map_err(|_| ParseQuatError)?,
+            };
+            match &mut components[index] {
+                Some(_) => return Err(ParseQuatError),
+                component => *component = Some(value),
+            }
+        }
+
+        match components {
+            [None, None, None, None] => Err(ParseQuatError),
+            [a, b, c, d] => Ok(Quat(
+                a.unwrap_or_default(),
+                b.unwrap_or_default(),
+                c.unwrap_or_default(),
+                d.unwrap_or_default(),
+            )),
         }
     }
 }
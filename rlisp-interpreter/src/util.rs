@@ -3,10 +3,16 @@
 
 use crate::{
     exception::Exception,
-    expression::Expression::{self, *},
+    expression::{
+        Callable::*,
+        Expression::{self, *},
+    },
 };
 use im::ConsList;
-use std::{io::prelude::*, rc::Rc};
+use std::{
+    io::{self, prelude::*},
+    rc::Rc,
+};
 use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 #[macro_export]
@@ -64,15 +70,21 @@ pub fn clear_color() {
 
 fn print_err_no_ln(ex: &Exception) {
     let mut sout = StandardStream::stdout(ColorChoice::Always);
-    sout.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))
-        .expect("failed to set stdout color");
-    write!(sout, "error({})", ex.error_code())
-        .expect("failed to write to stdout");
-    sout.set_color(ColorSpec::new().set_fg(None).set_bold(true))
-        .expect("failed to set stdout color");
-    write!(sout, ": {}", ex).expect("failed to write to stdout");
-    sout.set_color(ColorSpec::new().set_fg(None).set_bold(false))
-        .expect("failed to set stdout color");
+    write_err_no_ln(ex, &mut sout).expect("failed to write to stdout");
+}
+
+fn write_err_no_ln(
+    ex: &Exception,
+    out: &mut impl WriteColor,
+) -> io::Result<()> {
+    out.set_color(ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true))?;
+    write!(out, "error({})", ex.error_code())?;
+    out.set_color(ColorSpec::new().set_fg(None).set_bold(true))?;
+    write!(out, ": {}", ex)?;
+    if let Some(pos) = ex.position() {
+        write!(out, " at line {}, col {}", pos.line, pos.col)?;
+    }
+    out.set_color(ColorSpec::new().set_fg(None).set_bold(false))
 }
 
 /// Prints the specified exception in the following format:
@@ -118,21 +130,122 @@ pub fn print_pretty(text: impl AsRef<str>, color: Option<Color>, style: Style) {
         .expect("failed to set stdout color");
 }
 
+/// Prints the specified exception to `stdout`, followed by the frames of its
+/// stack trace.
 pub fn print_stack_trace(ex: &Exception) {
     let mut sout = StandardStream::stdout(ColorChoice::Always);
+    write_stack_trace(ex, &mut sout).expect("failed to write to stdout");
+}
+
+/// Writes the specified exception to the specified writer, followed by the
+/// frames of its stack trace.
+pub fn write_stack_trace(
+    ex: &Exception,
+    out: &mut impl WriteColor,
+) -> io::Result<()> {
     let stack: Vec<_> = ex.stack().iter().collect();
-    print_err_no_ln(ex);
+    write_err_no_ln(ex, out)?;
     for (i, item) in stack.into_iter().rev().enumerate() {
-        sout.set_color(ColorSpec::new().set_fg(None).set_bold(false))
-            .unwrap();
-        write!(sout, "\n at ").unwrap();
-        sout.set_color(ColorSpec::new().set_fg(None).set_bold(true))
-            .unwrap();
-        write!(sout, "[{}]", i).unwrap();
-        sout.set_color(ColorSpec::new().set_fg(None).set_bold(false))
-            .unwrap();
-        write!(sout, " {}", item).unwrap();
+        out.set_color(ColorSpec::new().set_fg(None).set_bold(false))?;
+        write!(out, "\n at ")?;
+        out.set_color(ColorSpec::new().set_fg(None).set_bold(true))?;
+        write!(out, "[{}]", i)?;
+        out.set_color(ColorSpec::new().set_fg(None).set_bold(false))?;
+        write!(out, " {}", item)?;
+    }
+    writeln!(out)?;
+    out.reset()
+}
+
+/// The width, in columns, beyond which `pretty_format` breaks expressions
+/// across multiple lines.
+pub const PRETTY_WIDTH: usize = 80;
+
+/// Formats the specified expression in the same way as its `Display`
+/// implementation, except that lists, vectors, and structs that do not fit
+/// within `PRETTY_WIDTH` columns are broken across multiple lines, with their
+/// elements indented.
+pub fn pretty_format(expr: &Expression) -> String {
+    pretty_format_width(expr, PRETTY_WIDTH)
+}
+
+/// Formats the specified expression as `pretty_format` does, breaking
+/// expressions that do not fit within the specified width.
+pub fn pretty_format_width(expr: &Expression, width: usize) -> String {
+    let mut buf = String::new();
+    pretty_format_to(expr, width, 0, &mut buf);
+    buf
+}
+
+fn pretty_format_to(
+    expr: &Expression,
+    width: usize,
+    indent: usize,
+    buf: &mut String,
+) {
+    let compact = expr.to_string();
+    if indent + compact.chars().count() <= width {
+        buf.push_str(&compact);
+        return;
+    }
+
+    // The opening text, the elements, and how many elements share a line with
+    // the opening text
+    let (open, items, inline): (String, Vec<Expression>, usize) = match expr {
+        Cons(list) if list.len() == 2 => {
+            let head = list.head().unwrap();
+            let body = list.tail().and_then(|tail| tail.head()).unwrap();
+            let prefix = match head.as_ref() {
+                Callable(Quote) => Some('\''),
+                Callable(Quasiquote) => Some('`'),
+                Callable(Unquote) => Some(','),
+                _ => None,
+            };
+            match prefix {
+                Some(prefix) => {
+                    buf.push(prefix);
+                    pretty_format_to(&body, width, indent + 1, buf);
+                    return;
+                }
+                None => (
+                    "(".into(),
+                    vec![head.as_ref().clone(), body.as_ref().clone()],
+                    1,
+                ),
+            }
+        }
+        Cons(list) => {
+            // Forms headed by a symbol keep their first argument on the same
+            // line, as in `(if <cond>`
+            let inline = match list.head().as_ref().map(|head| head.as_ref()) {
+                Some(Symbol(_)) => 2,
+                _ => 1,
+            };
+            let items = list.iter().map(|expr| expr.as_ref().clone()).collect();
+            ("(".into(), items, inline)
+        }
+        Vector(items) => ("#(".into(), items.as_ref().clone(), 1),
+        Struct(data) => (format!("(make-{}", data.name), data.data.clone(), 0),
+        _ => {
+            buf.push_str(&compact);
+            return;
+        }
+    };
+
+    buf.push_str(&open);
+    let child_indent = indent + 2;
+    for (i, item) in items.iter().enumerate() {
+        if i < inline {
+            if i > 0 {
+                buf.push(' ');
+            }
+            let col = buf.chars().rev().take_while(|&c| c != '\n').count();
+            pretty_format_to(item, width, col, buf);
+        } else {
+            buf.push('\n');
+            buf.push_str(&" ".repeat(child_indent));
+            pretty_format_to(item, width, child_indent, buf);
+        }
     }
-    write!(sout, "\n").unwrap();
-    clear_color();
+    buf.push(')');
 }
@@ -3,34 +3,51 @@
 //! then the intrinsic function is provided the evaluated arguments to produce
 //! its output.
 use rlisp_interpreter::{
-    context::Context,
+    context::{Context, Input, Output},
     exception::{ErrorCode, Exception, ExceptionData},
     expression::{
         Callable::*,
         Expression::{self, *},
-        StructData,
+        PromiseState, StructData,
     },
+    foreign::Foreign,
     im::ConsList,
+    intern::{self, Sym},
+    pattern::{extract_symbols, pattern_match, replace_symbols},
     quat::Quat,
-    termcolor::Color,
-    util::{print_pretty, print_stack_trace, wrap_begin, Str, Style},
+    termcolor::{Color, ColorChoice, NoColor, StandardStream},
+    util::{
+        pretty_format, pretty_format_width, print_pretty, write_stack_trace,
+        Str, Style,
+    },
 };
 
+#[cfg(feature = "wasm")]
+use crate::host;
+#[cfg(feature = "native")]
+use crate::terminal::{with_raw_mode, StdinTerminal};
+#[cfg(feature = "native")]
+use crate::thread::{self, Channel, ThreadHandle};
 #[cfg(feature = "native")]
 use http_request::http_request;
 
 #[cfg(feature = "native")]
 use rlisp_interpreter::rand::prelude::*;
 
-use rlisp_parser::{preprocessor::*, Parser};
+use regex::Regex;
+use rlisp_parser::{preprocessor::*, Parser, Position};
 use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::HashMap,
     env,
-    error::Error,
     fs::File,
-    io::{self, prelude::*, stdin, stdout, BufReader},
+    io::{self, prelude::*, stdin, BufReader},
     ops::{Add, Div, Mul, Rem, Sub},
-    path::Path,
+    path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
+    time::SystemTime,
 };
 
 /// Evaluates the specified unary function, checking arity and type signatures.
@@ -42,6 +59,16 @@ fn unary_fn(args: &[Expression], f: impl Fn(f64) -> f64) -> Expression {
     }
 }
 
+/// Evaluates the specified numeric predicate, checking arity and type
+/// signatures.
+fn predicate_fn(args: &[Expression], f: impl Fn(f64) -> bool) -> Expression {
+    match args {
+        [Num(x)] => Bool(f(*x)),
+        [value] => Error(Rc::new(Exception::signature("num", value.type_of()))),
+        arr => Error(Rc::new(Exception::arity(1, arr.len()))),
+    }
+}
+
 /// Evaluates the specified binary function, checking arity and type
 /// signatures.
 fn binary_fn(args: &[Expression], f: impl Fn(f64, f64) -> f64) -> Expression {
@@ -246,8 +273,11 @@ pub fn mul(args: &[Expression], _: &mut Context) -> Expression {
 
 /// `/ :: num ... -> num`
 ///
-/// Produces the quotient of the two specified values.
-pub fn div(args: &[Expression], _: &mut Context) -> Expression {
+/// Produces the quotient of the two specified values. Dividing by zero throws
+/// an exception, unless disabled by `set-zero-division-error`, while the
+/// reciprocal of zero is always infinite.
+pub fn div(args: &[Expression], ctx: &mut Context) -> Expression {
+    let zero_division_error = ctx.zero_division_error();
     match args.len() {
         0 => Error(Rc::new(Exception::custom(
             4,
@@ -265,7 +295,7 @@ pub fn div(args: &[Expression], _: &mut Context) -> Expression {
                 let nums: Result<Vec<_>, Exception> = tail
                     .iter()
                     .map(|expr| match expr {
-                        Num(n) if *n == 0.0 => {
+                        Num(n) if *n == 0.0 && zero_division_error => {
                             Err(Exception::custom(38, "division by 0"))
                         }
                         Num(n) => Ok(*n),
@@ -333,7 +363,7 @@ pub fn head(args: &[Expression], _: &mut Context) -> Expression {
             list.head().map(|head| (*head).clone()).unwrap_or_else(|| {
                 Error(Rc::new(Exception::custom(
                     10,
-                    "cannot get the tail of an empty list",
+                    "cannot get the head of an empty list",
                 )))
             })
         }
@@ -391,6 +421,18 @@ pub fn eq(args: &[Expression], _: &mut Context) -> Expression {
     }
 }
 
+/// `equal? :: a a -> bool`
+///
+/// Tests the two arguments for structural equality, comparing the contents of
+/// lists, vectors, structs, and boxes at any depth. Numbers are equal to the
+/// equivalent quaternions.
+pub fn equal(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [a, b] => Bool(a == b),
+        args => Error(Rc::new(Exception::arity(2, args.len()))),
+    }
+}
+
 /// `< :: a a -> bool`
 ///
 /// Determines whether or not the first argument is less than the second.
@@ -449,54 +491,169 @@ pub fn gte(args: &[Expression], _: &mut Context) -> Expression {
     }
 }
 
-/// `println :: a ... -> nil`
+/// `display :: a ... -> nil`
+///
+/// Prints the specified values one after another, without a new line.
+/// Strings are printed without quotes.
+pub fn display(args: &[Expression], ctx: &mut Context) -> Expression {
+    write_output(&display_text(args), ctx)
+}
+
+/// `displayln :: a ... -> nil`
+///
+/// Prints the specified values in the same way as `display`, followed by a
+/// new line.
+pub fn displayln(args: &[Expression], ctx: &mut Context) -> Expression {
+    write_output(&format!("{}\n", display_text(args)), ctx)
+}
+
+/// `eprint :: a ... -> nil`
 ///
-/// Prints the specified values, separated by spaces.
-pub fn display(args: &[Expression], _: &mut Context) -> Expression {
+/// Prints the specified values to `stderr`, in the same way as `display`.
+pub fn eprint(args: &[Expression], ctx: &mut Context) -> Expression {
+    write_error_output(&display_text(args), ctx)
+}
+
+/// `eprintln :: a ... -> nil`
+///
+/// Prints the specified values to `stderr`, in the same way as `display`,
+/// followed by a new line.
+pub fn eprintln(args: &[Expression], ctx: &mut Context) -> Expression {
+    write_error_output(&format!("{}\n", display_text(args)), ctx)
+}
+
+/// Produces the text printed by `display` for the specified values.
+fn display_text(args: &[Expression]) -> String {
+    let mut buf = String::new();
     for arg in args {
         let fmt = match arg {
             Str(s) => s.to_string(),
             other => other.to_string(),
         };
-        print!("{}", fmt);
+        buf.push_str(&fmt);
     }
-    stdout()
-        .flush()
+    buf
+}
+
+/// Writes the specified text to the output of the specified context,
+/// flushing it unless it is buffered.
+pub(crate) fn write_output(text: &str, ctx: &mut Context) -> Expression {
+    write_to(ctx.output(), text)
         .map_err(|_| Exception::custom(12, "could not flush stdout"))
         .map(|_| Expression::default())
         .unwrap_or_else(|ex| Error(Rc::new(ex)))
 }
 
+/// Writes the specified text to the error output of the specified context,
+/// flushing it unless it is buffered.
+fn write_error_output(text: &str, ctx: &mut Context) -> Expression {
+    write_to(ctx.error_output(), text)
+        .map_err(|_| Exception::custom(12, "could not flush stderr"))
+        .map(|_| Expression::default())
+        .unwrap_or_else(|ex| Error(Rc::new(ex)))
+}
+
+fn write_to(output: &mut Output, text: &str) -> io::Result<()> {
+    output.write_all(text.as_bytes())?;
+    output.flush_unbuffered()
+}
+
 /// `display-debug :: a ... -> nil`
 ///
 /// Prints the specified values in debug mode, separated by spaces.
-pub fn display_debug(args: &[Expression], _: &mut Context) -> Expression {
+pub fn display_debug(args: &[Expression], ctx: &mut Context) -> Expression {
+    let mut buf = String::new();
     for arg in args {
         let fmt = match arg {
             Str(s) => s.to_string(),
             other => other.to_string(),
         };
-        print!("{:?}", fmt);
+        buf.push_str(&format!("{:?}", fmt));
     }
-    stdout()
-        .flush()
-        .map_err(|_| Exception::custom(12, "could not flush stdout"))
-        .map(|_| Expression::default())
-        .unwrap_or_else(|ex| Error(Rc::new(ex)))
+    write_output(&buf, ctx)
 }
 
 /// `newline :: -> nil`
 ///
 /// Prints a new line.
-pub fn newline(args: &[Expression], _: &mut Context) -> Expression {
+pub fn newline(args: &[Expression], ctx: &mut Context) -> Expression {
     if args.len() == 0 {
-        println!();
-        Expression::default()
+        write_output("\n", ctx)
     } else {
         Error(Rc::new(Exception::arity(0, args.len())))
     }
 }
 
+/// Produces the escape sequence that clears the terminal and moves the cursor
+/// to the top left corner.
+fn clear_screen_escape() -> &'static str {
+    "\x1b[2J\x1b[H"
+}
+
+/// Produces the escape sequence that moves the cursor to the specified
+/// zero-based column and row.
+fn move_cursor_escape(col: usize, row: usize) -> String {
+    format!("\x1b[{};{}H", row + 1, col + 1)
+}
+
+/// Produces the escape sequence that hides or shows the cursor.
+fn cursor_visibility_escape(visible: bool) -> &'static str {
+    if visible {
+        "\x1b[?25h"
+    } else {
+        "\x1b[?25l"
+    }
+}
+
+/// `clear-screen :: -> nil`
+///
+/// Clears the terminal and moves the cursor to the top left corner.
+pub fn clear_screen(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => write_output(clear_screen_escape(), ctx),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// `move-cursor :: num num -> nil`
+///
+/// Moves the cursor to the specified zero-based column and row of the
+/// terminal.
+pub fn move_cursor(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Num(col), Num(row)] => match (count(*col), count(*row)) {
+            (Ok(col), Ok(row)) => {
+                write_output(&move_cursor_escape(col, row), ctx)
+            }
+            (Err(ex), _) | (_, Err(ex)) => ex,
+        },
+        [Num(_), other] | [other, _] => {
+            Error(Rc::new(Exception::signature("num", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `hide-cursor :: -> nil`
+///
+/// Hides the terminal cursor.
+pub fn hide_cursor(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => write_output(cursor_visibility_escape(false), ctx),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// `show-cursor :: -> nil`
+///
+/// Shows the terminal cursor.
+pub fn show_cursor(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => write_output(cursor_visibility_escape(true), ctx),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
 /// `append :: [a] ... -> [a]`
 ///
 /// Append all specified lists to the first specified list.
@@ -520,6 +677,34 @@ pub fn append(args: &[Expression], _: &mut Context) -> Expression {
     })
 }
 
+/// `flatten :: [a] -> [a]`
+///
+/// Flattens the specified list deeply, splicing the elements of every nested
+/// list, at any depth, into a single list. Other elements are kept as they
+/// are, such that `(flatten '((1 2) (3 (4))))` is `(1 2 3 4)`.
+pub fn flatten(args: &[Expression], _: &mut Context) -> Expression {
+    fn flatten_into(list: &ConsList<Expression>, buf: &mut Vec<Expression>) {
+        for x in list.iter() {
+            match x.as_ref() {
+                Cons(nested) => flatten_into(nested, buf),
+                x => buf.push(x.clone()),
+            }
+        }
+    }
+
+    match args {
+        [Cons(list)] => {
+            let mut buf = Vec::new();
+            flatten_into(list, &mut buf);
+            Cons(ConsList::from(buf))
+        }
+        [other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
 /// `empty? :: [a] -> bool`
 ///
 /// Determines whether or not the specified list is empty.
@@ -531,6 +716,206 @@ pub fn empty(args: &[Expression], _: &mut Context) -> Expression {
     }
 }
 
+/// `assoc :: a [[a b]] -> [a b] | nil`
+///
+/// Produces the first pair in the specified association list whose key is
+/// equal to the specified key, as by `eq?`, or `nil` if there is none.
+pub fn assoc(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [key, Cons(list)] => find_pair(list, |other| other == key),
+        [_, other] => Error(Rc::new(Exception::signature(
+            "(list (list a b))",
+            other.type_of(),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `assq :: symbol [[symbol b]] -> [symbol b] | nil`
+///
+/// Produces the first pair in the specified association list whose key is
+/// the specified symbol, or `nil` if there is none. Unlike `assoc`, only
+/// symbols are compared, which is done by their interned IDs.
+pub fn assq(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Symbol(key), Cons(list)] => find_pair(list, |other| match other {
+            Symbol(other) => other.id() == key.id(),
+            _ => false,
+        }),
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(symbol, (list (list symbol b)))",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `alist->map :: [[k v]] -> (map k v)`
+///
+/// Converts the specified association list into a map, which is a function
+/// producing the value of the first pair with the specified key, as by
+/// `eq?`, or `nil` if there is none.
+pub fn alist_to_map(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Cons(list)] => {
+            let pairs: Result<Vec<_>, _> = list
+                .iter()
+                .map(|pair| {
+                    pair_of(&pair).map(|(key, value)| {
+                        (key.as_ref().clone(), value.as_ref().clone())
+                    })
+                })
+                .collect();
+            let pairs = match pairs {
+                Ok(pairs) => pairs,
+                Err(ex) => return ex,
+            };
+            let map = move |args: &[Expression], _: &mut Context| match args {
+                [key] => pairs
+                    .iter()
+                    .find(|(other, _)| other == key)
+                    .map(|(_, value)| value.clone())
+                    .unwrap_or_default(),
+                xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+            };
+            Callable(Intrinsic(Rc::new(map)))
+        }
+        [other] => Error(Rc::new(Exception::signature(
+            "(list (list k v))",
+            other.type_of(),
+        ))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// Splits the specified element of an association list into its key and
+/// value, producing an exception unless it is a list of two elements.
+fn pair_of(
+    pair: &Expression,
+) -> Result<(Arc<Expression>, Arc<Expression>), Expression> {
+    match pair {
+        Cons(list) if list.len() == 2 => {
+            // Safe to unwrap after checking length
+            let key = list.head().unwrap();
+            let value = list.iter().nth(1).unwrap();
+            Ok((key, value))
+        }
+        other => Err(Error(Rc::new(Exception::custom(
+            62,
+            format!("malformed association list element: `{}`", other),
+        )))),
+    }
+}
+
+/// Finds the first pair in the specified association list whose key matches
+/// the specified predicate, producing `nil` if there is none.
+fn find_pair(
+    list: &ConsList<Expression>,
+    matches: impl Fn(&Expression) -> bool,
+) -> Expression {
+    for pair in list.iter() {
+        match pair_of(&pair) {
+            Ok((key, _)) if matches(&key) => return pair.as_ref().clone(),
+            Ok(_) => (),
+            Err(ex) => return ex,
+        }
+    }
+    Expression::default()
+}
+
+/// `pattern-match :: a b [symbol] -> [[symbol c]]`
+///
+/// Matches the specified input against the specified pattern, as by `match`,
+/// producing an association list of the values bound to each symbol in the
+/// pattern in the order they appear, or `nil` if the input does not match.
+/// The symbols in the optional list of literals must appear as themselves in
+/// the input rather than being bound. As a pattern that binds nothing also
+/// produces `nil`, such patterns are better tested with `equal?`.
+pub fn pattern_match_fn(args: &[Expression], _: &mut Context) -> Expression {
+    let (pattern, input, literals) = match args {
+        [pattern, input] => (pattern, input, Vec::new()),
+        [pattern, input, Cons(literals)] => {
+            let literals: Option<Vec<Sym>> = literals
+                .iter()
+                .map(|literal| match literal.as_ref() {
+                    Symbol(sym) => Some(sym.clone()),
+                    _ => None,
+                })
+                .collect();
+            match literals {
+                Some(literals) => (pattern, input, literals),
+                None => {
+                    return Error(Rc::new(Exception::signature(
+                        "list of symbols",
+                        args[2].to_string(),
+                    )))
+                }
+            }
+        }
+        [_, _, other] => {
+            return Error(Rc::new(Exception::signature(
+                "list",
+                other.type_of(),
+            )))
+        }
+        xs => return Error(Rc::new(Exception::arity(2, xs.len()))),
+    };
+
+    let matches = match pattern_match(&literals, pattern, input) {
+        Ok(matches) => matches,
+        Err(_) => return Expression::default(),
+    };
+    let mut seen = Vec::new();
+    let pairs: Vec<Expression> = extract_symbols(&literals, pattern)
+        .into_iter()
+        .filter_map(|name| {
+            if seen.contains(&name) {
+                return None;
+            }
+            let value = matches.get(&name)?.clone();
+            seen.push(name.clone());
+            Some(Cons(ConsList::from(vec![Symbol(name), value])))
+        })
+        .collect();
+    Cons(ConsList::from(pairs))
+}
+
+/// `substitute :: a [[symbol b]] -> a`
+///
+/// Replaces each symbol in the specified template that is a key of the
+/// specified association list with its value, such as the bindings produced
+/// by `pattern-match`. The template is not evaluated.
+pub fn substitute(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [template, Cons(bindings)] => {
+            let mut matches = HashMap::new();
+            for pair in bindings.iter() {
+                match pair_of(&pair) {
+                    Ok((key, value)) => match key.as_ref() {
+                        Symbol(name) => {
+                            matches
+                                .entry(name.clone())
+                                .or_insert_with(|| value.as_ref().clone());
+                        }
+                        other => {
+                            return Error(Rc::new(Exception::signature(
+                                "symbol",
+                                other.type_of(),
+                            )))
+                        }
+                    },
+                    Err(ex) => return ex,
+                }
+            }
+            replace_symbols(template, &matches)
+        }
+        [_, other] => {
+            Error(Rc::new(Exception::signature("list", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
 /// `eval :: a -> b`
 ///
 /// Evaluates the specified expression.
@@ -570,115 +955,485 @@ pub fn readfile(args: &[Expression], _: &mut Context) -> Expression {
     }
 }
 
-fn load_file(file: String) -> Result<Expression, Box<dyn StdError>> {
-    // Look for directive lines
-    let mut use_preprocessor = false;
-    let iter = file
-        .lines()
-        .filter(|line| !line.is_empty())
-        .filter(|line| line.trim().starts_with('#'))
-        .map(|line| line.split_at(1).1);
-    for line in iter {
-        if line.starts_with("!") {
-            continue;
-        } else if line == "enable-preprocessor" {
-            use_preprocessor = true;
-        } else {
-            Err(format!("{} is not a known preprocessor command", line))?;
+/// `list-dir :: str -> [str]`
+///
+/// Produces the names of the entries in the specified directory, sorted
+/// alphabetically.
+#[cfg(feature = "native")]
+pub fn list_dir(args: &[Expression], _: &mut Context) -> Expression {
+    fn read_dir(name: &str) -> Result<Vec<String>, io::Error> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(name)? {
+            let entry = entry?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
         }
+        names.sort();
+        Ok(names)
     }
 
-    let removed_commands: String = file
-        .lines()
-        .filter(|line| !line.trim().starts_with('#'))
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    // println!("{}", removed_commands);
-    let processed;
-    let iter = match use_preprocessor {
-        true => {
-            let stripped = first_pass(removed_commands);
-            processed = second_pass(stripped);
-            processed.chars()
-        }
-        false => removed_commands.chars(),
-    };
-
-    let mut parser = Parser::new(iter);
-
-    let mut exprs = Vec::new();
-    while let Some(expr) = parser.parse_expr() {
-        exprs.push(expr);
+    match args {
+        [Str(s)] => read_dir(s.as_ref())
+            .map(|names| {
+                let list: ConsList<Expression> = names
+                    .into_iter()
+                    .map::<Expression, _>(|name| name.into())
+                    .collect();
+                Cons(list)
+            })
+            .unwrap_or_else(|e| {
+                Error(Rc::new(Exception::custom(
+                    43,
+                    format!(
+                        "could not read directory {}, reason: {}",
+                        s,
+                        e.to_string().to_lowercase()
+                    ),
+                )))
+            }),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
     }
-
-    let expr = wrap_begin(exprs.into());
-    Ok(expr)
 }
 
-/// Attempts to read and parse the specified file, using the preprocessor as
-/// needed.
-fn load_path(file_name: impl AsRef<str>) -> Result<Expression, Box<Error>> {
-    let file = File::open(file_name.as_ref())?;
-    let mut reader = BufReader::new(file);
-
-    let mut buf = String::new();
-
-    reader.read_to_string(&mut buf)?;
-
-    load_file(buf)
+/// `file-exists? :: str -> bool`
+///
+/// Determines whether or not a file or directory exists at the specified
+/// path.
+#[cfg(feature = "native")]
+pub fn file_exists(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => Bool(Path::new(s.as_ref()).exists()),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
 }
-use std::error::Error as StdError;
 
+/// `is-dir? :: str -> bool`
+///
+/// Determines whether or not the specified path refers to a directory.
 #[cfg(feature = "native")]
-fn request(uri: impl AsRef<str>) -> Result<String, Box<dyn StdError>> {
-    Ok(http_request(uri).ok_or("this method is not yet implemented")?)
+pub fn is_dir(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => Bool(Path::new(s.as_ref()).is_dir()),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
 }
 
+/// Converts the result of a filesystem operation into an expression,
+/// producing `nil` on success.
 #[cfg(feature = "native")]
-fn load_http(url: impl AsRef<str>) -> Result<Expression, Box<dyn StdError>> {
-    let text = request(url)?;
-    load_file(text)
+fn fs_result(res: io::Result<()>, description: String) -> Expression {
+    res.map(|_| Expression::default()).unwrap_or_else(|e| {
+        Error(Rc::new(Exception::custom(
+            44,
+            format!(
+                "{}, reason: {}",
+                description,
+                e.to_string().to_lowercase()
+            ),
+        )))
+    })
 }
 
+/// `make-dir :: str -> nil`
+///
+/// Creates the specified directory, along with any missing parent
+/// directories.
 #[cfg(feature = "native")]
-pub fn read_http(args: &[Expression], _: &mut Context) -> Expression {
+pub fn make_dir(args: &[Expression], _: &mut Context) -> Expression {
     match args {
-        [Str(s)] => {
-            // .. Do stuff
-            request(s).map(|s| s.into()).unwrap_or_else(|e| {
-                Error(Rc::new(Exception::custom(99, e.to_string())))
-            })
-        }
+        [Str(s)] => fs_result(
+            std::fs::create_dir_all(s.as_ref()),
+            format!("could not create directory {}", s),
+        ),
         [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
         xs => Error(Rc::new(Exception::arity(1, xs.len()))),
     }
 }
 
-trait IsUrl {
-    fn is_url(&self) -> bool;
+/// `remove-file :: str -> nil`
+///
+/// Removes the specified file.
+#[cfg(feature = "native")]
+pub fn remove_file(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => fs_result(
+            std::fs::remove_file(s.as_ref()),
+            format!("could not remove file {}", s),
+        ),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
 }
 
-impl<T> IsUrl for T
-where
-    T: AsRef<str>,
-{
-    fn is_url(&self) -> bool {
-        let s = self.as_ref();
-        s.starts_with("http://") || s.starts_with("https://")
+/// `rename :: str str -> nil`
+///
+/// Renames the specified file or directory, replacing the destination if it
+/// already exists.
+#[cfg(feature = "native")]
+pub fn rename(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(from), Str(to)] => fs_result(
+            std::fs::rename(from.as_ref(), to.as_ref()),
+            format!("could not rename {} to {}", from, to),
+        ),
+        [x, y] => Error(Rc::new(Exception::signature(
+            "str, str",
+            format!("{}, {}", x.type_of(), y.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
     }
 }
 
-/// `import :: string -> a`
+/// `current-dir :: -> str`
 ///
-/// Reads, parses, and runs the specified file, returning its result.
+/// Produces the current working directory.
 #[cfg(feature = "native")]
-pub fn import(args: &[Expression], ctx: &mut Context) -> Expression {
-    #[cfg(target_os = "windows")]
-    // fn clean_file_path(path: impl AsRef<str>) -> String {
-    //     let path = path.as_ref();
-    //     path.replace("/", "\\")
-    // }
+pub fn current_dir(args: &[Expression], _: &mut Context) -> Expression {
+    match args.len() {
+        0 => env::current_dir()
+            .map(|dir| dir.to_string_lossy().into_owned().into())
+            .unwrap_or_else(|e| {
+                Error(Rc::new(Exception::custom(
+                    44,
+                    format!(
+                        "could not read current directory, reason: {}",
+                        e.to_string().to_lowercase()
+                    ),
+                )))
+            }),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// `set-current-dir :: str -> nil`
+///
+/// Changes the current working directory to the specified directory.
+#[cfg(feature = "native")]
+pub fn set_current_dir(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => fs_result(
+            env::set_current_dir(s.as_ref()),
+            format!("could not change current directory to {}", s),
+        ),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// Evaluates the specified path component function, producing an empty string
+/// if the component is not present.
+fn path_fn(
+    args: &[Expression],
+    f: impl Fn(&Path) -> Option<&std::ffi::OsStr>,
+) -> Expression {
+    match args {
+        [Str(s)] => f(Path::new(s.as_ref()))
+            .map(|component| component.to_string_lossy().into_owned())
+            .unwrap_or_default()
+            .into(),
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `path-join :: str ... -> str`
+///
+/// Joins the specified path segments using the platform's path separator. An
+/// absolute segment replaces everything before it.
+pub fn path_join(args: &[Expression], _: &mut Context) -> Expression {
+    let segments: Result<Vec<_>, &Expression> = args
+        .iter()
+        .map(|expr| match expr {
+            Str(s) => Ok(s),
+            other => Err(other),
+        })
+        .collect();
+    segments
+        .map(|segments| {
+            let path: std::path::PathBuf =
+                segments.iter().map(|s| s.as_ref()).collect();
+            path.to_string_lossy().into_owned().into()
+        })
+        .unwrap_or_else(|x| {
+            Error(Rc::new(Exception::signature("str", x.type_of())))
+        })
+}
+
+/// `path-basename :: str -> str`
+///
+/// Produces the final component of the specified path.
+pub fn path_basename(args: &[Expression], _: &mut Context) -> Expression {
+    path_fn(args, Path::file_name)
+}
+
+/// `path-dirname :: str -> str`
+///
+/// Produces the specified path without its final component.
+pub fn path_dirname(args: &[Expression], _: &mut Context) -> Expression {
+    path_fn(args, |path| path.parent().map(Path::as_os_str))
+}
+
+/// `path-extension :: str -> str`
+///
+/// Produces the extension of the final component of the specified path,
+/// without the leading `.`.
+pub fn path_extension(args: &[Expression], _: &mut Context) -> Expression {
+    path_fn(args, Path::extension)
+}
+
+/// Determines whether or not the specified line of a file is a directive,
+/// such as `#enable-preprocessor`. Lines beginning with a vector literal or a
+/// block comment are not directives.
+fn is_directive(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('#') && !line.starts_with("#(") && !line.starts_with("#|")
+}
+
+/// Handles the directives of the specified file, producing its source with
+/// the directives removed and the preprocessor applied as needed.
+fn preprocess(file: String) -> Result<String, Box<dyn StdError>> {
+    /// The directives recognized at the start of a line.
+    const DIRECTIVES: &[&str] = &["#!<interpreter>", "#enable-preprocessor"];
+
+    // Look for directive lines
+    let mut use_preprocessor = false;
+    let iter = file
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| is_directive(line))
+        .map(|(i, line)| (i + 1, line.trim().split_at(1).1));
+    for (line_num, line) in iter {
+        if line.starts_with('!') {
+            continue;
+        } else if line == "enable-preprocessor" {
+            use_preprocessor = true;
+        } else {
+            Err(format!(
+                "line {}: `#{}` is not a known preprocessor directive; \
+                 expected one of: {}",
+                line_num,
+                line,
+                DIRECTIVES.join(", ")
+            ))?;
+        }
+    }
+
+    // Blank out directives, keeping line numbers intact for syntax errors
+    let removed_commands: String = file
+        .lines()
+        .map(|line| if is_directive(line) { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // println!("{}", removed_commands);
+    match use_preprocessor {
+        true => {
+            let stripped = first_pass(removed_commands);
+            Ok(second_pass(stripped).map_err(|ex| ex.to_string())?)
+        }
+        false => Ok(removed_commands),
+    }
+}
+
+/// A top-level form of a file, along with the position at which it begins.
+type Form = (Position, Expression);
+
+/// Parses the top-level forms of the specified file, using the preprocessor as
+/// needed. If a syntax error is encountered, it is produced as the error, such
+/// that no part of a malformed file is ever evaluated.
+fn load_file(file: String) -> Result<Vec<Form>, Box<dyn StdError>> {
+    let src = preprocess(file)?;
+    let mut parser = Parser::new(src.chars());
+
+    let mut forms = Vec::new();
+    while let Some(form) = parser.try_parse_located()? {
+        forms.push(form);
+    }
+    Ok(forms)
+}
+
+/// Evaluates the specified top-level forms one at a time, producing the value
+/// of the last. If a form produces an exception, evaluation stops and the
+/// exception is extended with the index and position of the form.
+fn eval_forms(forms: &[Form], ctx: &mut Context) -> Expression {
+    let mut res = Expression::default();
+    for (i, (pos, form)) in forms.iter().enumerate() {
+        res = form.eval(ctx);
+        if let Error(ex) = &res {
+            let file = ctx
+                .get_cur_file()
+                .map(|file| format!(" of {}", file))
+                .unwrap_or_default();
+            let frame = format!(
+                "<top-level form {} at line {}, col {}{}>",
+                i + 1,
+                pos.line,
+                pos.col,
+                file
+            );
+            return Error(Rc::new(ex.extend(&Str(frame.into()))));
+        }
+    }
+    res
+}
+
+/// Parses the specified file without evaluating it, using the preprocessor
+/// as needed. The first syntax error found, if any, is produced as an error.
+fn check_file(file: String) -> Result<(), Box<dyn StdError>> {
+    let src = preprocess(file)?;
+    match Parser::new(src.chars()).parse_all() {
+        Error(ex) => Err(ex.to_string().into()),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the entire contents of the specified file.
+fn read_path(file_name: impl AsRef<str>) -> Result<String, Box<dyn StdError>> {
+    let file = File::open(file_name.as_ref())?;
+    let mut reader = BufReader::new(file);
+
+    let mut buf = String::new();
+
+    reader.read_to_string(&mut buf)?;
+
+    Ok(buf)
+}
+
+/// A parsed file, along with the modification time of the file when it was
+/// read.
+type CachedFile = (SystemTime, Rc<Vec<Form>>);
+
+thread_local! {
+    /// The files parsed by `load_path`, keyed by their canonical paths.
+    static PARSE_CACHE: RefCell<HashMap<PathBuf, CachedFile>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Attempts to read and parse the specified file, using the preprocessor as
+/// needed. The parsed file is cached, so that it is only read again once it
+/// has been modified.
+fn load_path(
+    file_name: impl AsRef<str>,
+) -> Result<Rc<Vec<Form>>, Box<dyn StdError>> {
+    let path = std::fs::canonicalize(file_name.as_ref())?;
+    let modified = std::fs::metadata(&path)?.modified()?;
+    let cached = PARSE_CACHE.with(|cache| match cache.borrow().get(&path) {
+        Some((time, forms)) if *time == modified => Some(forms.clone()),
+        _ => None,
+    });
+    if let Some(forms) = cached {
+        return Ok(forms);
+    }
+
+    let forms = Rc::new(load_file(read_path(file_name)?)?);
+    PARSE_CACHE.with(|cache| {
+        cache.borrow_mut().insert(path, (modified, forms.clone()))
+    });
+    Ok(forms)
+}
+
+/// Attempts to read and parse the specified file without evaluating any of
+/// it, producing the first syntax error found, if any.
+pub fn check_path(file_name: impl AsRef<str>) -> Result<(), Box<dyn StdError>> {
+    check_file(read_path(file_name)?)
+}
+use std::error::Error as StdError;
+
+#[cfg(feature = "native")]
+fn request(uri: impl AsRef<str>) -> Result<String, Box<dyn StdError>> {
+    Ok(http_request(uri).ok_or("this method is not yet implemented")?)
+}
+
+#[cfg(feature = "native")]
+fn load_http(url: impl AsRef<str>) -> Result<Rc<Vec<Form>>, Box<dyn StdError>> {
+    let text = request(url)?;
+    load_file(text).map(Rc::new)
+}
+
+#[cfg(feature = "native")]
+pub fn read_http(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => {
+            // .. Do stuff
+            request(s).map(|s| s.into()).unwrap_or_else(|e| {
+                Error(Rc::new(Exception::custom(99, e.to_string())))
+            })
+        }
+        [x] => Error(Rc::new(Exception::signature("str", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+trait IsUrl {
+    fn is_url(&self) -> bool;
+}
+
+impl<T> IsUrl for T
+where
+    T: AsRef<str>,
+{
+    fn is_url(&self) -> bool {
+        let s = self.as_ref();
+        s.starts_with("http://") || s.starts_with("https://")
+    }
+}
+
+/// `import :: string -> nil`
+///
+/// Reads, parses, and runs the specified file. Files that have already been
+/// imported into the context are skipped.
+#[cfg(feature = "native")]
+pub fn import(args: &[Expression], ctx: &mut Context) -> Expression {
+    import_file(args, ctx, false)
+}
+
+/// `force-import :: string -> nil`
+///
+/// Reads, parses, and runs the specified file, even if it has already been
+/// imported into the context.
+#[cfg(feature = "native")]
+pub fn force_import(args: &[Expression], ctx: &mut Context) -> Expression {
+    import_file(args, ctx, true)
+}
+
+/// Produces the exception for a file that could not be read by `import`,
+/// distinguishing files that do not exist from other failures. Syntax errors
+/// in the file are produced as they are.
+#[cfg(feature = "native")]
+fn read_error(file_name: &str, err: &(dyn StdError + 'static)) -> Exception {
+    if let Some(ex) = err.downcast_ref::<Exception>() {
+        return ex.clone();
+    }
+    match err.downcast_ref::<io::Error>() {
+        Some(err) if err.kind() == io::ErrorKind::NotFound => {
+            Exception::custom(59, format!("file not found: \"{}\"", file_name))
+        }
+        _ => Exception::custom(
+            14,
+            format!(
+                "could not read file: \"{}\", reason: {}",
+                file_name,
+                err.to_string().to_lowercase()
+            ),
+        ),
+    }
+}
+
+/// Imports the specified file. Unless `force` is set, files that have already
+/// been read by the context are not run a second time.
+#[cfg(feature = "native")]
+fn import_file(
+    args: &[Expression],
+    ctx: &mut Context,
+    force: bool,
+) -> Expression {
+    #[cfg(target_os = "windows")]
+    // fn clean_file_path(path: impl AsRef<str>) -> String {
+    //     let path = path.as_ref();
+    //     path.replace("/", "\\")
+    // }
     #[cfg(not(target_os = "windows"))]
     fn clean_file_path(path: impl AsRef<str>) -> String {
         path.as_ref().to_string()
@@ -772,11 +1527,11 @@ pub fn import(args: &[Expression], ctx: &mut Context) -> Expression {
             let new_file_name = resolve_file_path(file_name, ctx);
 
             // Check if we have read the file already
-            // if ctx.has_read_file(&new_file_name) {
-            //     Expression::default()
-            // } else {
             let file_str = new_file_name.to_str();
-            ctx.add_file(file_str);
+            if !force && ctx.has_read_file(&file_str) {
+                return Expression::default();
+            }
+            ctx.add_file(file_str.clone());
 
             let res = match &new_file_name {
                 ResolvedFile::Url(url) => load_http(url),
@@ -786,23 +1541,23 @@ pub fn import(args: &[Expression], ctx: &mut Context) -> Expression {
             // let res = load_file(&new_file_name);
             let prev_file_name = ctx.get_cur_file();
             ctx.insert("__FILE__", new_file_name.as_str());
-            let res = res.map(|ex| ex.eval(ctx)).unwrap_or_else(|e| {
-                Error(Rc::new(Exception::custom(
-                    14,
-                    format!(
-                        "could not read file: \"{}\", reason: {}",
-                        file_name,
-                        e.to_string().to_lowercase()
-                    ),
-                )))
-            });
+            let res =
+                res.map(|forms| eval_forms(&forms, ctx))
+                    .unwrap_or_else(|e| {
+                        Error(Rc::new(read_error(file_name, e.as_ref())))
+                    });
             if let Some(prev) = prev_file_name {
                 ctx.insert("__FILE__", prev);
             }
-            if res.is_exception() {
-                res
-            } else {
-                Expression::default()
+            match res {
+                Error(ex) => {
+                    // Allow the file to be imported again once the error is
+                    // fixed
+                    ctx.remove_file(&file_str);
+                    let frame = format!("<import of {}>", file_str);
+                    Error(Rc::new(ex.extend(&Str(frame.into()))))
+                }
+                _ => Expression::default(),
             }
         }
         xs => Error(Rc::new(Exception::arity(1, xs.len()))),
@@ -812,63 +1567,488 @@ pub fn import(args: &[Expression], ctx: &mut Context) -> Expression {
 /// `readline :: -> string`
 ///
 /// Waits for the user to enter a line and returns the contents of the line.
-pub fn readline(args: &[Expression], _: &mut Context) -> Expression {
+/// Produces `nil` at the end of the input, so that an empty line may be
+/// distinguished from there being no more lines to read.
+pub fn readline(args: &[Expression], ctx: &mut Context) -> Expression {
     match args.len() {
         0 => {
-            let mut buf = String::new();
-            stdin()
-                .read_line(&mut buf)
-                .map_err(|_| Exception::custom(15, "failed to read stdin"))
-                .map(|_| Str(buf.trim().into()))
-                .unwrap_or_else(|ex| Error(Rc::new(ex)))
+            let peeked = ctx.peeked_char().take();
+            let mut buf: String = peeked.into_iter().collect();
+            match (peeked, ctx.input()) {
+                (Some('\n'), _) => Ok(0),
+                (_, Input::Stdin) => stdin().read_line(&mut buf),
+                (_, Input::Reader(reader)) => reader.read_line(&mut buf),
+            }
+            .map_err(|_| Exception::custom(15, "failed to read stdin"))
+            .map(|_| match buf.as_str() {
+                "" => Expression::default(),
+                line => Str(line.trim().into()),
+            })
+            .unwrap_or_else(|ex| Error(Rc::new(ex)))
         }
         n => Error(Rc::new(Exception::arity(0, n))),
     }
 }
 
-fn quote(expr: Expression) -> Expression {
-    let list = ConsList::from(vec![Callable(Quote), expr]);
-    Cons(list)
+/// `read-char :: -> string`
+///
+/// Waits for the user to press a key and returns the character entered,
+/// without waiting for a newline where the terminal supports it. Produces
+/// `nil` at the end of the input.
+#[cfg(feature = "native")]
+pub fn read_char(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 if ctx.peeked_char().is_some() => {
+            char_or_eof(ctx.peeked_char().take())
+        }
+        0 => match ctx.input() {
+            Input::Stdin => {
+                with_raw_mode(&mut StdinTerminal::default(), || {
+                    read_utf8_char(&mut stdin())
+                })
+            }
+            Input::Reader(reader) => read_utf8_char(reader),
+        }
+        .map_err(|_| Exception::custom(15, "failed to read stdin"))
+        .map(char_or_eof)
+        .unwrap_or_else(|ex| Error(Rc::new(ex))),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
 }
 
-/// `parse :: string -> expr`
+/// `peek-char :: -> string`
 ///
-/// Parses the specified string as an expression.
-pub fn parse(args: &[Expression], _: &mut Context) -> Expression {
+/// Waits for the user to press a key and returns the character entered, like
+/// `read-char`, but leaves the character to be read again by the next read.
+/// Produces `nil` at the end of the input.
+#[cfg(feature = "native")]
+pub fn peek_char(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 if ctx.peeked_char().is_some() => char_or_eof(*ctx.peeked_char()),
+        0 => match read_char(args, ctx) {
+            Str(s) => {
+                *ctx.peeked_char() = s.chars().next();
+                Str(s)
+            }
+            other => other,
+        },
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// Produces the specified character as a string, or `nil` at the end of the
+/// input.
+#[cfg(feature = "native")]
+fn char_or_eof(c: Option<char>) -> Expression {
+    c.map(|c| Str(c.to_string().into())).unwrap_or_default()
+}
+
+/// Produces the length in bytes of a UTF-8 encoded character beginning with
+/// the specified byte.
+#[cfg(feature = "native")]
+fn utf8_len(first: u8) -> usize {
+    match first {
+        b if b & 0b1000_0000 == 0 => 1,
+        b if b & 0b1110_0000 == 0b1100_0000 => 2,
+        b if b & 0b1111_0000 == 0b1110_0000 => 3,
+        _ => 4,
+    }
+}
+
+/// Decodes a single UTF-8 encoded character from the specified bytes.
+#[cfg(feature = "native")]
+fn decode_utf8_char(bytes: &[u8]) -> io::Result<Option<char>> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+        .map(Some)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+}
+
+/// Reads a single UTF-8 encoded character from the specified reader, or
+/// `None` at the end of the input.
+#[cfg(feature = "native")]
+fn read_utf8_char(reader: &mut impl Read) -> io::Result<Option<char>> {
+    let mut buf = [0; 4];
+    if reader.read(&mut buf[..1])? == 0 {
+        return Ok(None);
+    }
+    let len = utf8_len(buf[0]);
+    reader.read_exact(&mut buf[1..len])?;
+    decode_utf8_char(&buf[..len])
+}
+
+/// `spawn :: (-> a) -> thread`
+///
+/// Calls the specified lambda with no arguments on a new thread, producing a
+/// handle to that thread. The lambda runs in a fresh context, and so may only
+/// use intrinsics, the values it has captured, and itself.
+#[cfg(feature = "native")]
+pub fn spawn(args: &[Expression], ctx: &mut Context) -> Expression {
     match args {
-        [Str(s)] => {
-            let mut parser = Parser::new(s.chars());
-            parser
-                .parse_expr()
-                .unwrap_or_else(|| quote(Expression::default()))
+        [f] if f.is_callable() => {
+            let version = ctx
+                .get("version")
+                .map(|v| v.to_string())
+                .unwrap_or_default();
+            thread::spawn(f, own_name(f, ctx).as_ref(), version)
+                .map(|handle| Foreign(Rc::new(handle)))
+                .unwrap_or_else(|ex| Error(Rc::new(ex)))
+        }
+        [other] => {
+            Error(Rc::new(Exception::signature("lambda", other.type_of())))
         }
-        [x] => Error(Rc::new(Exception::signature("string", x.type_of()))),
         xs => Error(Rc::new(Exception::arity(1, xs.len()))),
     }
 }
 
-/// `type-of :: a -> symbol`
+/// Produces the name by which the specified lambda refers to itself, if any.
+/// Functions defined with `define` are not in their own capture, and instead
+/// find themselves by name in the context.
+#[cfg(feature = "native")]
+fn own_name(f: &Expression, ctx: &Context) -> Option<Sym> {
+    let data = match f {
+        Callable(Lambda(data)) => data,
+        _ => return None,
+    };
+    data.body
+        .extract_symbols(ctx)
+        .into_iter()
+        .find(|(_, value)| match value {
+            Callable(Lambda(other)) => Rc::ptr_eq(data, other),
+            _ => false,
+        })
+        .map(|(name, _)| name)
+}
+
+/// `join :: thread -> a`
 ///
-/// Produces the type of the specified expression.
-pub fn type_of(args: &[Expression], _: &mut Context) -> Expression {
+/// Waits for the specified thread to finish, producing the result of its
+/// lambda.
+#[cfg(feature = "native")]
+pub fn join(args: &[Expression], _: &mut Context) -> Expression {
     match args {
-        [ex] => Symbol(ex.type_of()),
+        [Foreign(value)] if value.downcast_ref::<ThreadHandle>().is_some() => {
+            value
+                .downcast_ref::<ThreadHandle>()
+                .unwrap()
+                .join()
+                .unwrap_or_else(|ex| Error(Rc::new(ex)))
+        }
+        [other] => {
+            Error(Rc::new(Exception::signature("thread", other.type_of())))
+        }
         xs => Error(Rc::new(Exception::arity(1, xs.len()))),
     }
 }
 
-/// Stores data for splitting an interpolated string into its various parts.
-#[derive(Debug)]
-enum StrSection<'a> {
-    /// A literal string.
-    Literal(&'a str),
+/// Produces the channel stored in the specified expression.
+#[cfg(feature = "native")]
+fn channel(expr: &Expression) -> Result<&Channel, Expression> {
+    match expr {
+        Foreign(value) => value.downcast_ref::<Channel>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error(Rc::new(Exception::signature("channel", expr.type_of())))
+    })
+}
 
-    // The contents of an interpolated string to be interpolated.
-    Expr(&'a str),
+/// `make-channel :: -> channel`
+///
+/// Produces a new channel, through which threads may send each other values.
+#[cfg(feature = "native")]
+pub fn make_channel(args: &[Expression], _: &mut Context) -> Expression {
+    match args.len() {
+        0 => Foreign(Rc::new(Channel::open())),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
 }
 
-/// Splits the specified string into a list of its literal and expression
-/// parts.
+/// `channel-send :: channel a -> nil`
+///
+/// Sends the specified value through the specified channel.
+#[cfg(feature = "native")]
+pub fn channel_send(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [ch, value] => match channel(ch) {
+            Ok(ch) => ch
+                .send(value)
+                .map(|_| Expression::default())
+                .unwrap_or_else(|ex| Error(Rc::new(ex))),
+            Err(ex) => ex,
+        },
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `channel-recv :: channel -> a`
+///
+/// Waits for a value to be sent through the specified channel, producing it.
+#[cfg(feature = "native")]
+pub fn channel_recv(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [ch] => match channel(ch) {
+            Ok(ch) => ch.recv().unwrap_or_else(|ex| Error(Rc::new(ex))),
+            Err(ex) => ex,
+        },
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+fn quote(expr: Expression) -> Expression {
+    let list = ConsList::from(vec![Callable(Quote), expr]);
+    Cons(list)
+}
+
+/// `dump-preprocessed :: string -> string`
+///
+/// Runs the preprocessor over the specified source text, producing the
+/// resulting s-expression text without evaluating it.
+pub fn dump_preprocessed(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => match second_pass(first_pass(s.to_string())) {
+            Ok(processed) => Str(processed.into()),
+            Err(ex) => Error(Rc::new(ex)),
+        },
+        [x] => Error(Rc::new(Exception::signature("string", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `parse :: string -> expr`
+///
+/// Parses the specified string as an expression.
+pub fn parse(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(s)] => {
+            let mut parser = Parser::new(s.chars());
+            parser
+                .parse_expr()
+                .unwrap_or_else(|| quote(Expression::default()))
+        }
+        [x] => Error(Rc::new(Exception::signature("string", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `bindings :: -> [symbol]`
+///
+/// Produces the names of all bindings visible in the current context, sorted
+/// alphabetically.
+pub fn bindings(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => Cons(
+            ctx.binding_names()
+                .into_iter()
+                .map(|name| Symbol(name.into()))
+                .collect(),
+        ),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// `rc-count :: a -> num`
+///
+/// Produces the number of strong references to the shared allocation
+/// underlying the specified value, or 1 if it has none. Lists always produce
+/// 1, as the sharing of their nodes cannot be observed, so this cannot
+/// diagnose the retention of lists.
+#[cfg(feature = "debug")]
+pub fn rc_count(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value] => Num(value.rc_count() as f64),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `type-of :: a -> symbol`
+///
+/// Produces the type of the specified expression.
+pub fn type_of(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [ex] => Symbol(ex.type_of().into()),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `gensym :: -> symbol`
+///
+/// Produces a symbol that no previous call has produced, such as `g__0`, for
+/// use in macros that must not capture the names used by their callers.
+pub fn gensym(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [] => Symbol(format!("g__{}", ctx.next_gensym()).into()),
+        xs => Error(Rc::new(Exception::arity(0, xs.len()))),
+    }
+}
+
+/// Produces the fields of the specified `error` struct, as passed to the
+/// handler of `try`.
+fn error_fields(expr: &Expression) -> Result<&[Expression], Expression> {
+    match expr {
+        Struct(data) if &*data.name == "error" && data.data.len() == 3 => {
+            Ok(&data.data)
+        }
+        other => Err(Error(Rc::new(Exception::signature(
+            "error",
+            other.type_of(),
+        )))),
+    }
+}
+
+/// `error? :: a -> bool`
+///
+/// Determines whether or not the specified value is an `error` struct, such
+/// as one caught by `try` or `catch`.
+///
+/// This and the other error accessors take the `error` struct rather than the
+/// exception itself, since an argument that throws propagates its exception
+/// before any function is called. An expression that may throw is therefore
+/// first wrapped in `catch`, as in `(error? (catch (head '())))`.
+pub fn is_error(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value] => Bool(error_fields(value).is_ok()),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `error-message :: error -> string`
+///
+/// Produces the message of the specified `error` struct, which is the same as
+/// its description.
+pub fn error_message(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value] => error_fields(value)
+            .map(|fields| fields[1].clone())
+            .unwrap_or_else(|ex| ex),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `coalesce :: a... -> a`
+///
+/// Produces the first of the specified values that is not nil, or nil if all
+/// of them are.
+pub fn coalesce(args: &[Expression], _: &mut Context) -> Expression {
+    args.iter()
+        .find(|ex| !ex.is_nil())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// `default :: a b -> a`
+///
+/// Produces the first of the specified values unless it is nil, in which case
+/// the second is produced.
+pub fn default(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [ex, default] => {
+            if ex.is_nil() {
+                default.clone()
+            } else {
+                ex.clone()
+            }
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `assert-type :: a symbol -> a`
+///
+/// Produces the specified value unchanged if its type, as given by `type-of`,
+/// is the specified type. Otherwise, a signature exception is produced.
+pub fn assert_type(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [ex, Symbol(expected)] => {
+            let found = ex.type_of();
+            if *found == **expected {
+                ex.clone()
+            } else {
+                Error(Rc::new(Exception::signature(
+                    expected.name().clone(),
+                    found,
+                )))
+            }
+        }
+        [_, x] => Error(Rc::new(Exception::signature("symbol", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `struct->list :: struct -> [a]`
+///
+/// Produces a list of the values stored in the specified struct, in the order
+/// of its fields.
+pub fn struct_to_list(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Struct(data)] => Cons(data.data.iter().cloned().collect()),
+        [x] => Error(Rc::new(Exception::signature("struct", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `struct-fields :: struct -> [symbol]`
+///
+/// Produces a list of the field names of the specified struct.
+pub fn struct_fields(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Struct(data)] => match ctx.get_struct_fields(&data.name) {
+            Some(fields) => Cons(
+                fields
+                    .iter()
+                    .map(|field| Symbol(field.clone().into()))
+                    .collect(),
+            ),
+            None => Error(Rc::new(Exception::custom(
+                50,
+                format!("struct `{}` has no known fields", data.name),
+            ))),
+        },
+        [x] => Error(Rc::new(Exception::signature("struct", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// A multiple-value result, as produced by `values`. This is a foreign value,
+/// so that it cannot be mistaken for a struct defined by a program.
+pub struct Values(pub Vec<Expression>);
+
+impl Foreign for Values {
+    fn type_name(&self) -> Str {
+        "values".into()
+    }
+
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign> {
+        // The values are immutable
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// `values :: a... -> values`
+///
+/// Produces the specified values as a single multiple-value result, to be
+/// destructured by `let-values`.
+pub fn values(args: &[Expression], _: &mut Context) -> Expression {
+    Foreign(Rc::new(Values(args.to_vec())))
+}
+
+/// Stores data for splitting an interpolated string into its various parts.
+#[derive(Debug)]
+enum StrSection<'a> {
+    /// A literal string.
+    Literal(&'a str),
+
+    // The contents of an interpolated string to be interpolated.
+    Expr(&'a str),
+}
+
+/// Splits the specified string into a list of its literal and expression
+/// parts.
 fn split_str(s: &str) -> Result<Vec<StrSection>, Exception> {
     use self::StrSection::*;
     let mut strs = Vec::new();
@@ -995,6 +2175,55 @@ pub fn set(args: &[Expression], env: &mut Context) -> Expression {
     }
 }
 
+/// `undefine :: symbol -> nil`
+///
+/// Removes the binding of the specified symbol from the innermost scope that
+/// defines it.
+pub fn undefine(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Symbol(s)] => match ctx.undefine(s) {
+            Some(_) => Expression::default(),
+            None => Error(Rc::new(Exception::undefined(s.clone()))),
+        },
+        [x] => Error(Rc::new(Exception::signature("symbol", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `set-exit-on-error :: bool -> nil`
+///
+/// Sets whether or not an uncaught exception escaping an interactive session
+/// should exit the program with a non-zero exit code. Uncaught exceptions from
+/// loading files always do.
+pub fn set_exit_on_error(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Bool(b)] => {
+            ctx.set_exit_on_error(*b);
+            Expression::default()
+        }
+        [x] => Error(Rc::new(Exception::signature("bool", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `set-zero-division-error :: bool -> nil`
+///
+/// Sets whether or not dividing by zero with `/` throws an exception. If not,
+/// the quotient is infinite, or `NaN` if the dividend is also zero.
+pub fn set_zero_division_error(
+    args: &[Expression],
+    ctx: &mut Context,
+) -> Expression {
+    match args {
+        [Bool(b)] => {
+            ctx.set_zero_division_error(*b);
+            Expression::default()
+        }
+        [x] => Error(Rc::new(Exception::signature("bool", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
 /// `sqrt :: num -> num`
 ///
 /// Produces the square root of the specified number.
@@ -1078,10 +2307,34 @@ pub fn atan2(args: &[Expression], _: &mut Context) -> Expression {
     binary_fn(args, f64::atan2)
 }
 
+/// `pretty-print :: a -> nil`
+/// `pretty-print :: a num -> nil`
+///
+/// Prints the specified value followed by a newline, breaking lists, vectors,
+/// and structs that do not fit within the specified width, or 80 columns,
+/// across multiple indented lines.
+pub fn pretty_print(args: &[Expression], ctx: &mut Context) -> Expression {
+    let res = match args {
+        [expr] => Ok(pretty_format(expr)),
+        [expr, Num(width)] => {
+            count(*width).map(|width| pretty_format_width(expr, width))
+        }
+        [_, other] => {
+            Err(Error(Rc::new(Exception::signature("num", other.type_of()))))
+        }
+        xs => Err(Error(Rc::new(Exception::arity(1, xs.len())))),
+    };
+    match res {
+        Ok(text) => write_output(&format!("{}\n", text), ctx),
+        Err(ex) => ex,
+    }
+}
+
 /// `display-pretty :: symbol symbol str -> nil`
 ///
-/// Prints the specified string using the specified formatting options.
-pub fn display_pretty(args: &[Expression], _: &mut Context) -> Expression {
+/// Prints the specified string using the specified formatting options. The
+/// formatting is only applied when printing directly to `stdout`.
+pub fn display_pretty(args: &[Expression], ctx: &mut Context) -> Expression {
     fn get_style(style: impl AsRef<str>) -> Result<Style, Exception> {
         match style.as_ref() {
             "bold" => Ok(Style::Bold),
@@ -1110,10 +2363,13 @@ pub fn display_pretty(args: &[Expression], _: &mut Context) -> Expression {
     match args {
         [Symbol(color), Symbol(style), Str(text)] => {
             match (get_color(color), get_style(style)) {
-                (Ok(color), Ok(style)) => {
-                    print_pretty(text, color, style);
-                    Expression::default()
-                }
+                (Ok(color), Ok(style)) => match ctx.output() {
+                    Output::Stdout => {
+                        print_pretty(text, color, style);
+                        Expression::default()
+                    }
+                    _ => write_output(text, ctx),
+                },
                 (Err(ex), _) => Error(Rc::new(ex)),
                 (_, Err(ex)) => Error(Rc::new(ex)),
             }
@@ -1175,162 +2431,3003 @@ pub fn env_var(args: &[Expression], _: &mut Context) -> Expression {
     }
 }
 
-pub fn string_concat(args: &[Expression], _: &mut Context) -> Expression {
-    let mut buf = String::new();
+/// A mutable buffer for building strings incrementally.
+pub struct StringBuilder(RefCell<String>);
 
-    for arg in args {
-        buf.push_str(&format!("{}", arg));
+impl Foreign for StringBuilder {
+    fn type_name(&self) -> Str {
+        "string-builder".into()
     }
 
-    Str(buf.into())
-}
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign> {
+        Rc::new(StringBuilder(RefCell::new(self.0.borrow().clone())))
+    }
 
-#[cfg(feature = "native")]
-pub fn random(args: &[Expression], ctx: &mut Context) -> Expression {
-    match args.len() {
-        0 => {
-            let rng = ctx.rng();
-            Num(Rng::gen(rng))
-        }
-        n => Error(Rc::new(Exception::arity(0, n))),
+    fn as_any(&self) -> &dyn Any {
+        self
     }
 }
 
-fn timestamp() -> f64 {
-    let timespec = time::get_time();
-    let mills: f64 =
-        timespec.sec as f64 + (timespec.nsec as f64 / 1000.0 / 1000.0 / 1000.0);
-    mills
+/// Extracts the string builder from the specified expression.
+fn string_builder(expr: &Expression) -> Result<&StringBuilder, Expression> {
+    match expr {
+        Foreign(value) => value.downcast_ref::<StringBuilder>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error(Rc::new(Exception::signature(
+            "string-builder",
+            expr.type_of(),
+        )))
+    })
 }
 
-pub fn time_secs(args: &[Expression], _: &mut Context) -> Expression {
+/// `make-string-builder :: -> string-builder`
+///
+/// Produces a new, empty string builder.
+pub fn make_string_builder(args: &[Expression], _: &mut Context) -> Expression {
     match args.len() {
-        0 => {
-            let time = timestamp();
-            Num(time)
-        }
+        0 => Foreign(Rc::new(StringBuilder(RefCell::new(String::new())))),
         n => Error(Rc::new(Exception::arity(0, n))),
     }
 }
 
-pub fn repeat(args: &[Expression], ctx: &mut Context) -> Expression {
+/// `string-builder-add! :: string-builder a ... -> nil`
+///
+/// Appends the specified values to the specified string builder, formatted as
+/// `display` would format them.
+pub fn string_builder_add(args: &[Expression], _: &mut Context) -> Expression {
     match args {
-        [Num(n), cb @ Callable(_)] => {
-            let n = *n;
-            if n.trunc() == n {
-                let n = n as u32;
-                for _ in 0..n {
-                    let res = cb.call(&ConsList::new(), ctx);
-                    if res.is_exception() {
-                        return res;
-                    }
+        [builder, values @ ..] => match string_builder(builder) {
+            Ok(StringBuilder(buf)) => {
+                let mut buf = buf.borrow_mut();
+                for value in values {
+                    buf.push_str(&value.to_string());
                 }
                 Expression::default()
-            } else {
-                Error(Rc::new(Exception::custom(
-                    100,
-                    "expected integral number",
-                )))
             }
-        }
-        [a, b] => Error(Rc::new(Exception::signature(
-            "(num, procedure)",
-            format!("({}, {})", a.type_of(), b.type_of()),
-        ))),
-        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+            Err(ex) => ex,
+        },
+        [] => Error(Rc::new(Exception::arity(1, 0))),
     }
 }
 
-pub fn print_error(args: &[Expression], _: &mut Context) -> Expression {
+/// `string-builder->string :: string-builder -> string`
+///
+/// Produces the contents of the specified string builder.
+pub fn string_builder_to_string(
+    args: &[Expression],
+    _: &mut Context,
+) -> Expression {
     match args {
-        [Struct(data)] => {
-            let StructData { name, data } = data.as_ref();
-            // Check struct type
-            if name.as_ref() == "error" {
-                // Extract components
-                match &data[..] {
-                    [Num(code), Str(description), Cons(stack)] => {
-                        // Create new exception from components
-                        let code = *code as ErrorCode;
-                        let description = description.clone();
-                        let stack = stack.clone();
-                        let data = ExceptionData::Custom(code, description);
-                        let error = Exception { data, stack };
+        [builder] => match string_builder(builder) {
+            Ok(StringBuilder(buf)) => Str(buf.borrow().as_str().into()),
+            Err(ex) => ex,
+        },
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
 
-                        // Print the exception
-                        print_stack_trace(&error);
+/// A mutable buffer for building lists incrementally, without the quadratic
+/// cost of repeatedly appending to a list.
+pub struct ListBuilder(RefCell<Vec<Expression>>);
 
-                        Expression::default()
-                    }
-                    [a, b, c] => Error(Rc::new(Exception::signature(
-                        "(num, str, cons)",
-                        format!(
-                            "({}, {}, {})",
-                            a.type_of(),
-                            b.type_of(),
-                            c.type_of()
-                        ),
-                    ))),
-                    xs => Error(Rc::new(Exception::arity(3, xs.len()))),
+impl Foreign for ListBuilder {
+    fn type_name(&self) -> Str {
+        "list-builder".into()
+    }
+
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign> {
+        Rc::new(ListBuilder(RefCell::new(self.0.borrow().clone())))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Extracts the list builder from the specified expression.
+fn list_builder(expr: &Expression) -> Result<&ListBuilder, Expression> {
+    match expr {
+        Foreign(value) => value.downcast_ref::<ListBuilder>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error(Rc::new(Exception::signature(
+            "list-builder",
+            expr.type_of(),
+        )))
+    })
+}
+
+/// `make-list-builder :: -> list-builder`
+///
+/// Produces a new, empty list builder.
+pub fn make_list_builder(args: &[Expression], _: &mut Context) -> Expression {
+    match args.len() {
+        0 => Foreign(Rc::new(ListBuilder(RefCell::new(Vec::new())))),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+/// `list-builder-add! :: list-builder a ... -> nil`
+///
+/// Appends the specified values to the end of the specified list builder, in
+/// constant time for each value.
+pub fn list_builder_add(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [builder, values @ ..] => match list_builder(builder) {
+            Ok(ListBuilder(buf)) => {
+                buf.borrow_mut().extend_from_slice(values);
+                Expression::default()
+            }
+            Err(ex) => ex,
+        },
+        [] => Error(Rc::new(Exception::arity(1, 0))),
+    }
+}
+
+/// `list-builder->list :: list-builder -> [a]`
+///
+/// Produces a list of the values added to the specified list builder, in the
+/// order they were added. The builder may continue to be added to.
+pub fn list_builder_to_list(
+    args: &[Expression],
+    _: &mut Context,
+) -> Expression {
+    match args {
+        [builder] => match list_builder(builder) {
+            Ok(ListBuilder(buf)) => Cons(ConsList::from(buf.borrow().clone())),
+            Err(ex) => ex,
+        },
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `box :: a -> box`
+///
+/// Produces a new mutable box holding the specified value.
+pub fn make_box(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value] => Boxed(Rc::new(RefCell::new(value.clone()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `unbox :: box -> a`
+///
+/// Produces the value currently held by the specified box.
+pub fn unbox(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Boxed(value)] => value.borrow().clone(),
+        [other] => Error(Rc::new(Exception::signature("box", other.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `set-box! :: box a -> nil`
+///
+/// Replaces the value held by the specified box with the specified value.
+pub fn set_box(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Boxed(value), new_value] => {
+            *value.borrow_mut() = new_value.clone();
+            Expression::default()
+        }
+        [other, _] => {
+            Error(Rc::new(Exception::signature("box", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `make-counter :: -> (-> num)`
+///
+/// Produces a function of no arguments that increments an internal counter
+/// each time it is called, producing the new count.
+pub fn make_counter(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [] => {
+            let count = Cell::new(0.0);
+            let counter = move |args: &[Expression], _: &mut Context| match args
+            {
+                [] => {
+                    count.set(count.get() + 1.0);
+                    Num(count.get())
                 }
-            } else {
-                Error(Rc::new(Exception::signature("exception", name.clone())))
+                xs => Error(Rc::new(Exception::arity(0, xs.len()))),
+            };
+            Callable(Intrinsic(Rc::new(counter)))
+        }
+        xs => Error(Rc::new(Exception::arity(0, xs.len()))),
+    }
+}
+
+/// `copy :: a -> a`
+///
+/// Produces an independent copy of the specified value. Mutable values, such
+/// as boxes and string builders, are copied wherever they appear within
+/// lists, vectors, structs, and boxes, so that mutating the copy does not
+/// affect the original. Values containing nothing mutable are shared.
+pub fn copy(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value] => copy_mutable(value, &mut HashMap::new())
+            .unwrap_or_else(|| value.clone()),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// Copies the mutable values within the specified value, producing `None` if
+/// it contains none. Copies are recorded by the address of their original,
+/// so that a value appearing several times, such as a box containing itself,
+/// is copied only once.
+fn copy_mutable(
+    value: &Expression,
+    copies: &mut HashMap<usize, Expression>,
+) -> Option<Expression> {
+    match value {
+        Foreign(foreign) => {
+            let key = Rc::as_ptr(foreign) as *const () as usize;
+            let copy = copies
+                .entry(key)
+                .or_insert_with(|| Foreign(foreign.clone().copy()));
+            Some(copy.clone())
+        }
+        Boxed(cell) => {
+            let key = Rc::as_ptr(cell) as usize;
+            if let Some(copy) = copies.get(&key) {
+                return Some(copy.clone());
             }
+            let copy = Rc::new(RefCell::new(Expression::default()));
+            copies.insert(key, Boxed(copy.clone()));
+            let inner = cell.borrow().clone();
+            *copy.borrow_mut() = copy_mutable(&inner, copies).unwrap_or(inner);
+            Some(Boxed(copy))
         }
-        [other] => {
-            Error(Rc::new(Exception::signature("error", other.type_of())))
+        Cons(list) => copy_all(list.iter().map(|x| x.as_ref().clone()), copies)
+            .map(|xs| Cons(xs.into_iter().collect())),
+        Vector(xs) => {
+            copy_all(xs.iter().cloned(), copies).map(|xs| Vector(Rc::new(xs)))
+        }
+        Struct(data) => copy_all(data.data.iter().cloned(), copies).map(|xs| {
+            Struct(Rc::new(StructData {
+                name: data.name.clone(),
+                data: xs,
+            }))
+        }),
+        _ => None,
+    }
+}
+
+/// Copies the mutable values within each of the specified values, producing
+/// `None` if none contain any.
+fn copy_all(
+    values: impl Iterator<Item = Expression>,
+    copies: &mut HashMap<usize, Expression>,
+) -> Option<Vec<Expression>> {
+    let mut copied = false;
+    let values = values
+        .map(|value| match copy_mutable(&value, copies) {
+            Some(copy) => {
+                copied = true;
+                copy
+            }
+            None => value,
+        })
+        .collect();
+    if copied {
+        Some(values)
+    } else {
+        None
+    }
+}
+
+pub fn string_concat(args: &[Expression], _: &mut Context) -> Expression {
+    let mut buf = String::new();
+
+    for arg in args {
+        buf.push_str(&format!("{}", arg));
+    }
+
+    Str(buf.into())
+}
+
+/// `string-replace :: string string string -> string`
+///
+/// Replaces every occurrence of the second string within the first with the
+/// third.
+pub fn string_replace(args: &[Expression], _: &mut Context) -> Expression {
+    replace_with(args, |haystack, from, to| haystack.replace(from, to))
+}
+
+/// `string-replace-first :: string string string -> string`
+///
+/// Replaces the first occurrence of the second string within the first with
+/// the third.
+pub fn string_replace_first(
+    args: &[Expression],
+    _: &mut Context,
+) -> Expression {
+    replace_with(args, |haystack, from, to| haystack.replacen(from, to, 1))
+}
+
+/// Applies the specified replacement to the haystack, pattern, and
+/// replacement strings, rejecting an empty pattern.
+fn replace_with(
+    args: &[Expression],
+    replace: impl Fn(&str, &str, &str) -> String,
+) -> Expression {
+    match args {
+        [Str(_), Str(from), Str(_)] if from.is_empty() => Error(Rc::new(
+            Exception::custom(60, "cannot replace an empty string"),
+        )),
+        [Str(haystack), Str(from), Str(to)] => {
+            Str(replace(haystack, from, to).into())
+        }
+        [x, y, z] => Error(Rc::new(Exception::signature(
+            "(string, string, string)",
+            format!("({}, {}, {})", x.type_of(), y.type_of(), z.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(3, xs.len()))),
+    }
+}
+
+/// `string-trim :: string [string] -> string`
+///
+/// Removes leading and trailing whitespace from the specified string. If a
+/// second string is specified, any of its characters are removed instead.
+pub fn string_trim(args: &[Expression], _: &mut Context) -> Expression {
+    trim(args, TrimSide::Both)
+}
+
+/// `string-trim-start :: string [string] -> string`
+///
+/// Removes leading whitespace from the specified string, or any of the
+/// characters of the second string, if specified.
+pub fn string_trim_start(args: &[Expression], _: &mut Context) -> Expression {
+    trim(args, TrimSide::Start)
+}
+
+/// `string-trim-end :: string [string] -> string`
+///
+/// Removes trailing whitespace from the specified string, or any of the
+/// characters of the second string, if specified.
+pub fn string_trim_end(args: &[Expression], _: &mut Context) -> Expression {
+    trim(args, TrimSide::End)
+}
+
+/// The ends of a string from which characters are trimmed.
+#[derive(Clone, Copy)]
+enum TrimSide {
+    Both,
+    Start,
+    End,
+}
+
+/// Trims the specified side of the string given as the first argument, of
+/// either whitespace or the characters of the string given as the second.
+fn trim(args: &[Expression], side: TrimSide) -> Expression {
+    fn trim_by(s: &str, side: TrimSide, f: impl Fn(char) -> bool) -> &str {
+        match side {
+            TrimSide::Both => s.trim_matches(f),
+            TrimSide::Start => s.trim_start_matches(f),
+            TrimSide::End => s.trim_end_matches(f),
+        }
+    }
+
+    match args {
+        [Str(s)] => Str(trim_by(s, side, char::is_whitespace).into()),
+        [Str(s), Str(chars)] => {
+            Str(trim_by(s, side, |ch| chars.contains(ch)).into())
+        }
+        [Str(_), other] | [other] | [other, _] => {
+            Error(Rc::new(Exception::signature("string", other.type_of())))
         }
         xs => Error(Rc::new(Exception::arity(1, xs.len()))),
     }
 }
 
-pub fn args(args: &[Expression], _: &mut Context) -> Expression {
+/// `regex-match? :: string string -> bool`
+///
+/// Determines whether or not the specified regular expression matches
+/// anywhere within the specified string.
+pub fn regex_is_match(args: &[Expression], _: &mut Context) -> Expression {
+    with_regex(args, |re, text| Bool(re.is_match(text)))
+}
+
+/// `regex-find :: string string -> string | nil`
+///
+/// Produces the first match of the specified regular expression within the
+/// specified string, or `nil` if there is none.
+pub fn regex_find(args: &[Expression], _: &mut Context) -> Expression {
+    with_regex(args, |re, text| match re.find(text) {
+        Some(m) => Str(m.as_str().into()),
+        None => Expression::default(),
+    })
+}
+
+/// `regex-captures :: string string -> [string]`
+///
+/// Produces the first match of the specified regular expression within the
+/// specified string, followed by the text captured by each of its groups.
+/// Groups that did not take part in the match capture the empty string. If
+/// there is no match, the empty list is produced.
+pub fn regex_captures(args: &[Expression], _: &mut Context) -> Expression {
+    with_regex(args, |re, text| match re.captures(text) {
+        Some(caps) => Cons(
+            caps.iter()
+                .map(|m| Str(m.map_or("", |m| m.as_str()).into()))
+                .collect(),
+        ),
+        None => Expression::default(),
+    })
+}
+
+/// Compiles the regular expression given as the first argument, and applies
+/// the specified function to it and the string given as the second.
+fn with_regex(
+    args: &[Expression],
+    f: impl Fn(&Regex, &str) -> Expression,
+) -> Expression {
+    match args {
+        [Str(pattern), Str(text)] => match Regex::new(pattern) {
+            Ok(re) => f(&re, text),
+            Err(err) => Error(Rc::new(Exception::custom(
+                61,
+                format!("invalid regular expression: {}", err),
+            ))),
+        },
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(string, string)",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `fill :: string num -> string`
+///
+/// Repeats the specified pattern until it is exactly the specified number of
+/// characters wide, truncating the final repetition as needed.
+pub fn fill(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(pattern), Num(width)] => {
+            let width = match count(*width) {
+                Ok(width) => width,
+                Err(ex) => return ex,
+            };
+            if pattern.is_empty() && width > 0 {
+                return Error(Rc::new(Exception::custom(
+                    49,
+                    "cannot fill with an empty string",
+                )));
+            }
+            let filled: String = pattern.chars().cycle().take(width).collect();
+            Str(filled.into())
+        }
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(string, num)",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `draw-box :: [string] -> string`
+///
+/// Renders the specified lines inside a box drawn with Unicode box-drawing
+/// characters, padding each line to the width of the widest.
+pub fn draw_box(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Cons(list)] => {
+            let lines: Result<Vec<Str>, Expression> = list
+                .iter()
+                .map(|line| match line.as_ref() {
+                    Str(s) => Ok(s.clone()),
+                    other => Err(Error(Rc::new(Exception::signature(
+                        "string",
+                        other.type_of(),
+                    )))),
+                })
+                .collect();
+            let lines = match lines {
+                Ok(lines) => lines,
+                Err(ex) => return ex,
+            };
+            let width = lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0);
+            let rule: String = "─".repeat(width + 2);
+            let mut buf = format!("┌{}┐\n", rule);
+            for line in lines.iter() {
+                let padding = width - line.chars().count();
+                buf.push_str(&format!("│ {}{} │\n", line, " ".repeat(padding)));
+            }
+            buf.push_str(&format!("└{}┘", rule));
+            Str(buf.into())
+        }
+        [x] => Error(Rc::new(Exception::signature("cons", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `host-call :: string [a] -> a`
+///
+/// Calls the host function registered under the specified name with the
+/// specified arguments, producing its result.
+#[cfg(feature = "wasm")]
+pub fn host_call(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Str(name), Cons(host_args)] => match host::get_host_fn(name) {
+            Some(f) => host::marshal_args(host_args)
+                .and_then(|host_args| {
+                    f(&host_args).map_err(|msg| {
+                        Exception::custom(
+                            54,
+                            format!("host function `{}` failed: {}", name, msg),
+                        )
+                    })
+                })
+                .and_then(|res| host::unmarshal_result(&res))
+                .unwrap_or_else(|ex| Error(Rc::new(ex))),
+            None => Error(Rc::new(Exception::custom(
+                53,
+                format!("no host function named `{}`", name),
+            ))),
+        },
+        [Str(_), other] => {
+            Error(Rc::new(Exception::signature("list", other.type_of())))
+        }
+        [other, _] => {
+            Error(Rc::new(Exception::signature("string", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `intern-stats :: -> [[symbol num]]`
+///
+/// Produces statistics describing the symbol intern table: the number of
+/// interned symbols, their total length in bytes, and the number of live
+/// references to them.
+pub fn intern_stats(args: &[Expression], _: &mut Context) -> Expression {
     match args.len() {
         0 => {
-            let arg_strs: ConsList<Expression> = env::args()
-                .map::<Expression, _>(|arg| arg.as_str().into())
-                .collect();
-            let tail = arg_strs.tail().unwrap_or_default();
-            Cons(tail)
+            let stats = intern::stats();
+            let pairs = vec![
+                ("symbols", stats.symbols),
+                ("bytes", stats.bytes),
+                ("references", stats.references),
+            ];
+            Cons(
+                pairs
+                    .into_iter()
+                    .map(|(name, n)| {
+                        Cons(ConsList::from(vec![
+                            Symbol(name.into()),
+                            Num(n as f64),
+                        ]))
+                    })
+                    .collect(),
+            )
         }
         n => Error(Rc::new(Exception::arity(0, n))),
     }
 }
 
-pub fn floor(args: &[Expression], _: &mut Context) -> Expression {
-    unary_fn(args, f64::floor)
+/// `terminal-size :: -> [num num]`
+///
+/// Produces the number of columns and rows of the terminal attached to
+/// standard output, or 80 columns and 24 rows if there is no terminal.
+#[cfg(feature = "native")]
+pub fn terminal_size(args: &[Expression], _: &mut Context) -> Expression {
+    match args.len() {
+        0 => {
+            let (cols, rows) = terminal_dimensions().unwrap_or((80, 24));
+            Cons(ConsList::from(vec![Num(cols.into()), Num(rows.into())]))
+        }
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
 }
 
-pub fn ceil(args: &[Expression], _: &mut Context) -> Expression {
-    unary_fn(args, f64::ceil)
+/// Queries the dimensions of the terminal attached to standard output as
+/// columns and rows.
+#[cfg(all(feature = "native", unix))]
+fn terminal_dimensions() -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let res = unsafe {
+        libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size)
+    };
+    if res == 0 && size.ws_col > 0 && size.ws_row > 0 {
+        Some((size.ws_col, size.ws_row))
+    } else {
+        None
+    }
 }
 
-// fn convert_zero_arity<T>(
-//     f: impl Fn() -> T,
-// ) -> impl Fn(&[Expression], &mut Context) -> Expression
-// where
-//     T: Into<Expression>,
-// {
-//     move |_, _| f().into()
-// }
+#[cfg(all(feature = "native", not(unix)))]
+fn terminal_dimensions() -> Option<(u16, u16)> {
+    None
+}
 
-pub fn pow(args: &[Expression], _: &mut Context) -> Expression {
-    binary_fn(args, f64::powf)
+#[cfg(feature = "native")]
+pub fn random(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => {
+            let rng = ctx.rng();
+            Num(Rng::gen(rng))
+        }
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
 }
 
-pub fn chars(args: &[Expression], _: &mut Context) -> Expression {
-    fn str_chars(s: &Str) -> Expression {
-        let list: ConsList<Expression> = s
-            .chars()
-            .map(|ch| format!("{}", ch).into())
-            .map(|s| Expression::Str(s))
-            .collect();
-        Expression::Cons(list)
+fn timestamp() -> f64 {
+    let timespec = time::get_time();
+    let mills: f64 =
+        timespec.sec as f64 + (timespec.nsec as f64 / 1000.0 / 1000.0 / 1000.0);
+    mills
+}
+
+pub fn time_secs(args: &[Expression], _: &mut Context) -> Expression {
+    match args.len() {
+        0 => {
+            let time = timestamp();
+            Num(time)
+        }
+        n => Error(Rc::new(Exception::arity(0, n))),
     }
+}
 
+pub fn repeat(args: &[Expression], ctx: &mut Context) -> Expression {
     match args {
-        [Str(s)] => str_chars(s),
-        [x] => Error(Rc::new(Exception::signature("str|symbol", x.type_of()))),
-        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+        [Num(n), cb @ Callable(_)] => {
+            let n = *n;
+            if n.trunc() == n {
+                let n = n as u32;
+                for _ in 0..n {
+                    let res = cb.call(&ConsList::new(), ctx);
+                    if res.is_exception() {
+                        return res;
+                    }
+                }
+                Expression::default()
+            } else {
+                Error(Rc::new(Exception::custom(
+                    100,
+                    "expected integral number",
+                )))
+            }
+        }
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(num, procedure)",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// Calls the specified callable with the specified, already evaluated,
+/// arguments.
+pub(crate) fn call_with(
+    f: &Expression,
+    args: impl IntoIterator<Item = Expression>,
+    ctx: &mut Context,
+) -> Expression {
+    let list: ConsList<_> = Some(f.clone())
+        .into_iter()
+        .chain(args.into_iter().map(quote))
+        .collect();
+    f.call(&list, ctx)
+}
+
+/// Extracts the lists from the specified arguments, producing a signature
+/// exception for the first argument that is not a list.
+fn lists(
+    args: &[Expression],
+) -> Result<Vec<&ConsList<Expression>>, Expression> {
+    args.iter()
+        .map(|arg| match arg {
+            Cons(list) => Ok(list),
+            other => Err(Error(Rc::new(Exception::signature(
+                "cons",
+                other.type_of(),
+            )))),
+        })
+        .collect()
+}
+
+/// `force :: promise -> a`
+///
+/// Produces the value of the specified promise, evaluating its expressions if
+/// it has not yet been forced. The value is cached, so the expressions are
+/// evaluated at most once. Values other than promises are produced unchanged.
+pub fn force(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Promise(promise)] => {
+            let thunk = match &*promise.borrow() {
+                PromiseState::Forced(value) => return value.clone(),
+                PromiseState::Delayed(thunk) => thunk.clone(),
+            };
+            let value = call_with(&thunk, None, ctx);
+            if !value.is_exception() {
+                *promise.borrow_mut() = PromiseState::Forced(value.clone());
+            }
+            value
+        }
+        [value] => value.clone(),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `memoize :: (a... -> b) -> (a... -> b)`
+///
+/// Produces a function that calls the specified function, caching its result
+/// for each list of arguments it is called with. Exceptions are not cached.
+/// Arguments are compared by value, so a box that is mutated after being
+/// passed will not find its earlier result. Arguments that are not equal to
+/// themselves, such as `NaN`, are never cached.
+#[allow(clippy::mutable_key_type)]
+pub fn memoize(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [f] if f.is_callable() => {
+            let f = f.clone();
+            let cache: RefCell<HashMap<Vec<Expression>, Expression>> =
+                RefCell::new(HashMap::new());
+            let memoized = move |args: &[Expression], ctx: &mut Context| {
+                if let Some(res) = cache.borrow().get(args) {
+                    return res.clone();
+                }
+                let res = call_with(&f, args.iter().cloned(), ctx);
+
+                // Arguments that are not equal to themselves would never be
+                // found in the cache
+                #[allow(clippy::eq_op)]
+                let reflexive = args.iter().all(|arg| arg == arg);
+                if reflexive && !res.is_exception() {
+                    cache.borrow_mut().insert(args.to_vec(), res.clone());
+                }
+                res
+            };
+            Callable(Intrinsic(Rc::new(memoized)))
+        }
+        [other] => {
+            Error(Rc::new(Exception::signature("callable", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `partial :: (a... b... -> c) a... -> (b... -> c)`
+///
+/// Produces a function that calls the specified function with the specified
+/// leading arguments, followed by those it is called with. The arguments are
+/// only checked against the function when it is finally called.
+pub fn partial(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [f, leading @ ..] if f.is_callable() => {
+            let f = f.clone();
+            let leading = leading.to_vec();
+            let applied = move |args: &[Expression], ctx: &mut Context| {
+                let args = leading.iter().chain(args.iter()).cloned();
+                call_with(&f, args, ctx)
+            };
+            Callable(Intrinsic(Rc::new(applied)))
+        }
+        [other, ..] => {
+            Error(Rc::new(Exception::signature("callable", other.type_of())))
+        }
+        [] => Error(Rc::new(Exception::arity(1, 0))),
+    }
+}
+
+/// `for-each :: (a... -> b) [a]... -> nil`
+///
+/// Applies the specified function to each element of the specified lists
+/// purely for its side effects. If multiple lists are specified, the function
+/// is called with one element from each, stopping at the end of the shortest
+/// list. Iteration stops at the first exception, which is produced.
+pub fn for_each(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, xs @ ..] if !xs.is_empty() => {
+            let lists = match lists(xs) {
+                Ok(lists) => lists,
+                Err(ex) => return ex,
+            };
+            let len = lists.iter().map(|list| list.len()).min().unwrap_or(0);
+            let mut iters: Vec<_> =
+                lists.iter().map(|list| list.iter()).collect();
+            for _ in 0..len {
+                let args: Vec<_> = iters
+                    .iter_mut()
+                    .filter_map(|iter| iter.next())
+                    .map(|x| x.as_ref().clone())
+                    .collect();
+                let res = call_with(f, args, ctx);
+                if res.is_exception() {
+                    return res;
+                }
+            }
+            Expression::default()
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `flat-map :: (a -> [b]) [a] -> [b]`
+///
+/// Applies the specified function to each element of the specified list,
+/// appending the lists it produces. Unlike `flatten`, only those lists are
+/// spliced, and not any lists nested within them.
+pub fn flat_map(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Cons(list)] => {
+            let mut buf = Vec::new();
+            for x in list.iter() {
+                match call_with(f, Some(x.as_ref().clone()), ctx) {
+                    Cons(ys) => {
+                        buf.extend(ys.iter().map(|y| y.as_ref().clone()))
+                    }
+                    ex @ Error(_) => return ex,
+                    other => {
+                        return Error(Rc::new(Exception::signature(
+                            "cons",
+                            other.type_of(),
+                        )))
+                    }
+                }
+            }
+            Cons(ConsList::from(buf))
+        }
+        [_, other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// Folds the specified elements from last to first, calling the specified
+/// function with each element and the accumulator.
+fn fold_from_end(
+    f: &Expression,
+    acc: Expression,
+    xs: &[Arc<Expression>],
+    ctx: &mut Context,
+) -> Expression {
+    let mut acc = acc;
+    for x in xs.iter().rev() {
+        acc = call_with(f, vec![x.as_ref().clone(), acc], ctx);
+        if acc.is_exception() {
+            break;
+        }
+    }
+    acc
+}
+
+/// `fold-right :: (a b -> b) b [a] -> b`
+///
+/// Combines the elements of the specified list from last to first, calling
+/// the specified function with each element and the result so far, starting
+/// from the specified value. The function takes its arguments in the same
+/// order as for `foldl`, which instead starts from the first element, such
+/// that `(fold-right cons nil xs)` reproduces `xs`. Unlike `foldr`, long lists
+/// do not deepen the stack.
+pub fn fold_right(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, acc, Cons(list)] => {
+            let xs: Vec<_> = list.iter().collect();
+            fold_from_end(f, acc.clone(), &xs, ctx)
+        }
+        [_, _, other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(3, xs.len()))),
+    }
+}
+
+/// `reduce-right :: (a a -> a) [a] -> a`
+///
+/// Combines the elements of the specified non-empty list as `fold-right`
+/// does, starting from its last element.
+pub fn reduce_right(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Cons(list)] => {
+            let xs: Vec<_> = list.iter().collect();
+            match xs.split_last() {
+                Some((last, xs)) => {
+                    fold_from_end(f, last.as_ref().clone(), xs, ctx)
+                }
+                None => Error(Rc::new(Exception::custom(
+                    66,
+                    "cannot reduce an empty list",
+                ))),
+            }
+        }
+        [_, other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// Applies the specified predicate to each element of the specified list,
+/// stopping at the first result equal to `stop`, in which case `stop` is
+/// produced. Otherwise, `!stop` is produced.
+fn short_circuit(
+    args: &[Expression],
+    ctx: &mut Context,
+    stop: bool,
+) -> Expression {
+    match args {
+        [f, Cons(list)] => {
+            for x in list.iter() {
+                match call_with(f, Some(x.as_ref().clone()), ctx) {
+                    Bool(b) if b == stop => return Bool(stop),
+                    Bool(_) => (),
+                    ex @ Error(_) => return ex,
+                    other => {
+                        return Error(Rc::new(Exception::signature(
+                            "bool",
+                            other.type_of(),
+                        )))
+                    }
+                }
+            }
+            Bool(!stop)
+        }
+        [_, x] => Error(Rc::new(Exception::signature("cons", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `any? :: (a -> bool) [a] -> bool`
+///
+/// Determines whether or not the specified predicate holds for any element of
+/// the specified list, stopping at the first element for which it does.
+pub fn any(args: &[Expression], ctx: &mut Context) -> Expression {
+    short_circuit(args, ctx, true)
+}
+
+/// `all? :: (a -> bool) [a] -> bool`
+///
+/// Determines whether or not the specified predicate holds for every element
+/// of the specified list, stopping at the first element for which it does not.
+pub fn all(args: &[Expression], ctx: &mut Context) -> Expression {
+    short_circuit(args, ctx, false)
+}
+
+/// Converts the specified number into a count of list elements, producing an
+/// exception if it is negative or not integral.
+fn count(n: f64) -> Result<usize, Expression> {
+    if n.trunc() != n {
+        Err(Error(Rc::new(Exception::custom(
+            100,
+            "expected integral number",
+        ))))
+    } else if n < 0.0 {
+        Err(Error(Rc::new(Exception::custom(
+            48,
+            format!("expected a non-negative count, found {}", n),
+        ))))
+    } else {
+        Ok(n as usize)
+    }
+}
+
+/// Applies the specified list slicing function to the count and list in the
+/// specified arguments.
+fn slice_fn(
+    args: &[Expression],
+    f: impl Fn(&ConsList<Expression>, usize) -> ConsList<Expression>,
+) -> Expression {
+    match args {
+        [Num(n), Cons(list)] => match count(*n) {
+            Ok(n) => Cons(f(list, n)),
+            Err(ex) => ex,
+        },
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(num, cons)",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `take :: num [a] -> [a]`
+///
+/// Produces the first `n` elements of the specified list, or the whole list
+/// if it has fewer than `n` elements.
+pub fn take(args: &[Expression], _: &mut Context) -> Expression {
+    slice_fn(args, |list, n| {
+        list.iter().take(n).map(|x| x.as_ref().clone()).collect()
+    })
+}
+
+/// `drop :: num [a] -> [a]`
+///
+/// Produces the specified list without its first `n` elements.
+pub fn drop(args: &[Expression], _: &mut Context) -> Expression {
+    slice_fn(args, |list, n| {
+        list.iter().skip(n).map(|x| x.as_ref().clone()).collect()
+    })
+}
+
+/// Finds the number of leading elements of the specified list for which the
+/// predicate holds.
+fn count_while(
+    f: &Expression,
+    list: &ConsList<Expression>,
+    ctx: &mut Context,
+) -> Result<usize, Expression> {
+    let mut n = 0;
+    for x in list.iter() {
+        match call_with(f, Some(x.as_ref().clone()), ctx) {
+            Bool(true) => n += 1,
+            Bool(false) => break,
+            ex @ Error(_) => return Err(ex),
+            other => {
+                return Err(Error(Rc::new(Exception::signature(
+                    "bool",
+                    other.type_of(),
+                ))))
+            }
+        }
+    }
+    Ok(n)
+}
+
+/// `count :: a [a] -> num`
+///
+/// Counts the elements of the specified list that are equal to the specified
+/// value, as by `eq?`.
+pub fn count_equal(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [value, Cons(list)] => {
+            Num(list.iter().filter(|x| x.as_ref() == value).count() as f64)
+        }
+        [_, other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `count-if :: (a -> bool) [a] -> num`
+///
+/// Counts the elements of the specified list for which the specified
+/// predicate holds. The first exception thrown by the predicate is produced
+/// instead.
+pub fn count_if(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Cons(list)] => {
+            let mut n = 0;
+            for x in list.iter() {
+                match call_with(f, Some(x.as_ref().clone()), ctx) {
+                    Bool(true) => n += 1,
+                    Bool(false) => (),
+                    ex @ Error(_) => return ex,
+                    other => {
+                        return Error(Rc::new(Exception::signature(
+                            "bool",
+                            other.type_of(),
+                        )))
+                    }
+                }
+            }
+            Num(n as f64)
+        }
+        [_, other] => {
+            Error(Rc::new(Exception::signature("cons", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `take-while :: (a -> bool) [a] -> [a]`
+///
+/// Produces the leading elements of the specified list for which the
+/// specified predicate holds, stopping at the first element for which it does
+/// not.
+pub fn take_while(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Cons(list)] => match count_while(f, list, ctx) {
+            Ok(n) => take(&[Num(n as f64), Cons(list.clone())], ctx),
+            Err(ex) => ex,
+        },
+        [_, x] => Error(Rc::new(Exception::signature("cons", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `drop-while :: (a -> bool) [a] -> [a]`
+///
+/// Produces the specified list without the leading elements for which the
+/// specified predicate holds.
+pub fn drop_while(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Cons(list)] => match count_while(f, list, ctx) {
+            Ok(n) => drop(&[Num(n as f64), Cons(list.clone())], ctx),
+            Err(ex) => ex,
+        },
+        [_, x] => Error(Rc::new(Exception::signature("cons", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `print-error :: error -> nil`
+///
+/// Prints the specified error, as caught by `try`, to `stderr`, followed by
+/// the frames of its stack trace.
+pub fn print_error(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [Struct(data)] => {
+            let StructData { name, data } = data.as_ref();
+            // Check struct type
+            if name.as_ref() == "error" {
+                // Extract components
+                match &data[..] {
+                    [Num(code), Str(description), Cons(stack)] => {
+                        // Create new exception from components
+                        let code = *code as ErrorCode;
+                        let description = description.clone();
+                        let stack = stack.clone();
+                        let data = ExceptionData::Custom(code, description);
+                        let error = Exception {
+                            data,
+                            stack,
+                            position: None,
+                        };
+
+                        // Print the exception
+                        match ctx.error_output() {
+                            Output::Stderr => write_stack_trace(
+                                &error,
+                                &mut StandardStream::stderr(
+                                    ColorChoice::Always,
+                                ),
+                            ),
+                            output => write_stack_trace(
+                                &error,
+                                &mut NoColor::new(output),
+                            ),
+                        }
+                        .map_err(|_| {
+                            Exception::custom(12, "could not flush stderr")
+                        })
+                        .map(|_| Expression::default())
+                        .unwrap_or_else(|ex| Error(Rc::new(ex)))
+                    }
+                    [a, b, c] => Error(Rc::new(Exception::signature(
+                        "(num, str, cons)",
+                        format!(
+                            "({}, {}, {})",
+                            a.type_of(),
+                            b.type_of(),
+                            c.type_of()
+                        ),
+                    ))),
+                    xs => Error(Rc::new(Exception::arity(3, xs.len()))),
+                }
+            } else {
+                Error(Rc::new(Exception::signature("exception", name.clone())))
+            }
+        }
+        [other] => {
+            Error(Rc::new(Exception::signature("error", other.type_of())))
+        }
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `args :: -> [string]`
+///
+/// Produces the command-line arguments passed to the running script.
+pub fn args(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args.len() {
+        0 => Cons(
+            ctx.program_args()
+                .iter()
+                .map(|arg| Str(arg.clone()))
+                .collect(),
+        ),
+        n => Error(Rc::new(Exception::arity(0, n))),
+    }
+}
+
+pub fn floor(args: &[Expression], _: &mut Context) -> Expression {
+    unary_fn(args, f64::floor)
+}
+
+pub fn ceil(args: &[Expression], _: &mut Context) -> Expression {
+    unary_fn(args, f64::ceil)
+}
+
+/// `round-to :: num num -> num`
+///
+/// Rounds the specified number to the specified number of decimal places,
+/// rounding halfway cases away from zero. This hides the noise left by
+/// floating point arithmetic, such that `(round-to (+ 0.1 0.2) 2)` is `0.3`.
+pub fn round_to(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Num(x), Num(places)] => {
+            let places = match count(*places) {
+                Ok(places) => places,
+                Err(ex) => return ex,
+            };
+            let scale = 10f64.powi(places.min(i32::MAX as usize) as i32);
+            let scaled = x * scale;
+            if scaled.is_finite() {
+                Num(scaled.round() / scale)
+            } else {
+                // Too many places to make a difference
+                Num(*x)
+            }
+        }
+        [a, b] => Error(Rc::new(Exception::signature(
+            "(num, num)",
+            format!("({}, {})", a.type_of(), b.type_of()),
+        ))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `zero? :: num -> bool`
+///
+/// Determines whether or not the specified number is zero.
+pub fn is_zero(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, |x| x == 0.0)
+}
+
+/// `positive? :: num -> bool`
+///
+/// Determines whether or not the specified number is greater than zero.
+pub fn is_positive(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, |x| x > 0.0)
+}
+
+/// `negative? :: num -> bool`
+///
+/// Determines whether or not the specified number is less than zero.
+pub fn is_negative(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, |x| x < 0.0)
+}
+
+/// Determines whether or not the integral number given as the only argument
+/// has the specified remainder when divided by two.
+fn parity(args: &[Expression], remainder: f64) -> Expression {
+    match args {
+        [Num(x)] if x.trunc() == *x => Bool(x.rem_euclid(2.0) == remainder),
+        [Num(_)] => {
+            Error(Rc::new(Exception::custom(100, "expected integral number")))
+        }
+        [value] => Error(Rc::new(Exception::signature("num", value.type_of()))),
+        arr => Error(Rc::new(Exception::arity(1, arr.len()))),
+    }
+}
+
+/// `even? :: num -> bool`
+///
+/// Determines whether or not the specified integral number is even.
+pub fn is_even(args: &[Expression], _: &mut Context) -> Expression {
+    parity(args, 0.0)
+}
+
+/// `odd? :: num -> bool`
+///
+/// Determines whether or not the specified integral number is odd.
+pub fn is_odd(args: &[Expression], _: &mut Context) -> Expression {
+    parity(args, 1.0)
+}
+
+/// `nan? :: num -> bool`
+///
+/// Determines whether or not the specified number is not a number, such as
+/// the product of zero and infinity.
+pub fn is_nan(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, f64::is_nan)
+}
+
+/// `inf? :: num -> bool`
+///
+/// Determines whether or not the specified number is positive or negative
+/// infinity, such as the result of `(pow 10 400)`.
+pub fn is_inf(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, f64::is_infinite)
+}
+
+/// `finite? :: num -> bool`
+///
+/// Determines whether or not the specified number is neither infinite nor
+/// `nan`.
+pub fn is_finite(args: &[Expression], _: &mut Context) -> Expression {
+    predicate_fn(args, f64::is_finite)
+}
+
+// fn convert_zero_arity<T>(
+//     f: impl Fn() -> T,
+// ) -> impl Fn(&[Expression], &mut Context) -> Expression
+// where
+//     T: Into<Expression>,
+// {
+//     move |_, _| f().into()
+// }
+
+pub fn pow(args: &[Expression], _: &mut Context) -> Expression {
+    binary_fn(args, f64::powf)
+}
+
+pub fn chars(args: &[Expression], _: &mut Context) -> Expression {
+    fn str_chars(s: &Str) -> Expression {
+        let list: ConsList<Expression> = s
+            .chars()
+            .map(|ch| format!("{}", ch).into())
+            .map(|s| Expression::Str(s))
+            .collect();
+        Expression::Cons(list)
+    }
+
+    match args {
+        [Str(s)] => str_chars(s),
+        [x] => Error(Rc::new(Exception::signature("str|symbol", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// Extracts the character from the specified string, producing a signature
+/// exception unless it consists of exactly one character.
+fn single_char(expr: &Expression) -> Result<char, Expression> {
+    if let Str(s) = expr {
+        let mut chars = s.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Ok(ch);
+        }
+    }
+    Err(Error(Rc::new(Exception::signature("char", expr.type_of()))))
+}
+
+/// Applies the specified function to the character given as the only
+/// argument.
+fn char_fn(args: &[Expression], f: impl Fn(char) -> Expression) -> Expression {
+    match args {
+        [arg] => single_char(arg).map(f).unwrap_or_else(|ex| ex),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `char-upcase :: char -> string`
+///
+/// Converts the specified character to upper case. Some characters become
+/// more than one character, such that `"ß"` becomes `"SS"`.
+pub fn char_upcase(args: &[Expression], _: &mut Context) -> Expression {
+    char_fn(args, |ch| Str(ch.to_uppercase().collect::<String>().into()))
+}
+
+/// `char-downcase :: char -> string`
+///
+/// Converts the specified character to lower case.
+pub fn char_downcase(args: &[Expression], _: &mut Context) -> Expression {
+    char_fn(args, |ch| Str(ch.to_lowercase().collect::<String>().into()))
+}
+
+/// `char-alphabetic? :: char -> bool`
+///
+/// Determines whether or not the specified character is alphabetic.
+pub fn char_is_alphabetic(args: &[Expression], _: &mut Context) -> Expression {
+    char_fn(args, |ch| Bool(ch.is_alphabetic()))
+}
+
+/// `char-numeric? :: char -> bool`
+///
+/// Determines whether or not the specified character is numeric.
+pub fn char_is_numeric(args: &[Expression], _: &mut Context) -> Expression {
+    char_fn(args, |ch| Bool(ch.is_numeric()))
+}
+
+/// `char-whitespace? :: char -> bool`
+///
+/// Determines whether or not the specified character is whitespace.
+pub fn char_is_whitespace(args: &[Expression], _: &mut Context) -> Expression {
+    char_fn(args, |ch| Bool(ch.is_whitespace()))
+}
+
+/// `list->string :: [string] -> string`
+///
+/// Concatenates the specified list of characters, such as those produced by
+/// `chars`, back into a single string.
+pub fn list_to_string(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Cons(list)] => {
+            let mut buf = String::new();
+            for ch in list.iter() {
+                match ch.as_ref() {
+                    Str(s) => buf.push_str(s),
+                    other => {
+                        return Error(Rc::new(Exception::signature(
+                            "string",
+                            other.type_of(),
+                        )))
+                    }
+                }
+            }
+            Str(buf.into())
+        }
+        [x] => Error(Rc::new(Exception::signature("list", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `list->vector :: [a] -> vector`
+///
+/// Produces a vector containing the elements of the specified list.
+pub fn list_to_vector(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Cons(list)] => {
+            Vector(Rc::new(list.iter().map(|x| x.as_ref().clone()).collect()))
+        }
+        [x] => Error(Rc::new(Exception::signature("list", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `vector->list :: vector -> [a]`
+///
+/// Produces a list containing the elements of the specified vector.
+pub fn vector_to_list(args: &[Expression], _: &mut Context) -> Expression {
+    match args {
+        [Vector(xs)] => Cons(xs.iter().cloned().collect()),
+        [x] => Error(Rc::new(Exception::signature("vector", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(1, xs.len()))),
+    }
+}
+
+/// `vector-map :: (a -> b) vector -> vector`
+///
+/// Applies the specified function to each element of the specified vector in
+/// order, producing a vector of the results. The first exception is produced
+/// instead.
+pub fn vector_map(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Vector(xs)] => {
+            let mut buf = Vec::with_capacity(xs.len());
+            for x in xs.iter() {
+                let res = call_with(f, Some(x.clone()), ctx);
+                if res.is_exception() {
+                    return res;
+                }
+                buf.push(res);
+            }
+            Vector(Rc::new(buf))
+        }
+        [_, x] => Error(Rc::new(Exception::signature("vector", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+/// `vector-for-each :: (a -> b) vector -> nil`
+///
+/// Applies the specified function to each element of the specified vector in
+/// order, purely for its side effects. Iteration stops at the first exception,
+/// which is produced.
+pub fn vector_for_each(args: &[Expression], ctx: &mut Context) -> Expression {
+    match args {
+        [f, Vector(xs)] => {
+            for x in xs.iter() {
+                let res = call_with(f, Some(x.clone()), ctx);
+                if res.is_exception() {
+                    return res;
+                }
+            }
+            Expression::default()
+        }
+        [_, x] => Error(Rc::new(Exception::signature("vector", x.type_of()))),
+        xs => Error(Rc::new(Exception::arity(2, xs.len()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_context, tests::SharedBuffer};
+    use rlisp_interpreter::util::nil;
+
+    /// Parses and evaluates the specified source in the specified context.
+    fn eval_str(src: &str, ctx: &mut Context) -> Expression {
+        Parser::new(src.chars()).parse_all().eval(ctx)
+    }
+
+    /// Produces a fresh, empty directory in the system temp directory.
+    #[cfg(feature = "native")]
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "rlisp-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_list_dir() {
+        let dir = temp_dir("list-dir");
+        File::create(dir.join("b.rl")).unwrap();
+        File::create(dir.join("a.rl")).unwrap();
+        std::fs::create_dir(dir.join("sub")).unwrap();
+        let dir_str = dir.to_string_lossy();
+
+        let mut ctx = init_context("test");
+        let found = eval_str(&format!("(list-dir \"{}\")", dir_str), &mut ctx);
+        let expected = eval_str("'(\"a.rl\" \"b.rl\" \"sub\")", &mut ctx);
+        assert_eq!(found, expected);
+
+        let src = format!("(is-dir? \"{}/sub\")", dir_str);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(true));
+        let src = format!("(is-dir? \"{}/a.rl\")", dir_str);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(false));
+        let src = format!("(file-exists? \"{}/a.rl\")", dir_str);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(true));
+        let src = format!("(file-exists? \"{}/c.rl\")", dir_str);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(false));
+
+        let src = format!("(list-dir \"{}/missing\")", dir_str);
+        assert!(eval_str(&src, &mut ctx).is_exception());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_path_fns() {
+        let mut ctx = init_context("test");
+        let joined = Path::new("lib").join("rlisp").join("stdlib.rl");
+        let found =
+            eval_str("(path-join \"lib\" \"rlisp\" \"stdlib.rl\")", &mut ctx);
+        assert_eq!(found, Str(joined.to_string_lossy().as_ref().into()));
+
+        let path = "/home/user/lib/stdlib.rl";
+        let found =
+            eval_str(&format!("(path-basename \"{}\")", path), &mut ctx);
+        assert_eq!(found, Str("stdlib.rl".into()));
+        let found = eval_str(&format!("(path-dirname \"{}\")", path), &mut ctx);
+        assert_eq!(found, Str("/home/user/lib".into()));
+        let found =
+            eval_str(&format!("(path-extension \"{}\")", path), &mut ctx);
+        assert_eq!(found, Str("rl".into()));
+        let found = eval_str("(path-extension \"Makefile\")", &mut ctx);
+        assert_eq!(found, Str("".into()));
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_import_once() {
+        let dir = temp_dir("import");
+        let file = dir.join("counter.rl");
+        std::fs::write(&file, "(set-internal! 'count (+ count 1))").unwrap();
+
+        let mut ctx = init_context("test");
+        ctx.insert("count", 0);
+        let src = format!("(import \"{}\")", file.to_string_lossy());
+        eval_str(&src, &mut ctx);
+        eval_str(&src, &mut ctx);
+        assert_eq!(ctx.get("count"), Some(&Num(1.0)));
+
+        let src = format!("(force-import \"{}\")", file.to_string_lossy());
+        eval_str(&src, &mut ctx);
+        assert_eq!(ctx.get("count"), Some(&Num(2.0)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_import_cache() {
+        let dir = temp_dir("import-cache");
+        let file = dir.join("counter.rl");
+        std::fs::write(&file, "(set-internal! 'count (+ count 1))").unwrap();
+        let modified = std::fs::metadata(&file).unwrap().modified().unwrap();
+
+        let mut ctx = init_context("test");
+        ctx.insert("count", 0);
+        let src = format!("(force-import \"{}\")", file.to_string_lossy());
+        eval_str(&src, &mut ctx);
+        assert_eq!(ctx.get("count"), Some(&Num(1.0)));
+
+        // While the modification time is unchanged, the file is not read again
+        std::fs::write(&file, "(set-internal! 'count (+ count 10))").unwrap();
+        let set_modified = |time| {
+            let file = std::fs::OpenOptions::new().write(true).open(&file);
+            file.unwrap().set_modified(time).unwrap();
+        };
+        set_modified(modified);
+        eval_str(&src, &mut ctx);
+        assert_eq!(ctx.get("count"), Some(&Num(2.0)));
+
+        set_modified(modified + std::time::Duration::from_secs(1));
+        eval_str(&src, &mut ctx);
+        assert_eq!(ctx.get("count"), Some(&Num(12.0)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_import_errors() {
+        fn import_error(src: &str, ctx: &mut Context) -> Rc<Exception> {
+            match eval_str(src, ctx) {
+                Error(ex) => ex,
+                other => panic!("expected an error, found {}", other),
+            }
+        }
+        fn has_frame(ex: &Exception, text: &str) -> bool {
+            ex.stack()
+                .iter()
+                .any(|frame| frame.to_string().contains(text))
+        }
+
+        let dir = temp_dir("import-errors");
+        let mut ctx = init_context("test");
+
+        let missing = dir.join("missing.rl");
+        let src = format!("(import \"{}\")", missing.to_string_lossy());
+        assert_eq!(import_error(&src, &mut ctx).error_code(), 59);
+
+        let syntax = dir.join("syntax.rl");
+        std::fs::write(&syntax, "(define x 1)\n(define y \"unclosed)").unwrap();
+        let src = format!("(import \"{}\")", syntax.to_string_lossy());
+        let ex = import_error(&src, &mut ctx);
+        assert_eq!(ex.error_code(), 8);
+        assert!(ex.to_string().contains("line 2"));
+        assert!(has_frame(&ex, "<import of"));
+
+        // Nothing in a file with a syntax error is run
+        let trailing = dir.join("trailing.rl");
+        std::fs::write(&trailing, "(define defined-before 1)\n(display")
+            .unwrap();
+        let src = format!("(import \"{}\")", trailing.to_string_lossy());
+        assert_eq!(import_error(&src, &mut ctx).error_code(), 6);
+        assert!(eval_str("defined-before", &mut ctx).is_exception());
+
+        let throws = dir.join("throws.rl");
+        std::fs::write(&throws, "(define x 1)\n(undefined-function x)")
+            .unwrap();
+        let src = format!("(import \"{}\")", throws.to_string_lossy());
+        let ex = import_error(&src, &mut ctx);
+        assert_eq!(ex.error_code(), 1);
+        assert!(has_frame(&ex, "top-level form 2"));
+        assert!(has_frame(&ex, "throws.rl"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_fs_mutation() {
+        let dir = temp_dir("fs-mutation");
+        let nested = dir.join("a").join("b");
+        let mut ctx = init_context("test");
+
+        let src = format!("(make-dir \"{}\")", nested.to_string_lossy());
+        assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+        assert!(nested.is_dir());
+
+        let from = nested.join("from.txt");
+        let to = nested.join("to.txt");
+        std::fs::write(&from, "contents").unwrap();
+        let src = format!(
+            "(rename \"{}\" \"{}\")",
+            from.to_string_lossy(),
+            to.to_string_lossy()
+        );
+        assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+        assert!(!from.exists());
+        assert_eq!(std::fs::read_to_string(&to).unwrap(), "contents");
+
+        let src = format!("(remove-file \"{}\")", to.to_string_lossy());
+        assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+        assert!(!to.exists());
+        assert!(eval_str(&src, &mut ctx).is_exception());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bindings() {
+        let mut ctx = init_context("test");
+        let has_x = |names: Expression| match names {
+            Cons(names) => names.iter().any(|name| *name == Symbol("x".into())),
+            _ => false,
+        };
+        assert!(!has_x(eval_str("(bindings)", &mut ctx)));
+        eval_str("(define x 1)", &mut ctx);
+        assert!(has_x(eval_str("(bindings)", &mut ctx)));
+    }
+
+    #[test]
+    fn test_undefine() {
+        let mut ctx = init_context("test");
+        eval_str("(define x 1)", &mut ctx);
+        assert_eq!(eval_str("x", &mut ctx), Num(1.0));
+        assert_eq!(eval_str("(undefine 'x)", &mut ctx), Expression::default());
+        match eval_str("x", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(undefine 'x)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_undefine_innermost() {
+        let mut ctx = init_context("test");
+        eval_str("(define x 1)", &mut ctx);
+        ctx.ascend_scope();
+        ctx.insert("x", 2.0);
+        assert_eq!(eval_str("(undefine 'x)", &mut ctx), Expression::default());
+        assert_eq!(eval_str("x", &mut ctx), Num(1.0));
+        ctx.descend_scope();
+        assert_eq!(eval_str("x", &mut ctx), Num(1.0));
+    }
+
+    #[test]
+    fn test_error_position() {
+        let mut ctx = init_context("test");
+        let src = "(define x 1)\n\n(define y\n  (+ x\n     undefined-z))\n";
+        let forms = load_file(src.to_string()).unwrap();
+        let ex = match eval_forms(&forms, &mut ctx) {
+            Error(ex) => ex,
+            other => panic!("expected an error, found {}", other),
+        };
+        assert_eq!(ex.error_code(), 1);
+        assert_eq!(ex.position(), Some(Position { line: 5, col: 6 }));
+
+        let mut buf = Vec::new();
+        write_stack_trace(&ex, &mut NoColor::new(&mut buf)).unwrap();
+        let trace = String::from_utf8(buf).unwrap();
+        assert!(
+            trace.starts_with(
+                "error(1): undefined symbol: `undefined-z` at line 5, col 6"
+            ),
+            "{}",
+            trace
+        );
+    }
+
+    #[test]
+    fn test_load_file_vector() {
+        let mut ctx = init_context("test");
+        let forms = load_file("#(1 2)\n#| comment |#\n".to_string()).unwrap();
+        assert_eq!(
+            eval_forms(&forms, &mut ctx),
+            Vector(Rc::new(vec![Num(1.0), Num(2.0)]))
+        );
+        assert!(load_file("#unknown-command\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_check_file() {
+        assert!(check_file("(display 1)\n".to_string()).is_ok());
+        let err = check_file("#!/usr/bin/env rlisp\n(a\n".to_string());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "syntax error: unclosed list at line 2, col 1"
+        );
+    }
+
+    #[test]
+    fn test_unknown_directive() {
+        let err = load_file("(display 1)\n\n  #enable-magic\n".to_string())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "line 3: `#enable-magic` is not a known preprocessor directive; \
+             expected one of: #!<interpreter>, #enable-preprocessor"
+        );
+    }
+
+    #[test]
+    fn test_for_each() {
+        use std::cell::RefCell;
+
+        let mut ctx = init_context("test");
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let record = calls.clone();
+        ctx.insert(
+            "record",
+            Callable(Intrinsic(Rc::new(move |args, _| {
+                record.borrow_mut().push(args.to_vec());
+                Expression::default()
+            }))),
+        );
+
+        let res = eval_str("(for-each record '(1 2 3))", &mut ctx);
+        assert_eq!(res, Expression::default());
+        assert_eq!(
+            *calls.borrow(),
+            vec![vec![Num(1.0)], vec![Num(2.0)], vec![Num(3.0)]]
+        );
+
+        calls.borrow_mut().clear();
+        eval_str("(for-each record '(1 2 3) '(a b))", &mut ctx);
+        assert_eq!(
+            *calls.borrow(),
+            vec![
+                vec![Num(1.0), Symbol("a".into())],
+                vec![Num(2.0), Symbol("b".into())]
+            ]
+        );
+
+        // Iteration stops at the first exception
+        calls.borrow_mut().clear();
+        let res = eval_str(
+            "(for-each (lambda [x] (record (/ 1 x))) '(1 0 2))",
+            &mut ctx,
+        );
+        assert!(res.is_exception());
+        assert_eq!(*calls.borrow(), vec![vec![Num(1.0)]]);
+    }
+
+    #[test]
+    fn test_dump_preprocessed() {
+        let mut ctx = init_context("test");
+        let src = r#"(dump-preprocessed "define : square x\n  * x x\n")"#;
+        assert_eq!(
+            eval_str(src, &mut ctx),
+            Str(" (define ( square x) (* x x))".into())
+        );
+        match eval_str(r#"(dump-preprocessed "a\n \tb")"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 47),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_any_all() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str("(any? (lambda [x] (> x 2)) '(1 2 3))", &mut ctx),
+            Bool(true)
+        );
+        assert_eq!(
+            eval_str("(any? (lambda [x] (> x 3)) '(1 2 3))", &mut ctx),
+            Bool(false)
+        );
+        assert_eq!(
+            eval_str("(all? (lambda [x] (> x 0)) '(1 2 3))", &mut ctx),
+            Bool(true)
+        );
+        assert_eq!(
+            eval_str("(all? (lambda [x] (> x 1)) '(1 2 3))", &mut ctx),
+            Bool(false)
+        );
+        assert_eq!(
+            eval_str("(any? (lambda [x] true) '())", &mut ctx),
+            Bool(false)
+        );
+        assert_eq!(
+            eval_str("(all? (lambda [x] false) '())", &mut ctx),
+            Bool(true)
+        );
+
+        // Evaluation stops before the non-boolean result
+        assert_eq!(
+            eval_str(
+                "(any? (lambda [x] (if (= x 1) true x)) '(1 2))",
+                &mut ctx
+            ),
+            Bool(true)
+        );
+        match eval_str("(all? (lambda [x] x) '(true 2))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_eval_forms() {
+        let mut ctx = init_context("test");
+        let src =
+            "(define x 1)\n(define y 2)\n\n  (undefined-fn x)\n(define z 3)";
+        let forms = load_file(src.to_string()).unwrap();
+        match eval_forms(&forms, &mut ctx) {
+            Error(ex) => {
+                assert_eq!(ex.error_code(), 1);
+                assert_eq!(
+                    ex.stack().head().map(|frame| frame.to_string()),
+                    Some("<top-level form 3 at line 4, col 3>".to_string())
+                );
+            }
+            ex => panic!("expected an error, found {}", ex),
+        }
+
+        // Forms before the failing one have been evaluated, but not after
+        assert_eq!(eval_str("y", &mut ctx), Num(2.0));
+        assert!(eval_str("z", &mut ctx).is_exception());
+
+        // A syntax error rejects the file before any form is evaluated
+        let err = load_file("(define a 1)\n(b".to_string()).unwrap_err();
+        match err.downcast_ref::<Exception>() {
+            Some(ex) => assert_eq!(ex.error_code(), 6),
+            None => panic!("expected a syntax error, found {}", err),
+        }
+    }
+
+    #[test]
+    fn test_take_drop() {
+        let mut ctx = init_context("test");
+        let list = |xs: &[f64]| Cons(xs.iter().map(|&x| Num(x)).collect());
+        assert_eq!(eval_str("(take 2 '(1 2 3))", &mut ctx), list(&[1.0, 2.0]));
+        assert_eq!(
+            eval_str("(take 5 '(1 2 3))", &mut ctx),
+            list(&[1.0, 2.0, 3.0])
+        );
+        assert_eq!(eval_str("(drop 2 '(1 2 3))", &mut ctx), list(&[3.0]));
+        assert_eq!(eval_str("(drop 5 '(1 2 3))", &mut ctx), list(&[]));
+        match eval_str("(take -1 '(1 2 3))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 48),
+            ex => panic!("expected an error, found {}", ex),
+        }
+
+        assert_eq!(
+            eval_str("(take-while (lambda [x] (< x 3)) '(1 2 3 1))", &mut ctx),
+            list(&[1.0, 2.0])
+        );
+        assert_eq!(
+            eval_str("(drop-while (lambda [x] (< x 3)) '(1 2 3 1))", &mut ctx),
+            list(&[3.0, 1.0])
+        );
+
+        // The predicate is not called after the first failing element
+        assert_eq!(
+            eval_str("(take-while (lambda [x] (< x 2)) '(1 2 foo))", &mut ctx),
+            list(&[1.0])
+        );
+    }
+
+    #[test]
+    fn test_assert_type() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(assert-type 1 'num)", &mut ctx), Num(1.0));
+        assert_eq!(
+            eval_str(r#"(assert-type "a" 'string)"#, &mut ctx),
+            Str("a".into())
+        );
+        eval_str("(define-struct point [x y])", &mut ctx);
+        assert_eq!(
+            eval_str(
+                "(type-of (assert-type (make-point 1 2) 'point))",
+                &mut ctx
+            ),
+            Symbol("point".into())
+        );
+        match eval_str("(assert-type 1 'string)", &mut ctx) {
+            Error(ex) => {
+                assert_eq!(ex.error_code(), 9);
+                assert_eq!(
+                    ex.to_string(),
+                    "signature mismatch: expected string, found num"
+                );
+            }
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(coalesce nil 1 2)", &mut ctx), Num(1.0));
+        assert_eq!(eval_str("(coalesce nil '() nil)", &mut ctx), nil());
+        assert_eq!(eval_str("(coalesce)", &mut ctx), nil());
+    }
+
+    #[test]
+    fn test_default() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(default nil 2)", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(default 1 2)", &mut ctx), Num(1.0));
+        assert_eq!(
+            eval_str("(default '(1) 2)", &mut ctx),
+            eval_str("'(1)", &mut ctx)
+        );
+    }
+
+    #[test]
+    fn test_string_replace() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str(r#"(string-replace "a-b-c" "-" ", ")"#, &mut ctx),
+            Str("a, b, c".into())
+        );
+        assert_eq!(
+            eval_str(r#"(string-replace-first "a-b-c" "-" "")"#, &mut ctx),
+            Str("ab-c".into())
+        );
+        assert_eq!(
+            eval_str(r#"(string-replace "abc" "x" "y")"#, &mut ctx),
+            Str("abc".into())
+        );
+        match eval_str(r#"(string-replace "abc" "" "y")"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 60),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str(r#"(string-replace-first "abc" 1 "y")"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_string_trim() {
+        let mut ctx = init_context("test");
+        let padded = r#""\t  two words \n\n""#;
+        let src = format!("(string-trim {})", padded);
+        assert_eq!(eval_str(&src, &mut ctx), Str("two words".into()));
+        let src = format!("(string-trim-start {})", padded);
+        assert_eq!(eval_str(&src, &mut ctx), Str("two words \n\n".into()));
+        let src = format!("(string-trim-end {})", padded);
+        assert_eq!(eval_str(&src, &mut ctx), Str("\t  two words".into()));
+
+        assert_eq!(
+            eval_str(r#"(string-trim "--a-b--" "-")"#, &mut ctx),
+            Str("a-b".into())
+        );
+        assert_eq!(
+            eval_str(r#"(string-trim-end "1.500" "0.")"#, &mut ctx),
+            Str("1.5".into())
+        );
+        assert_eq!(
+            eval_str(r#"(string-trim-start "  x" "")"#, &mut ctx),
+            Str("  x".into())
+        );
+        match eval_str("(string-trim 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str(r#"(string-trim "x" 1)"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_regex() {
+        let mut ctx = init_context("test");
+        let date = r#""([0-9]{4})-([0-9]{2})(-([0-9]{2}))?""#;
+        let src = format!(r#"(regex-match? {} "due 2024-05")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(true));
+        let src = format!(r#"(regex-match? {} "no date")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx), Bool(false));
+
+        let src = format!(r#"(regex-find {} "due 2024-05-17!")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx), Str("2024-05-17".into()));
+        let src = format!(r#"(regex-find {} "no date")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+
+        let src = format!(r#"(regex-captures {} "due 2024-05")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx).to_string(), "(2024-05 2024 05  )");
+        let src = format!(r#"(regex-captures {} "no date")"#, date);
+        assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+
+        match eval_str(r#"(regex-match? "(unclosed" "x")"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 61),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_char_functions() {
+        let mut ctx = init_context("test");
+        let mut eval = |src: &str| eval_str(src, &mut ctx);
+        assert_eq!(eval(r#"(char-upcase "a")"#), Str("A".into()));
+        assert_eq!(eval(r#"(char-upcase "é")"#), Str("É".into()));
+        assert_eq!(eval(r#"(char-upcase "ß")"#), Str("SS".into()));
+        assert_eq!(eval(r#"(char-upcase "1")"#), Str("1".into()));
+        assert_eq!(eval(r#"(char-downcase "Q")"#), Str("q".into()));
+        assert_eq!(eval(r#"(char-downcase "Ж")"#), Str("ж".into()));
+
+        assert_eq!(eval(r#"(char-alphabetic? "z")"#), Bool(true));
+        assert_eq!(eval(r#"(char-alphabetic? "λ")"#), Bool(true));
+        assert_eq!(eval(r#"(char-alphabetic? "7")"#), Bool(false));
+        assert_eq!(eval(r#"(char-numeric? "7")"#), Bool(true));
+        assert_eq!(eval(r#"(char-numeric? "٣")"#), Bool(true));
+        assert_eq!(eval(r#"(char-numeric? "x")"#), Bool(false));
+        assert_eq!(eval(r#"(char-whitespace? "\t")"#), Bool(true));
+        assert_eq!(eval("(char-whitespace? \"\u{3000}\")"), Bool(true));
+        assert_eq!(eval(r#"(char-whitespace? "_")"#), Bool(false));
+
+        for src in &[r#"(char-upcase "ab")"#, r#"(char-numeric? "")"#] {
+            match eval(src) {
+                Error(ex) => assert_eq!(ex.error_code(), 9),
+                ex => panic!("expected an error, found {}", ex),
+            }
+        }
+        match eval("(char-whitespace? 1)") {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_list_to_string() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str(r#"(list->string (chars "hello"))"#, &mut ctx),
+            Str("hello".into())
+        );
+        assert_eq!(eval_str("(list->string '())", &mut ctx), Str("".into()));
+        match eval_str(r#"(list->string '("a" 1))"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_round_to() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(round-to 0.1 2)", &mut ctx), Num(0.1));
+        assert_eq!(eval_str("(round-to (+ 0.1 0.2) 2)", &mut ctx), Num(0.3));
+        assert_eq!(eval_str("(round-to 2.5 0)", &mut ctx), Num(3.0));
+        assert_eq!(eval_str("(round-to -1.25 1)", &mut ctx), Num(-1.3));
+        assert_eq!(eval_str("(round-to 1.5 400)", &mut ctx), Num(1.5));
+        match eval_str("(round-to 1.5 -1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 48),
+            ex => panic!("expected an error, found {}", ex),
+        }
+
+        // Integral values are already displayed without a fractional part
+        assert_eq!(eval_str("(round-to 2.9999 2)", &mut ctx).to_string(), "3");
+        assert_eq!(
+            eval_str("(+ 0.1 0.2)", &mut ctx).to_string(),
+            "0.30000000000000004"
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str(r#"(fill "-=" 6)"#, &mut ctx),
+            Str("-=-=-=".into())
+        );
+        assert_eq!(
+            eval_str(r#"(fill "abc" 7)"#, &mut ctx),
+            Str("abcabca".into())
+        );
+        assert_eq!(eval_str(r#"(fill "─" 3)"#, &mut ctx), Str("───".into()));
+        assert_eq!(eval_str(r#"(fill "" 0)"#, &mut ctx), Str("".into()));
+        match eval_str(r#"(fill "" 2)"#, &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 49),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_draw_box() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str(r#"(draw-box '("hello" "hi"))"#, &mut ctx),
+            Str("┌───────┐\n│ hello │\n│ hi    │\n└───────┘".into())
+        );
+        assert_eq!(
+            eval_str("(draw-box '())", &mut ctx),
+            Str("┌──┐\n└──┘".into())
+        );
+    }
+
+    #[test]
+    fn test_struct_reflection() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        assert_eq!(
+            eval_str("(struct->list (make-point 3 4))", &mut ctx),
+            Cons(ConsList::from(vec![Num(3.0), Num(4.0)]))
+        );
+        assert_eq!(
+            eval_str("(struct-fields (make-point 3 4))", &mut ctx),
+            Cons(ConsList::from(vec![Symbol("x".into()), Symbol("y".into())]))
+        );
+        match eval_str("(struct->list '(3 4))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_terminal_size() {
+        let mut ctx = init_context("test");
+        match eval_str("(terminal-size)", &mut ctx) {
+            Cons(list) => {
+                assert_eq!(list.len(), 2);
+                for n in list.iter() {
+                    match n.as_ref() {
+                        Num(n) => assert!(*n > 0.0),
+                        other => panic!("expected a number, found {}", other),
+                    }
+                }
+            }
+            ex => panic!("expected a list, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_args() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(args)", &mut ctx).to_string(), "()");
+
+        ctx.set_program_args(vec!["first".into(), "second arg".into()]);
+        let expected: ConsList<Expression> =
+            vec![Str("first".into()), Str("second arg".into())]
+                .into_iter()
+                .collect();
+        assert_eq!(eval_str("(args)", &mut ctx), Cons(expected));
+        match eval_str("(args 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 4),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_cursor_escapes() {
+        assert_eq!(clear_screen_escape(), "\x1b[2J\x1b[H");
+        assert_eq!(move_cursor_escape(0, 0), "\x1b[1;1H");
+        assert_eq!(move_cursor_escape(10, 4), "\x1b[5;11H");
+        assert_eq!(cursor_visibility_escape(false), "\x1b[?25l");
+        assert_eq!(cursor_visibility_escape(true), "\x1b[?25h");
+
+        let mut ctx = init_context("test");
+        match eval_str("(move-cursor -1 0)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 48),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(move-cursor 1 \"a\")", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_read_char() {
+        let mut ctx = init_context("test");
+        ctx.set_input(io::Cursor::new("aλ\nrest of line\n"));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("a".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("λ".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("\n".into()));
+        assert_eq!(
+            eval_str("(readline)", &mut ctx),
+            Str("rest of line".into())
+        );
+        assert_eq!(eval_str("(read-char)", &mut ctx), nil());
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_peek_char() {
+        let mut ctx = init_context("test");
+        ctx.set_input(io::Cursor::new("λx"));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("λ".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("λ".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("λ".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("x".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("x".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), nil());
+        assert_eq!(eval_str("(read-char)", &mut ctx), nil());
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_peek_char_split() {
+        // Each read produces a single byte, so no character is ever wholly
+        // within the buffer of the reader
+        let mut ctx = init_context("test");
+        let input = io::Cursor::new("é€\nλ\nrest\n");
+        ctx.set_input(io::BufReader::with_capacity(1, input));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("é".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("é".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("€".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("€".into()));
+        assert_eq!(eval_str("(read-char)", &mut ctx), Str("λ".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), Str("\n".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("rest".into()));
+        assert_eq!(eval_str("(peek-char)", &mut ctx), nil());
+        assert_eq!(eval_str("(readline)", &mut ctx), nil());
+    }
+
+    #[test]
+    fn test_intern_stats() {
+        let mut ctx = init_context("test");
+        let count = |ctx: &mut Context| match eval_str("(intern-stats)", ctx) {
+            Cons(stats) => match stats.head().unwrap().as_ref() {
+                Cons(pair) => {
+                    assert_eq!(pair.head().unwrap().to_string(), "symbols");
+                    match pair.tail().unwrap().head().unwrap().as_ref() {
+                        Num(n) => *n,
+                        other => panic!("expected a number, found {}", other),
+                    }
+                }
+                other => panic!("expected a list, found {}", other),
+            },
+            other => panic!("expected a list, found {}", other),
+        };
+        // The first call interns the names of the statistics themselves
+        count(&mut ctx);
+        let before = count(&mut ctx);
+        eval_str("(quote (fresh-symbol-1 fresh-symbol-2))", &mut ctx);
+        assert_eq!(count(&mut ctx), before + 2.0);
+    }
+
+    #[test]
+    fn test_symbol_loop_interns_nothing() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define (count-down n)
+                (if (= n 0) (quote done) (count-down (- n 1))))",
+            &mut ctx,
+        );
+        eval_str("(count-down 1)", &mut ctx);
+
+        // Symbols are looked up by ID, so a loop interns no new symbol names.
+        // That their names are not copied either is tested by counting
+        // allocations in `tests/allocations.rs`
+        let before = intern::stats();
+        assert_eq!(eval_str("(count-down 50)", &mut ctx).to_string(), "done");
+        let after = intern::stats();
+        assert_eq!(after.symbols, before.symbols);
+        assert_eq!(after.bytes, before.bytes);
+    }
+
+    #[test]
+    fn test_pretty_format() {
+        let mut ctx = init_context("test");
+        let expr = eval_str(
+            "(quote (define (f x) (if (eq? x 0) (quote done) (f (- x 1)))))",
+            &mut ctx,
+        );
+        assert_eq!(pretty_format(&expr), expr.to_string());
+        assert_eq!(
+            pretty_format_width(&expr, 30),
+            "(define (f x)\n  (if (eq? x 0)\n    'done\n    (f (- x 1))))"
+        );
+
+        eval_str("(define-struct point [x y])", &mut ctx);
+        let point = eval_str("(make-point (quote (1 2 3)) 4)", &mut ctx);
+        assert_eq!(
+            pretty_format_width(&point, 12),
+            "(make-point\n  (1 2 3)\n  4)"
+        );
+    }
+
+    #[test]
+    fn test_partial() {
+        let mut ctx = init_context("test");
+        eval_str("(define add-10 (partial + 10))", &mut ctx);
+        assert_eq!(eval_str("(add-10 5)", &mut ctx), Num(15.0));
+        assert_eq!(eval_str("(add-10 1 2)", &mut ctx), Num(13.0));
+
+        eval_str("(define (scale factor x) (* factor x))", &mut ctx);
+        eval_str("(define double (partial scale 2))", &mut ctx);
+        assert_eq!(eval_str("(double 21)", &mut ctx), Num(42.0));
+
+        // Arity is only checked when the function is finally called
+        eval_str("(define too-many (partial scale 1 2 3))", &mut ctx);
+        match eval_str("(too-many)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 4),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(partial 1 2)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_assoc() {
+        let mut ctx = init_context("test");
+        eval_str(r#"(define colors '((red "f00") (green "0f0")))"#, &mut ctx);
+        assert_eq!(
+            eval_str("(assoc 'green colors)", &mut ctx).to_string(),
+            "(green 0f0)"
+        );
+        assert_eq!(
+            eval_str("(assoc 'blue colors)", &mut ctx),
+            Expression::default()
+        );
+        assert_eq!(
+            eval_str("(assq 'red colors)", &mut ctx).to_string(),
+            "(red f00)"
+        );
+        assert_eq!(
+            eval_str("(assoc '(1 2) '(((1 2) pair) (3 num)))", &mut ctx)
+                .to_string(),
+            "((1 2) pair)"
+        );
+        match eval_str("(assoc 'b '((a 1) b (c 3)))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 62),
+            ex => panic!("expected an error, found {}", ex),
+        }
+
+        eval_str("(define color-map (alist->map colors))", &mut ctx);
+        assert_eq!(eval_str("(color-map 'red)", &mut ctx), Str("f00".into()));
+        assert_eq!(
+            eval_str("(color-map 'blue)", &mut ctx),
+            Expression::default()
+        );
+        match eval_str("(alist->map '((a 1) (b)))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 62),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_flatten() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str("(flatten '((1 2) (3 (4))))", &mut ctx).to_string(),
+            "(1 2 3 4)"
+        );
+        assert_eq!(
+            eval_str("(flatten '(1 () ((2)) \"three\"))", &mut ctx).to_string(),
+            "(1 2 three)"
+        );
+        assert_eq!(
+            eval_str(
+                "(flat-map (lambda (x) (cons x (cons (cons x '()) '())))
+                           '(1 2))",
+                &mut ctx
+            )
+            .to_string(),
+            "(1 (1) 2 (2))"
+        );
+        match eval_str("(flat-map (lambda (x) x) '(1 2))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_equal() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str(
+            "(define (nested x)
+                (cons (make-point x (cons (quat 1 2 3 4) '()))
+                      (cons (box (quat 0 1 0 0)) '())))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(equal? (nested (quat 1 0 0 0)) (nested 1))", &mut ctx),
+            Bool(true)
+        );
+        assert_eq!(
+            eval_str("(equal? (nested 1) (nested (quat 1 0 0 1)))", &mut ctx),
+            Bool(false)
+        );
+        assert_eq!(
+            eval_str(
+                "(equal? (make-point (quat 1 2 3 4) 0)
+                         (make-point (quat 1 2 3 5) 0))",
+                &mut ctx
+            ),
+            Bool(false)
+        );
+        assert_eq!(eval_str("(equal? '(1 (2)) '(1 2))", &mut ctx), Bool(false));
+
+        match eval_str("(equal? 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 4),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_num_predicates() {
+        let mut ctx = init_context("test");
+        let cases = [
+            ("(zero? 0)", true),
+            ("(zero? -0)", true),
+            ("(zero? 0.5)", false),
+            ("(positive? 2)", true),
+            ("(positive? 0)", false),
+            ("(negative? -2)", true),
+            ("(negative? 0)", false),
+            ("(even? 4)", true),
+            ("(even? -4)", true),
+            ("(even? 3)", false),
+            ("(odd? 3)", true),
+            ("(odd? -3)", true),
+            ("(odd? 0)", false),
+        ];
+        for (code, expected) in cases.iter() {
+            assert_eq!(eval_str(code, &mut ctx), Bool(*expected), "{}", code);
+        }
+
+        match eval_str("(even? 1.5)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 100),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(zero? \"0\")", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_float_predicates() {
+        let mut ctx = init_context("test");
+        let cases = [
+            ("(inf? (pow 10 400))", true),
+            ("(inf? (- (/ 0)))", true),
+            ("(inf? (* 0 (/ 0)))", false),
+            ("(inf? 1)", false),
+            ("(nan? (* 0 (/ 0)))", true),
+            ("(nan? (pow 10 400))", false),
+            ("(finite? 1.5)", true),
+            ("(finite? (* 1e308 10))", false),
+            ("(finite? (* 0 (/ 0)))", false),
+        ];
+        for (code, expected) in cases.iter() {
+            assert_eq!(eval_str(code, &mut ctx), Bool(*expected), "{}", code);
+        }
+
+        // Dividing by zero throws unless disabled
+        match eval_str("(/ 1.0 0.0)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 38),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        eval_str("(set-zero-division-error false)", &mut ctx);
+        assert_eq!(eval_str("(inf? (/ 1.0 0.0))", &mut ctx), Bool(true));
+        assert_eq!(eval_str("(inf? (/ -1.0 0.0))", &mut ctx), Bool(true));
+        assert_eq!(eval_str("(nan? (/ 0.0 0.0))", &mut ctx), Bool(true));
+    }
+
+    #[test]
+    fn test_vector_conversions() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str("(vector->list (list->vector '(1 2 3)))", &mut ctx),
+            eval_str("'(1 2 3)", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(list->vector '())", &mut ctx),
+            Vector(Rc::new(vec![]))
+        );
+        assert_eq!(
+            eval_str("(vector-map (lambda (x) (* x 2)) #(1 2 3))", &mut ctx),
+            Vector(Rc::new(vec![Num(2.0), Num(4.0), Num(6.0)]))
+        );
+
+        eval_str("(define seen (box '()))", &mut ctx);
+        eval_str(
+            "(vector-for-each
+                (lambda (x) (set-box! seen (cons x (unbox seen))))
+                #(1 2 3))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(unbox seen)", &mut ctx),
+            eval_str("'(3 2 1)", &mut ctx)
+        );
+
+        for code in [
+            "(list->vector #(1))",
+            "(vector->list '(1))",
+            "(vector-map head '(1))",
+        ]
+        .iter()
+        {
+            match eval_str(code, &mut ctx) {
+                Error(ex) => assert_eq!(ex.error_code(), 9, "{}", code),
+                ex => panic!("expected an error, found {}", ex),
+            }
+        }
+        assert!(eval_str("(vector-map head #(1))", &mut ctx).is_exception());
+    }
+
+    #[test]
+    fn test_gensym() {
+        let mut ctx = init_context("test");
+        let first = eval_str("(gensym)", &mut ctx);
+        let second = eval_str("(gensym)", &mut ctx);
+        assert_eq!(first, Symbol("g__0".into()));
+        assert_eq!(second, Symbol("g__1".into()));
+
+        // The counter is shared by every scope in the context
+        assert_eq!(
+            eval_str("((lambda () (gensym)))", &mut ctx),
+            Symbol("g__2".into())
+        );
+        assert_eq!(eval_str("(eq? (gensym) (gensym))", &mut ctx), Bool(false));
+    }
+
+    #[test]
+    fn test_pattern_match() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define bindings (pattern-match '(foo a b) '(foo 1 2) '(foo)))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("bindings", &mut ctx),
+            eval_str("'((a 1) (b 2))", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(substitute '(foo b a) bindings)", &mut ctx),
+            eval_str("'(foo 2 1)", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(pattern-match '(foo a b) '(bar 1 2) '(foo))", &mut ctx),
+            Expression::default()
+        );
+
+        // Without literals, every symbol in the pattern is bound
+        assert_eq!(
+            eval_str("(pattern-match '(f (x _)) '(+ (1 2)))", &mut ctx),
+            eval_str("'((f +) (x 1))", &mut ctx)
+        );
+
+        match eval_str("(substitute 'a '((1 2)))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(substitute 'a '(1))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 62),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_list_builder() {
+        let mut ctx = init_context("test");
+        eval_str("(define builder (make-list-builder))", &mut ctx);
+        eval_str(
+            "(do ([i 0 (+ i 1)]) ({i = 1000}) (list-builder-add! builder i))",
+            &mut ctx,
+        );
+        let list = match eval_str("(list-builder->list builder)", &mut ctx) {
+            Cons(list) => list,
+            other => panic!("expected a list, found {}", other),
+        };
+        assert_eq!(list.len(), 1000);
+        for (i, x) in list.iter().enumerate() {
+            assert_eq!(*x, Num(i as f64));
+        }
+
+        // Several values may be added at once, after finalizing
+        eval_str("(list-builder-add! builder 'a 'b)", &mut ctx);
+        assert_eq!(
+            eval_str("(drop 999 (list-builder->list builder))", &mut ctx)
+                .to_string(),
+            "(999 a b)"
+        );
+        assert_eq!(
+            eval_str("(type-of builder)", &mut ctx),
+            Symbol("list-builder".into())
+        );
+        match eval_str("(list-builder-add! '() 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_fold_right() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str("(fold-right cons nil '(1 2 3))", &mut ctx),
+            eval_str("'(1 2 3)", &mut ctx)
+        );
+        assert_eq!(eval_str("(fold-right - 0 '(1 2 3))", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(fold-right + 5 '())", &mut ctx), Num(5.0));
+        assert_eq!(eval_str("(reduce-right - '(1 2 3))", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(reduce-right + '(4))", &mut ctx), Num(4.0));
+
+        match eval_str("(reduce-right + '())", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 66),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        assert!(eval_str("(fold-right head 0 '(1))", &mut ctx).is_exception());
+    }
+
+    #[test]
+    fn test_count() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define xs
+                (do ([i 9 (- i 1)] [xs '() (cons i xs)]) ({i < 0} xs)))",
+            &mut ctx,
+        );
+        assert_eq!(eval_str("(count-if even? xs)", &mut ctx), Num(5.0));
+        assert_eq!(eval_str("(count-if odd? '())", &mut ctx), Num(0.0));
+        assert_eq!(eval_str("(count 3 xs)", &mut ctx), Num(1.0));
+        assert_eq!(
+            eval_str("(count \"a\" (chars \"banana\"))", &mut ctx),
+            Num(3.0)
+        );
+
+        match eval_str("(count-if (lambda (x) x) xs)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(count-if even? '(1 1.5))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 100),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_memoize() {
+        let mut ctx = init_context("test");
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        crate::define_intrinsic(&mut ctx, "count-call", move |_, _| {
+            counter.set(counter.get() + 1);
+            Expression::default()
+        });
+        eval_str(
+            "(define fib (memoize (lambda (n)
+                (count-call)
+                (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2)))))))",
+            &mut ctx,
+        );
+        assert_eq!(eval_str("(fib 60)", &mut ctx), Num(1548008755920.0));
+        assert_eq!(calls.get(), 61);
+
+        // Cached results are reused
+        assert_eq!(eval_str("(fib 30)", &mut ctx), Num(832040.0));
+        assert_eq!(calls.get(), 61);
+
+        // Arguments containing NaN would never be found, so are not cached
+        let nan = Rc::new(StructData {
+            name: "point".into(),
+            data: vec![Num(f64::NAN)],
+        });
+        ctx.insert("nan-point", Struct(nan.clone()));
+        eval_str("(define id (memoize (lambda (x) x)))", &mut ctx);
+        eval_str("(id nan-point)", &mut ctx);
+        eval_str("(id nan-point)", &mut ctx);
+        assert_eq!(Rc::strong_count(&nan), 2);
+
+        match eval_str("(memoize 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug")]
+    fn test_rc_count() {
+        let mut ctx = init_context("test");
+        eval_str("(define v #(1 2 3))", &mut ctx);
+        let count = |ctx: &mut Context| match eval_str("(rc-count v)", ctx) {
+            Num(n) => n,
+            other => panic!("expected a number, found {}", other),
+        };
+        let before = count(&mut ctx);
+        eval_str("(define w v)", &mut ctx);
+        assert_eq!(count(&mut ctx), before + 1.0);
+
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str("(define p (make-point 1 2))", &mut ctx);
+        eval_str("(define q p)", &mut ctx);
+        assert_eq!(eval_str("(rc-count p)", &mut ctx), Num(3.0));
+        assert_eq!(eval_str("(rc-count 5)", &mut ctx), Num(1.0));
+
+        // The sharing of lists is not observable, so they always produce 1
+        eval_str("(define xs (cons 1 (cons 2 nil)))", &mut ctx);
+        eval_str("(define ys xs)", &mut ctx);
+        assert_eq!(eval_str("(rc-count xs)", &mut ctx), Num(1.0));
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut ctx = init_context("test");
+        eval_str("(define a (make-string-builder))", &mut ctx);
+        eval_str("(string-builder-add! a \"hello\")", &mut ctx);
+        eval_str("(define b (copy a))", &mut ctx);
+        eval_str("(string-builder-add! b \", world\" 1)", &mut ctx);
+        eval_str("(string-builder-add! a \"!\")", &mut ctx);
+        assert_eq!(
+            eval_str("(string-builder->string a)", &mut ctx),
+            Str("hello!".into())
+        );
+        assert_eq!(
+            eval_str("(string-builder->string b)", &mut ctx),
+            Str("hello, world1".into())
+        );
+        assert_eq!(
+            eval_str("(type-of b)", &mut ctx),
+            Symbol("string-builder".into())
+        );
+        assert_eq!(eval_str("(eq? a a)", &mut ctx), Bool(true));
+        assert_eq!(eval_str("(eq? a b)", &mut ctx), Bool(false));
+
+        // Mutable values are copied within lists, structs, and boxes
+        eval_str("(define xs (cons a (cons 1 nil)))", &mut ctx);
+        eval_str("(define ys (copy xs))", &mut ctx);
+        eval_str("(string-builder-add! (head ys) \"?\")", &mut ctx);
+        assert_eq!(
+            eval_str("(string-builder->string a)", &mut ctx),
+            Str("hello!".into())
+        );
+        assert_eq!(
+            eval_str("(string-builder->string (head ys))", &mut ctx),
+            Str("hello!?".into())
+        );
+        eval_str("(define-struct holder [builder])", &mut ctx);
+        eval_str("(define h (copy (make-holder a)))", &mut ctx);
+        eval_str("(string-builder-add! (holder-builder h) \"?\")", &mut ctx);
+        assert_eq!(
+            eval_str("(string-builder->string a)", &mut ctx),
+            Str("hello!".into())
+        );
+        eval_str("(define outer (box (box 1)))", &mut ctx);
+        eval_str("(define outer-copy (copy outer))", &mut ctx);
+        eval_str("(set-box! (unbox outer-copy) 2)", &mut ctx);
+        assert_eq!(eval_str("(unbox (unbox outer))", &mut ctx), Num(1.0));
+
+        // A box containing itself is copied as a new box containing itself
+        eval_str("(define cycle (box 1))", &mut ctx);
+        eval_str("(set-box! cycle cycle)", &mut ctx);
+        eval_str("(define cycle-copy (copy cycle))", &mut ctx);
+        assert_eq!(
+            eval_str("(eq? (unbox cycle-copy) cycle-copy)", &mut ctx),
+            Bool(true)
+        );
+        eval_str("(set-box! cycle-copy 2)", &mut ctx);
+        assert_eq!(
+            eval_str("(type-of (unbox cycle))", &mut ctx),
+            Symbol("box".into())
+        );
+
+        // Immutable values are copied as themselves
+        assert_eq!(
+            eval_str("(copy (quote (1 2)))", &mut ctx).to_string(),
+            "(1 2)"
+        );
+        match eval_str("(string-builder->string 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_box() {
+        let mut ctx = init_context("test");
+        eval_str("(define counter (box 0))", &mut ctx);
+        eval_str(
+            "(define (increment b) (set-box! b (+ (unbox b) 1)))",
+            &mut ctx,
+        );
+        eval_str("(increment counter)", &mut ctx);
+        eval_str("(increment counter)", &mut ctx);
+        assert_eq!(eval_str("(unbox counter)", &mut ctx), Num(2.0));
+        assert_eq!(
+            eval_str("(type-of counter)", &mut ctx),
+            Symbol("box".into())
+        );
+
+        // Copies of a box are independent
+        eval_str("(define other (copy counter))", &mut ctx);
+        eval_str("(increment other)", &mut ctx);
+        assert_eq!(eval_str("(unbox counter)", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(unbox other)", &mut ctx), Num(3.0));
+
+        match eval_str("(unbox 1)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_box_shared() {
+        let mut ctx = init_context("test");
+        eval_str("(define shared (box 1))", &mut ctx);
+        eval_str(
+            "(define (make-setter b) (lambda (x) (set-box! b x)))",
+            &mut ctx,
+        );
+        eval_str("(define (make-getter b) (lambda () (unbox b)))", &mut ctx);
+        eval_str("(define set (make-setter shared))", &mut ctx);
+        eval_str("(define get (make-getter shared))", &mut ctx);
+        assert_eq!(eval_str("(set 5)", &mut ctx).to_string(), "()");
+        assert_eq!(eval_str("(get)", &mut ctx), Num(5.0));
+        assert_eq!(eval_str("(unbox shared)", &mut ctx), Num(5.0));
+
+        // Boxes are equal when their contents are equal
+        assert_eq!(eval_str("(eq? shared (box 5))", &mut ctx), Bool(true));
+        assert_eq!(eval_str("(eq? shared (box 6))", &mut ctx), Bool(false));
+        assert_eq!(eval_str("shared", &mut ctx).to_string(), "(box 5)");
+    }
+
+    #[test]
+    fn test_make_counter() {
+        let mut ctx = init_context("test");
+        eval_str("(define counter (make-counter))", &mut ctx);
+        assert_eq!(eval_str("(counter)", &mut ctx), Num(1.0));
+        assert_eq!(eval_str("(counter)", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(counter)", &mut ctx), Num(3.0));
+
+        // Each counter keeps its own count
+        eval_str("(define other (make-counter))", &mut ctx);
+        assert_eq!(eval_str("(other)", &mut ctx), Num(1.0));
+    }
+
+    #[test]
+    fn test_call_in_loop() {
+        let mut ctx = init_context("test");
+        eval_str("(define (add-pair x y) (+ x y))", &mut ctx);
+        eval_str("(define total (box 0))", &mut ctx);
+        eval_str(
+            "(repeat 10000 (lambda ()
+                (set-box! total (add-pair (unbox total) 2))))",
+            &mut ctx,
+        );
+        assert_eq!(eval_str("(unbox total)", &mut ctx), Num(20000.0));
+
+        // Arguments after an exception are not evaluated
+        let res =
+            eval_str("(add-pair (undefined-fn) (set-box! total 0))", &mut ctx);
+        assert!(res.is_exception());
+        assert_eq!(eval_str("(unbox total)", &mut ctx), Num(20000.0));
+    }
+
+    #[test]
+    fn test_readline_eof() {
+        let mut ctx = init_context("test");
+        ctx.set_input(io::Cursor::new("first\n\nlast"));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("first".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), Str("last".into()));
+        assert_eq!(eval_str("(readline)", &mut ctx), nil());
+        assert_eq!(eval_str("(readline)", &mut ctx), nil());
+    }
+
+    #[test]
+    fn test_display_output() {
+        let mut ctx = init_context("test");
+        let buf = SharedBuffer::default();
+        ctx.set_output(buf.clone());
+        eval_str("(display \"hi\")", &mut ctx);
+        assert_eq!(buf.contents(), "hi");
+
+        eval_str("(newline)", &mut ctx);
+        eval_str("(display-pretty 'red 'bold \"warning\")", &mut ctx);
+        assert_eq!(buf.contents(), "hi\nwarning");
+    }
+
+    #[test]
+    fn test_displayln() {
+        let mut ctx = init_context("test");
+        let buf = SharedBuffer::default();
+        ctx.set_output(buf.clone());
+        eval_str("(display \"a\" 1 '(b \"c\"))", &mut ctx);
+        assert_eq!(buf.contents(), "a1(b c)");
+        eval_str("(displayln \"d\" 2)", &mut ctx);
+        eval_str("(displayln)", &mut ctx);
+        assert_eq!(buf.contents(), "a1(b c)d2\n\n");
+    }
+
+    #[test]
+    fn test_error_output() {
+        let mut ctx = init_context("test");
+        let out = SharedBuffer::default();
+        let err = SharedBuffer::default();
+        ctx.set_output(out.clone());
+        ctx.set_error_output(err.clone());
+
+        eval_str("(eprint \"a\" 1)", &mut ctx);
+        eval_str("(eprintln \"b\")", &mut ctx);
+        assert_eq!(err.contents(), "a1b\n");
+
+        eval_str("(try (undefined-function) print-error)", &mut ctx);
+        assert!(err.contents().starts_with(
+            "a1b\nerror(1): undefined symbol: `undefined-function`"
+        ));
+        assert_eq!(out.contents(), "");
     }
 }
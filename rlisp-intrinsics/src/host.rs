@@ -0,0 +1,102 @@
+//! This module provides a bridge through which rlisp programs may call
+//! functions registered by the host environment, such as JavaScript functions
+//! when running in WebAssembly. Arguments and results cross the bridge as
+//! rlisp source text, so a host function receives its arguments as a single
+//! list literal and produces a single literal as its result.
+
+use crate::marshal::{marshal, unmarshal};
+use rlisp_interpreter::{
+    exception::Exception,
+    expression::Expression::{self, *},
+    im::ConsList,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// A function provided by the host environment. It is given its marshaled
+/// arguments and produces either its marshaled result or an error message.
+pub type HostFn = Rc<dyn Fn(&str) -> Result<String, String>>;
+
+thread_local! {
+    static HOST_FNS: RefCell<HashMap<String, HostFn>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Registers the specified host function under the specified name, replacing
+/// any function previously registered under that name.
+pub fn register_host_fn(
+    name: impl Into<String>,
+    f: impl Fn(&str) -> Result<String, String> + 'static,
+) {
+    HOST_FNS.with(|fns| fns.borrow_mut().insert(name.into(), Rc::new(f)));
+}
+
+/// Retrieves the host function registered under the specified name.
+pub fn get_host_fn(name: &str) -> Option<HostFn> {
+    HOST_FNS.with(|fns| fns.borrow().get(name).cloned())
+}
+
+/// Marshals the specified arguments into a list literal.
+pub fn marshal_args(args: &ConsList<Expression>) -> Result<String, Exception> {
+    marshal(&Cons(args.clone()))
+}
+
+/// Unmarshals the specified result text into a value. Empty text produces
+/// `nil`.
+pub fn unmarshal_result(text: &str) -> Result<Expression, Exception> {
+    unmarshal(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_context;
+    use rlisp_parser::Parser;
+
+    fn eval_str(src: &str) -> Expression {
+        let mut ctx = init_context("test");
+        Parser::new(src.chars()).parse_all().eval(&mut ctx)
+    }
+
+    #[test]
+    fn test_marshal_args() {
+        let args = ConsList::from(vec![
+            Num(1.5),
+            Bool(true),
+            Str("say \"hi\"\n".into()),
+            Cons(ConsList::from(vec![Symbol("a".into()), Num(2.0)])),
+        ]);
+        assert_eq!(
+            marshal_args(&args).unwrap(),
+            r#"(1.5 true "say \"hi\"\n" (a 2))"#
+        );
+        assert_eq!(
+            unmarshal_result(r#""say \"hi\"\n""#).unwrap(),
+            Str("say \"hi\"\n".into())
+        );
+        assert_eq!(unmarshal_result("").unwrap().to_string(), "()");
+    }
+
+    #[test]
+    fn test_host_call() {
+        register_host_fn("echo", |args| Ok(args.to_string()));
+        register_host_fn("fail", |_| Err("host failure".to_string()));
+
+        assert_eq!(
+            eval_str(r#"(host-call "echo" (quote (1 "two")))"#).to_string(),
+            "(1 two)"
+        );
+        match eval_str(r#"(host-call "fail" (quote ()))"#) {
+            Error(ex) => assert_eq!(ex.error_code(), 54),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str(r#"(host-call "missing" (quote ()))"#) {
+            Error(ex) => assert_eq!(ex.error_code(), 53),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str(r#"(host-call "echo" (cons (lambda (x) x) (quote ())))"#)
+        {
+            Error(ex) => assert_eq!(ex.error_code(), 55),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+}
@@ -8,7 +8,14 @@ use rlisp_interpreter::{
 use std::rc::Rc;
 
 pub mod functions;
+#[cfg(feature = "wasm")]
+pub mod host;
 pub mod macros;
+pub mod marshal;
+#[cfg(feature = "native")]
+pub mod terminal;
+#[cfg(feature = "native")]
+pub mod thread;
 
 /// Creates a context and loads all intrinsic functions and macros into it.
 pub fn init_context(version: &'static str) -> Context {
@@ -20,7 +27,11 @@ pub fn init_context(version: &'static str) -> Context {
     ctx
 }
 
-fn define_intrinsic(
+/// Defines an intrinsic function with the specified name in the specified
+/// context. The function is provided its evaluated arguments. This may be
+/// used by host programs to extend a context created by `init_context` with
+/// their own functions.
+pub fn define_intrinsic(
     ctx: &mut Context,
     ident: impl ToString,
     f: impl Fn(&[Expression], &mut Context) -> Expression + 'static,
@@ -31,7 +42,9 @@ fn define_intrinsic(
     );
 }
 
-fn define_macro(
+/// Defines an intrinsic macro with the specified name in the specified
+/// context. The macro is provided its unevaluated form, including its name.
+pub fn define_macro(
     ctx: &mut Context,
     ident: impl ToString,
     f: impl Fn(ConsList<Expression>, &mut Context) -> Expression + 'static,
@@ -63,20 +76,40 @@ macro_rules! define_macros {
 
 fn load_macros(ctx: &mut Context) {
     use self::macros::*;
+
     define_macros! {
         context: ctx,
         "define" => define,
         // "define-macro" => define_rlisp_macro,
         "define-macro-rule" => define_syntax_rule,
         "lambda" => lambda,
+        "delay" => delay,
         "λ" => lambda,
         "env" => env,
         "if" => if_expr,
         "cond" => cond,
         "let" => let_expr,
+        "let-values" => let_values,
+        "do" => do_loop,
         "try" => try_expr,
+        "with-cleanup" => with_cleanup,
+        "with-handlers" => with_handlers,
         "define-struct" => define_struct,
+        "struct-update" => struct_update,
+        "match" => match_expr,
+        "typecase" => typecase,
         "begin" => begin,
+        "begin0" => begin0,
+        "trace" => trace,
+        "untrace" => untrace,
+        "catch" => catch,
+        "with-buffered-output" => with_buffered_output,
+    }
+
+    #[cfg(feature = "native")]
+    define_macros! {
+        context: ctx,
+        "with-raw-mode" => with_raw_mode,
     }
 }
 
@@ -114,6 +147,7 @@ fn load_functions(ctx: &mut Context) {
         "rem" => rem,
         "eq?" => eq,
         "=" => eq,
+        "equal?" => equal,
         ">" => gt,
         ">=" => gte,
         "<" => lt,
@@ -132,7 +166,16 @@ fn load_functions(ctx: &mut Context) {
         "sqrt" => sqrt,
         "floor" => floor,
         "ceil" => ceil,
+        "round-to" => round_to,
         "pow" => pow,
+        "zero?" => is_zero,
+        "positive?" => is_positive,
+        "negative?" => is_negative,
+        "even?" => is_even,
+        "odd?" => is_odd,
+        "nan?" => is_nan,
+        "inf?" => is_inf,
+        "finite?" => is_finite,
 
         // Boolean logic
         "and" => and,
@@ -142,6 +185,7 @@ fn load_functions(ctx: &mut Context) {
         "not" => not,
 
         "set-internal!" => set,
+        "undefine" => undefine,
 
         // Lists
         "cons" => cons,
@@ -149,20 +193,63 @@ fn load_functions(ctx: &mut Context) {
         "head" => head,
         "tail" => tail,
         "chars" => chars,
+        "list->string" => list_to_string,
+        "char-upcase" => char_upcase,
+        "char-downcase" => char_downcase,
+        "char-alphabetic?" => char_is_alphabetic,
+        "char-numeric?" => char_is_numeric,
+        "char-whitespace?" => char_is_whitespace,
+        "list->vector" => list_to_vector,
+        "vector->list" => vector_to_list,
+        "vector-map" => vector_map,
+        "vector-for-each" => vector_for_each,
 
         "exit" => exit,
+        "set-exit-on-error" => set_exit_on_error,
+        "set-zero-division-error" => set_zero_division_error,
         "display" => display,
+        "displayln" => displayln,
         "display-debug" => display_debug,
         "display-pretty" => display_pretty,
+        "pretty-print" => pretty_print,
         "newline" => newline,
+        "eprint" => eprint,
+        "eprintln" => eprintln,
         "readline" => readline,
+        "clear-screen" => clear_screen,
+        "move-cursor" => move_cursor,
+        "hide-cursor" => hide_cursor,
+        "show-cursor" => show_cursor,
 
         "++" => append,
         "append" => append,
         "empty?" => empty,
+        "flatten" => flatten,
+        "flat-map" => flat_map,
+        "fold-right" => fold_right,
+        "reduce-right" => reduce_right,
+        "count" => count_equal,
+        "count-if" => count_if,
+        "assoc" => assoc,
+        "assq" => assq,
+        "alist->map" => alist_to_map,
+        "pattern-match" => pattern_match_fn,
+        "substitute" => substitute,
         "eval" => eval,
         "parse" => parse,
+        "dump-preprocessed" => dump_preprocessed,
         "type-of" => type_of,
+        "gensym" => gensym,
+        "error?" => is_error,
+        "error-message" => error_message,
+        "assert-type" => assert_type,
+        "coalesce" => coalesce,
+        "struct->list" => struct_to_list,
+        "struct-fields" => struct_fields,
+        "values" => values,
+        "default" => default,
+        "bindings" => bindings,
+        "intern-stats" => intern_stats,
         "format" => format,
 
         "quat" => quaternion,
@@ -171,11 +258,47 @@ fn load_functions(ctx: &mut Context) {
         "env-var" => env_var,
 
         "string-concat" => string_concat,
+        "string-replace" => string_replace,
+        "string-replace-first" => string_replace_first,
+        "string-trim" => string_trim,
+        "string-trim-start" => string_trim_start,
+        "string-trim-end" => string_trim_end,
+        "regex-match?" => regex_is_match,
+        "regex-find" => regex_find,
+        "regex-captures" => regex_captures,
+        "make-string-builder" => make_string_builder,
+        "string-builder-add!" => string_builder_add,
+        "string-builder->string" => string_builder_to_string,
+        "make-list-builder" => make_list_builder,
+        "list-builder-add!" => list_builder_add,
+        "list-builder->list" => list_builder_to_list,
+        "copy" => copy,
+        "box" => make_box,
+        "unbox" => unbox,
+        "set-box!" => set_box,
+        "make-counter" => make_counter,
+        "fill" => fill,
+        "draw-box" => draw_box,
         "current-time" => time_secs,
         "repeat" => repeat,
+        "memoize" => memoize,
+        "partial" => partial,
+        "force" => force,
+        "for-each" => for_each,
+        "any?" => any,
+        "all?" => all,
+        "take" => take,
+        "drop" => drop,
+        "take-while" => take_while,
+        "drop-while" => drop_while,
 
         "print-error" => print_error,
 
+        "path-join" => path_join,
+        "path-basename" => path_basename,
+        "path-dirname" => path_dirname,
+        "path-extension" => path_extension,
+
         "args" => args,
     }
 
@@ -183,12 +306,94 @@ fn load_functions(ctx: &mut Context) {
     define_intrinsics! {
         context: ctx,
         "import" => import,
+        "force-import" => force_import,
         "readfile" => readfile,
+        "list-dir" => list_dir,
+        "file-exists?" => file_exists,
+        "is-dir?" => is_dir,
+        "make-dir" => make_dir,
+        "remove-file" => remove_file,
+        "rename" => rename,
+        "current-dir" => current_dir,
+        "set-current-dir" => set_current_dir,
+        "terminal-size" => terminal_size,
+        "read-char" => read_char,
+        "peek-char" => peek_char,
+        "spawn" => spawn,
+        "join" => join,
+        "make-channel" => make_channel,
+        "channel-send" => channel_send,
+        "channel-recv" => channel_recv,
         "request" => read_http,
         "random" => random
     }
 
+    #[cfg(feature = "debug")]
+    define_intrinsics! {
+        context: ctx,
+        "rc-count" => rc_count,
+    }
+
+    #[cfg(feature = "wasm")]
+    define_intrinsics! {
+        context: ctx,
+        "host-call" => host_call,
+    }
+
     // Boolean logic
 
     // Lists
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rlisp_parser::Parser;
+    use std::{cell::RefCell, io};
+
+    /// A writer whose contents remain accessible after it is given to a
+    /// context.
+    #[derive(Clone, Default)]
+    pub(crate) struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        pub(crate) fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_define_intrinsic() {
+        let mut ctx = init_context("test");
+        define_intrinsic(&mut ctx, "double", |args, _| match args {
+            [Expression::Num(n)] => Expression::Num(n * 2.0),
+            _ => Expression::default(),
+        });
+        let res = Parser::new("(double 21)".chars())
+            .parse_all()
+            .eval(&mut ctx);
+        assert_eq!(res, Expression::Num(42.0));
+    }
+
+    #[test]
+    fn test_define_macro() {
+        let mut ctx = init_context("test");
+        define_macro(&mut ctx, "arg-count", |list, _| {
+            Expression::Num((list.len() - 1) as f64)
+        });
+        let res = Parser::new("(arg-count a b (c d))".chars())
+            .parse_all()
+            .eval(&mut ctx);
+        assert_eq!(res, Expression::Num(3.0));
+    }
+}
@@ -3,18 +3,29 @@
 //! macro reign to do whatever it will with the arguments.
 
 use rlisp_interpreter::{
-    context::Context,
+    context::{Context, Output},
     exception::Exception,
     expression::{
         Callable::*,
         Expression::{self, *},
-        LambdaData, StructData, ValidIdentifier,
+        LambdaData, PromiseState, StructData, ValidIdentifier,
     },
     im::ConsList,
+    intern::Sym,
     pattern::{pattern_match, replace_symbols},
     util::{nil, wrap_begin, Str},
 };
-use std::rc::Rc;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    io::BufWriter,
+    rc::{Rc, Weak},
+    sync::Arc,
+};
+
+use crate::functions::{call_with, write_output, Values};
+#[cfg(feature = "native")]
+use crate::terminal::{self, StdinTerminal};
 
 /// Creates a lambda with the specified parameters and body, capturing
 /// variables from the specified context. At the time of creation.
@@ -23,7 +34,7 @@ fn create_lambda(
     body: ConsList<Expression>,
     ctx: &Context,
 ) -> Expression {
-    let params: Result<ConsList<Str>, ()> = params
+    let params: Result<ConsList<Sym>, ()> = params
         .iter()
         .map(|param| match *param {
             Symbol(ref name) => Ok(name.clone()),
@@ -72,6 +83,25 @@ pub fn lambda(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
     }
 }
 
+/// `(delay <expr> ...)`
+///
+/// Produces a promise to evaluate the specified expressions, capturing
+/// variables from the current context. The expressions are not evaluated
+/// until the promise is forced with `force`.
+pub fn delay(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    match list.tail() {
+        Some(ref body) if !body.is_empty() => {
+            match create_lambda(ConsList::new(), body.clone(), ctx) {
+                ex @ Error(_) => ex,
+                thunk => {
+                    Promise(Rc::new(RefCell::new(PromiseState::Delayed(thunk))))
+                }
+            }
+        }
+        _ => Error(Rc::new(Exception::arity(1, 0))),
+    }
+}
+
 /// `(define <ident> <value>) | (define (<ident> <param1> ...) <expr1> ...)`
 ///
 /// Defines either a constant or a function with the specified name and value.
@@ -240,6 +270,15 @@ pub fn if_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
 /// Iterates through the predicates until one evaluaes to true. That
 /// predicate's matching value is returned.
 ///
+/// A case may instead take the form `[<test> => <f>]`, which matches when its
+/// test evaluates to any value other than `false` or `nil`. The function is
+/// then called with the value of the test, producing the result.
+///
+/// A predicate that is the symbol `else` always holds, regardless of any
+/// binding of that name. No bindings are introduced, so nothing escapes the
+/// `cond`. An exception thrown by a predicate or the chosen case is produced
+/// as-is.
+///
 /// # Examples
 /// ```rustlisp
 /// (define x 10)
@@ -248,30 +287,43 @@ pub fn if_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
 ///       [(eq? x 10) 'ten]
 ///       [else 'other])
 /// ; Is equal to 'ten
+///
+/// (cond [(regex-find "[0-9]+" "room 101")
+///        => (lambda (n) (string-concat "#" n))]
+///       [else "none"])
+/// ; Is equal to "#101"
 /// ```
 pub fn cond(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
-    ctx.ascend_scope();
-
-    // Ensure that "else" branch works
-    ctx.insert("else", Bool(true));
-
     let branches = list.tail().unwrap_or_else(|| ConsList::new());
     for branch in branches.iter() {
         match branch.as_ref() {
+            Cons(list)
+                if list.len() == 3
+                    && is_arrow(list.iter().nth(1).as_deref()) =>
+            {
+                // Safe to unwrap after checking length
+                let test = list.head().unwrap();
+                let f = list.iter().nth(2).unwrap();
+                match eval_test(&test, ctx) {
+                    ex @ Error(_) => return ex,
+                    Bool(false) => (),
+                    value if value.is_nil() => (),
+                    value => {
+                        let f = f.eval(ctx);
+                        return call_with(&f, Some(value), ctx);
+                    }
+                }
+            }
             Cons(list) if list.len() == 2 => {
                 let cond = list.head();
                 let value = list.tail().and_then(|tail| tail.head());
 
                 match (cond, value) {
-                    (Some(cond), Some(value)) => match cond.eval(ctx) {
-                        ex @ Error(_) => return ex.clone(),
+                    (Some(cond), Some(value)) => match eval_test(&cond, ctx) {
+                        ex @ Error(_) => return ex,
                         Bool(false) => (),
-                        Bool(true) => {
-                            ctx.descend_scope();
-                            return value.eval(ctx);
-                        }
+                        Bool(true) => return value.eval(ctx),
                         _ => {
-                            ctx.descend_scope();
                             return Error(Rc::new(Exception::syntax(
                                 18,
                                 "condition must be a boolean value",
@@ -279,7 +331,6 @@ pub fn cond(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
                         }
                     },
                     _ => {
-                        ctx.descend_scope();
                         return Error(Rc::new(Exception::syntax(
                             19,
                             "condition case must contain 2 elements",
@@ -288,7 +339,6 @@ pub fn cond(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
                 }
             }
             _ => {
-                ctx.descend_scope();
                 return Error(Rc::new(Exception::syntax(
                     20,
                     "condition case must be a list",
@@ -297,10 +347,24 @@ pub fn cond(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
         }
     }
 
-    ctx.descend_scope();
     Expression::default()
 }
 
+/// Evaluates the specified `cond` predicate, such that the symbol `else`
+/// always holds.
+fn eval_test(test: &Expression, ctx: &mut Context) -> Expression {
+    match test {
+        Symbol(sym) if &**sym == "else" => Bool(true),
+        test => test.eval(ctx),
+    }
+}
+
+/// Determines whether or not the specified element of a `cond` case is the
+/// symbol `=>`.
+fn is_arrow(expr: Option<&Expression>) -> bool {
+    matches!(expr, Some(Symbol(sym)) if &**sym == "=>")
+}
+
 /// `(let ([<name> <value>] ...) <expr> ...)`
 ///
 /// Binds the specified values to the specified identifiers, creating a new
@@ -376,6 +440,270 @@ pub fn let_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
     body.unwrap_or_else(|ex| Error(Rc::new(ex)))
 }
 
+/// `(let-values ([(<name> ...) <value>] ...) <expr> ...)`
+///
+/// Binds the components of each specified multiple-value result, as produced
+/// by `values`, to the specified identifiers, creating a new context, and
+/// evaluating the specified body expressions in that new context. A value not
+/// produced by `values` is treated as a single component. An exception is
+/// produced if the number of identifiers does not match the number of
+/// components.
+///
+/// # Examples
+/// ```rustlisp
+/// (let-values ([(q r) (values (floor (/ 7 2)) (% 7 2))])
+///     (+ q r))
+/// ; Is equal to 4
+/// ```
+pub fn let_values(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    let bindings = match list.iter().nth(1) {
+        Some(bindings) => match bindings.as_ref() {
+            Cons(bindings) => bindings.clone(),
+            _ => {
+                return Error(Rc::new(Exception::syntax(
+                    21,
+                    "binding list must be a list of bindings",
+                )))
+            }
+        },
+        None => return Error(Rc::new(Exception::arity(2, 0))),
+    };
+    if list.len() < 3 {
+        return Error(Rc::new(Exception::syntax(
+            24,
+            "let-values body not found",
+        )));
+    }
+
+    ctx.ascend_scope();
+    let result = match bind_values(&bindings, ctx) {
+        Ok(()) => begin(list.tail().unwrap(), ctx),
+        Err(ex) => ex,
+    };
+    ctx.descend_scope();
+    result
+}
+
+/// Evaluates each of the specified `let-values` bindings, inserting the
+/// components of each result into the current scope.
+fn bind_values(
+    bindings: &ConsList<Expression>,
+    ctx: &mut Context,
+) -> Result<(), Expression> {
+    for binding in bindings.iter() {
+        let (names, value) = match binding.as_ref() {
+            Cons(binding) if binding.len() == 2 => {
+                let mut binding = binding.iter();
+                (binding.next().unwrap(), binding.next().unwrap())
+            }
+            other => {
+                return Err(Error(Rc::new(Exception::syntax(
+                    23,
+                    format!(
+                        "binding must be a list containing a list of symbols and a value, found {}",
+                        other
+                    ),
+                ))))
+            }
+        };
+        let names: Vec<Sym> = match names.as_ref() {
+            Cons(names) => names
+                .iter()
+                .map(|name| match name.as_ref() {
+                    Symbol(name) => Ok(name.clone()),
+                    other => Err(Error(Rc::new(Exception::syntax(
+                        22,
+                        format!(
+                            "identifier in binding must be a symbol, found {}",
+                            other
+                        ),
+                    )))),
+                })
+                .collect::<Result<_, _>>()?,
+            other => {
+                return Err(Error(Rc::new(Exception::syntax(
+                    22,
+                    format!(
+                        "identifiers in binding must be a list of symbols, found {}",
+                        other
+                    ),
+                ))))
+            }
+        };
+
+        let value = value.eval(ctx);
+        if value.is_exception() {
+            return Err(value);
+        }
+        let components = match &value {
+            Foreign(foreign) => foreign
+                .downcast_ref::<Values>()
+                .map(|Values(values)| values.clone()),
+            _ => None,
+        }
+        .unwrap_or_else(|| vec![value]);
+        if names.len() != components.len() {
+            return Err(Error(Rc::new(Exception::custom(
+                56,
+                format!(
+                    "expected {} values, found {}",
+                    names.len(),
+                    components.len()
+                ),
+            ))));
+        }
+        for (name, component) in names.into_iter().zip(components) {
+            ctx.insert_symbol(name, component);
+        }
+    }
+    Ok(())
+}
+
+/// `(do ([<name> <init> <step>] ...) (<test> <expr> ...) <body> ...)`
+///
+/// Binds each of the specified identifiers to its initial value in a new
+/// context, and then repeatedly evaluates the body while the test is false.
+/// After each iteration, every identifier with a step is bound to the value
+/// of its step, all of which are evaluated before any is bound. Once the test
+/// is true, the expressions following it are evaluated, and the value of the
+/// last is produced.
+///
+/// # Examples
+/// ```rustlisp
+/// (do ([i 1 (+ i 1)]
+///      [sum 0 (+ sum i)])
+///     ({i > 10} sum))
+/// ; Is equal to 55
+/// ```
+pub fn do_loop(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    let (specs, exit) = match (list.iter().nth(1), list.iter().nth(2)) {
+        (Some(specs), Some(exit)) => (specs, exit),
+        _ => return Error(Rc::new(Exception::arity(2, list.len() - 1))),
+    };
+    let specs = match do_specs(&specs) {
+        Ok(specs) => specs,
+        Err(ex) => return ex,
+    };
+    let exit = match exit.as_ref() {
+        Cons(exit) if !exit.is_empty() => exit.clone(),
+        other => {
+            return Error(Rc::new(Exception::syntax(
+                63,
+                format!(
+                    "do loop test must be a non-empty list, found {}",
+                    other
+                ),
+            )))
+        }
+    };
+    // Safe to unwrap after checking length. The head of the body is the exit
+    // clause, which is skipped by `begin`
+    let body = list.tail().and_then(|tail| tail.tail()).unwrap();
+
+    // Initial values are evaluated before any are bound
+    let mut values = Vec::with_capacity(specs.len());
+    for (_, init, _) in specs.iter() {
+        match init.eval(ctx) {
+            ex @ Error(_) => return ex,
+            value => values.push(value),
+        }
+    }
+
+    ctx.ascend_scope();
+    for ((name, _, _), value) in specs.iter().zip(values) {
+        ctx.insert_symbol(name.clone(), value);
+    }
+    let result = run_do_loop(&specs, &exit, &body, ctx);
+    ctx.descend_scope();
+    result
+}
+
+/// The name, initial value, and optional step of a `do` loop variable.
+type DoSpec = (Sym, Arc<Expression>, Option<Arc<Expression>>);
+
+/// Parses the variable specifications of a `do` loop.
+fn do_specs(specs: &Expression) -> Result<Vec<DoSpec>, Expression> {
+    let specs = match specs {
+        Cons(specs) => specs,
+        _ => {
+            return Err(Error(Rc::new(Exception::syntax(
+                21,
+                "binding list must be a list of bindings",
+            ))))
+        }
+    };
+    specs
+        .iter()
+        .map(|spec| match spec.as_ref() {
+            Cons(spec) if spec.len() == 2 || spec.len() == 3 => {
+                let mut spec = spec.iter();
+                match spec.next().unwrap().as_ref() {
+                    Symbol(name) => {
+                        Ok((name.clone(), spec.next().unwrap(), spec.next()))
+                    }
+                    other => Err(Error(Rc::new(Exception::syntax(
+                        22,
+                        format!(
+                            "identifier in binding must be a symbol, found {}",
+                            other
+                        ),
+                    )))),
+                }
+            }
+            other => Err(Error(Rc::new(Exception::syntax(
+                23,
+                format!(
+                    "binding must be a list containing a symbol, a value, \
+                     and an optional step, found {}",
+                    other
+                ),
+            )))),
+        })
+        .collect()
+}
+
+/// Runs a `do` loop whose variables have been bound, producing the value of
+/// its exit expressions.
+fn run_do_loop(
+    specs: &[DoSpec],
+    exit: &ConsList<Expression>,
+    body: &ConsList<Expression>,
+    ctx: &mut Context,
+) -> Expression {
+    // Safe to unwrap as the exit clause is non-empty
+    let test = exit.head().unwrap();
+    loop {
+        match test.eval(ctx) {
+            // `begin` skips the head of its list, which is the test here
+            Bool(true) => return begin(exit.clone(), ctx),
+            Bool(false) => (),
+            ex @ Error(_) => return ex,
+            other => {
+                return Error(Rc::new(Exception::signature(
+                    "bool",
+                    other.type_of(),
+                )))
+            }
+        }
+        if let ex @ Error(_) = begin(body.clone(), ctx) {
+            return ex;
+        }
+
+        let mut steps = Vec::with_capacity(specs.len());
+        for (name, _, step) in specs.iter() {
+            if let Some(step) = step {
+                match step.eval(ctx) {
+                    ex @ Error(_) => return ex,
+                    value => steps.push((name.clone(), value)),
+                }
+            }
+        }
+        for (name, value) in steps {
+            ctx.insert_symbol(name, value);
+        }
+    }
+}
+
 /// `(try <expr> <handler>)`
 ///
 /// Attempts to evaluate the specified expression. If an exception is thrown,
@@ -390,15 +718,7 @@ pub fn try_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
             if handler.is_callable() {
                 let expr = expr.eval(ctx);
                 if let Error(ex) = expr {
-                    let description = ex.to_string();
-                    let expr = Struct(Rc::new(StructData {
-                        name: "error".into(),
-                        data: vec![
-                            (ex.error_code() as f64).into(), // error-code
-                            description.into(), // error-description
-                            Cons(ex.stack().clone()), // error-stack
-                        ],
-                    }));
+                    let expr = error_struct(&ex);
                     let handle_list = ConsList::from(vec![handler, expr]);
                     Cons(handle_list).eval(ctx)
                 } else {
@@ -415,6 +735,162 @@ pub fn try_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
     }
 }
 
+/// `(with-handlers ([<code or predicate> <handler>] ...) <expr> ...)`
+///
+/// Evaluates the specified body expressions, producing the value of the last.
+/// If an exception is thrown, the handler of the first clause whose code is
+/// that of the exception, or whose predicate holds for it, is called with the
+/// same `error` struct as the handler of `try`. Predicates must produce a
+/// boolean. If no clause matches, the exception is rethrown.
+///
+/// # Examples
+/// ```rustlisp
+/// (with-handlers ([10 (lambda (e) 'caught)]
+///                 [(lambda (e) true) (lambda (e) 'other)])
+///     (head '()))
+/// ; Is equal to 'caught
+/// ```
+pub fn with_handlers(
+    list: ConsList<Expression>,
+    ctx: &mut Context,
+) -> Expression {
+    let clauses = match list.iter().nth(1) {
+        Some(clauses) if list.len() > 2 => match clauses.as_ref() {
+            Cons(clauses) => clauses.clone(),
+            other => {
+                return Error(Rc::new(Exception::signature(
+                    "list",
+                    other.type_of(),
+                )))
+            }
+        },
+        _ => return Error(Rc::new(Exception::arity(2, list.len() - 1))),
+    };
+
+    let ex = match begin(list.tail().unwrap(), ctx) {
+        Error(ex) => ex,
+        value => return value,
+    };
+    let error = error_struct(&ex);
+    for clause in clauses.iter() {
+        let (test, handler) = match clause.as_ref() {
+            Cons(clause) if clause.len() == 2 => {
+                // Safe to unwrap after checking length
+                (clause.head().unwrap(), clause.iter().nth(1).unwrap())
+            }
+            other => {
+                return Error(Rc::new(Exception::syntax(
+                    65,
+                    format!(
+                        "with-handlers clause must be a list containing a test and a handler, found {}",
+                        other
+                    ),
+                )))
+            }
+        };
+        let matches = match test.eval(ctx) {
+            Num(code) => code == ex.error_code() as f64,
+            f @ Callable(_) => match call_with(&f, Some(error.clone()), ctx) {
+                Bool(matches) => matches,
+                ex @ Error(_) => return ex,
+                other => {
+                    return Error(Rc::new(Exception::signature(
+                        "bool",
+                        other.type_of(),
+                    )))
+                }
+            },
+            ex @ Error(_) => return ex,
+            other => {
+                return Error(Rc::new(Exception::signature(
+                    "num or procedure",
+                    other.type_of(),
+                )))
+            }
+        };
+        if matches {
+            let handler = handler.eval(ctx);
+            return call_with(&handler, Some(error), ctx);
+        }
+    }
+    Error(ex)
+}
+
+/// Produces the `error` struct describing the specified exception, as passed
+/// to the handler of `try`.
+fn error_struct(ex: &Exception) -> Expression {
+    Struct(Rc::new(StructData {
+        name: "error".into(),
+        data: vec![
+            (ex.error_code() as f64).into(), // error-code
+            ex.to_string().into(),           // error-description
+            Cons(ex.stack()),                // error-stack
+        ],
+    }))
+}
+
+/// `(catch <expr>)`
+///
+/// Evaluates the specified expression, producing its value. If it throws an
+/// exception, the exception is caught and the `error` struct describing it,
+/// as passed to the handler of `try`, is produced instead.
+///
+/// # Examples
+/// ```rustlisp
+/// (error-code (catch (head '())))
+/// ; Is equal to 10
+/// ```
+pub fn catch(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    match list.len() - 1 {
+        // Safe to unwrap after checking length
+        1 => match list.iter().nth(1).unwrap().eval(ctx) {
+            Error(ex) => error_struct(&ex),
+            value => value,
+        },
+        n => Error(Rc::new(Exception::arity(1, n))),
+    }
+}
+
+/// `(with-cleanup <before> <body> <after>)`
+///
+/// Evaluates the specified expressions in order, producing the value of the
+/// body. The after expression is evaluated even if the body throws an
+/// exception, in which case the exception is then rethrown. If the before
+/// expression throws an exception, neither of the others is evaluated. Unlike
+/// `try`, no handler is installed.
+///
+/// # Examples
+/// ```rustlisp
+/// (with-cleanup (set-box! busy true)
+///               (process-queue)
+///               (set-box! busy false))
+/// ```
+pub fn with_cleanup(
+    list: ConsList<Expression>,
+    ctx: &mut Context,
+) -> Expression {
+    match list.len() - 1 {
+        3 => {
+            // Safe to unwrap after checking length
+            let mut exprs = list.iter().skip(1);
+            let before = exprs.next().unwrap();
+            let body = exprs.next().unwrap();
+            let after = exprs.next().unwrap();
+
+            if let ex @ Error(_) = before.eval(ctx) {
+                return ex;
+            }
+            let res = body.eval(ctx);
+            match after.eval(ctx) {
+                // An exception from the body takes precedence
+                ex @ Error(_) if !res.is_exception() => ex,
+                _ => res,
+            }
+        }
+        n => Error(Rc::new(Exception::arity(3, n))),
+    }
+}
+
 /// `(define-struct <name> [<field1> ...])`
 ///
 /// Creates a custom struct type, providing a name and field names. A number of
@@ -451,16 +927,6 @@ pub fn define_struct(
                 )));
             }
 
-            let id;
-            if let Some(id_inner) = env.define_struct(name_str) {
-                id = id_inner;
-            } else {
-                return Error(Rc::new(Exception::custom(
-                    31,
-                    "could not define struct",
-                )));
-            }
-
             let members = list.iter().nth(2).unwrap();
 
             let members_symbols;
@@ -477,7 +943,7 @@ pub fn define_struct(
                 Vec::with_capacity(members_symbols.len());
             for ex in members_symbols.iter() {
                 match ex.as_ref() {
-                    Symbol(member) => member_names.push(member.clone()),
+                    Symbol(member) => member_names.push(member.name().clone()),
                     other => {
                         return Error(Rc::new(Exception::signature(
                             "symbol",
@@ -487,6 +953,18 @@ pub fn define_struct(
                 }
             }
 
+            let id;
+            if let Some(id_inner) =
+                env.define_struct(name_str, member_names.clone())
+            {
+                id = id_inner;
+            } else {
+                return Error(Rc::new(Exception::custom(
+                    31,
+                    "could not define struct",
+                )));
+            }
+
             // Create accessors
             for (i, member) in member_names.iter().enumerate() {
                 let get = move |args: &[Expression], _: &mut Context| match args
@@ -575,13 +1053,206 @@ pub fn define_struct(
     }
 }
 
+/// `(struct-update <instance> <field> <value>)`
+///
+/// Produces a copy of the specified struct instance with the specified field
+/// replaced by the specified value. The struct's type and other fields are
+/// preserved.
+pub fn struct_update(
+    list: ConsList<Expression>,
+    env: &mut Context,
+) -> Expression {
+    match list.len() - 1 {
+        3 => {
+            let mut args = list.iter().skip(1);
+            let instance = args.next().unwrap().eval(env);
+            let field = args.next().unwrap();
+            let value = args.next().unwrap().eval(env);
+            match (instance, field.as_ref(), value) {
+                (ex @ Error(_), ..) | (_, _, ex @ Error(_)) => ex,
+                (Struct(data), Symbol(field), value) => {
+                    let StructData { name, data } = data.as_ref();
+                    let index =
+                        env.get_struct_fields(name).and_then(|fields| {
+                            fields.iter().position(|name| **name == **field)
+                        });
+                    match index {
+                        Some(i) if i < data.len() => {
+                            let mut data = data.clone();
+                            data[i] = value;
+                            Struct(Rc::new(StructData {
+                                name: name.clone(),
+                                data,
+                            }))
+                        }
+                        _ => Error(Rc::new(Exception::custom(
+                            50,
+                            format!(
+                                "struct `{}` has no field `{}`",
+                                name, field
+                            ),
+                        ))),
+                    }
+                }
+                (Struct(_), field, _) => Error(Rc::new(Exception::signature(
+                    "symbol",
+                    field.type_of(),
+                ))),
+                (other, ..) => Error(Rc::new(Exception::signature(
+                    "struct",
+                    other.type_of(),
+                ))),
+            }
+        }
+        n => Error(Rc::new(Exception::arity(3, n))),
+    }
+}
+
+/// `(match <expr> [<pattern> <result>] ...)`
+///
+/// Evaluates the specified expression and compares it against each pattern in
+/// turn. The result of the first arm whose pattern matches is evaluated, with
+/// the symbols in the pattern bound to the matched values. A pattern may be a
+/// literal value, a symbol to bind, `_` to match anything, a list of patterns,
+/// or a struct constructor such as `(make-point x y)` to destructure a struct.
+///
+/// # Examples
+/// ```rustlisp
+/// (define-struct point [x y])
+/// (match (make-point 1 2)
+///     [(make-point 0 y) y]
+///     [(make-point x _) x])
+/// ; Is equal to 1
+/// ```
+pub fn match_expr(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    let value = match list.iter().nth(1) {
+        Some(expr) => expr.eval(ctx),
+        None => return Error(Rc::new(Exception::arity(1, 0))),
+    };
+    if value.is_exception() {
+        return value;
+    }
+
+    for arm in list.iter().skip(2) {
+        let (pattern, result) = match arm.as_ref() {
+            Cons(arm) if arm.len() == 2 => {
+                let mut arm = arm.iter();
+                (arm.next().unwrap(), arm.next().unwrap())
+            }
+            other => {
+                return Error(Rc::new(Exception::syntax(
+                    51,
+                    format!(
+                        "match arm must be a list containing a pattern and a result, found {}",
+                        other
+                    ),
+                )));
+            }
+        };
+
+        if let Ok(matches) = pattern_match(&[], &pattern, &value) {
+            ctx.ascend_scope();
+            for (ident, value) in matches {
+                ctx.insert(ident, value);
+            }
+            let result = result.eval(ctx);
+            ctx.descend_scope();
+            return result;
+        }
+    }
+
+    Error(Rc::new(Exception::custom(
+        52,
+        format!("no match arm matched `{}`", value),
+    )))
+}
+
+/// `(typecase <expr> [<type> <expr> ...] ...)`
+///
+/// Evaluates the specified expression and evaluates the body of the first
+/// branch whose type, as by `type-of`, is that of the value. A struct's name
+/// may be used as its type, and `else` matches any value. A branch's type may
+/// also be written as `(<type> <name>)` to bind the value to that name. If no
+/// branch matches, the result is `nil`.
+///
+/// # Examples
+/// ```rustlisp
+/// (define-struct point [x y])
+/// (typecase (make-point 1 2)
+///     [(num n) n]
+///     [(point p) (point-x p)]
+///     [else 0])
+/// ; Is equal to 1
+/// ```
+pub fn typecase(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    let value = match list.iter().nth(1) {
+        Some(expr) => expr.eval(ctx),
+        None => return Error(Rc::new(Exception::arity(1, 0))),
+    };
+    if value.is_exception() {
+        return value;
+    }
+    let type_name = value.type_of();
+
+    for branch in list.iter().skip(2) {
+        let (key, name, body) = match typecase_branch(&branch) {
+            Some(parts) => parts,
+            None => {
+                return Error(Rc::new(Exception::syntax(
+                    64,
+                    format!(
+                        "typecase branch must be a list containing a type and a body, found {}",
+                        branch
+                    ),
+                )))
+            }
+        };
+        if &*key != "else" && *key != *type_name {
+            continue;
+        }
+
+        ctx.ascend_scope();
+        if let Some(name) = name {
+            ctx.insert_symbol(name, value);
+        }
+        let result = begin(body, ctx);
+        ctx.descend_scope();
+        return result;
+    }
+
+    Expression::default()
+}
+
+/// Splits a `typecase` branch into its type, the optional name to bind the
+/// value to, and the branch itself, or produces `None` if it is malformed.
+fn typecase_branch(
+    branch: &Expression,
+) -> Option<(Sym, Option<Sym>, ConsList<Expression>)> {
+    let branch = match branch {
+        Cons(branch) if branch.len() >= 2 => branch,
+        _ => return None,
+    };
+    match branch.head()?.as_ref() {
+        Symbol(key) => Some((key.clone(), None, branch.clone())),
+        Cons(key) if key.len() == 2 => {
+            match (key.head()?.as_ref(), key.iter().nth(1)?.as_ref()) {
+                (Symbol(key), Symbol(name)) => {
+                    Some((key.clone(), Some(name.clone()), branch.clone()))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 /// `(begin <expr> ...)`
 ///
 /// Evalulates all provided expressions. The result of the last expression is
 /// returned.
 pub fn begin(list: ConsList<Expression>, env: &mut Context) -> Expression {
     let mut last_expr = Expression::default();
-    for expr in list.tail().unwrap_or_else(ConsList::new) {
+    for expr in list.tail().unwrap_or_default() {
         let result = expr.eval(env);
         if result.is_exception() {
             return result;
@@ -591,6 +1262,229 @@ pub fn begin(list: ConsList<Expression>, env: &mut Context) -> Expression {
     last_expr
 }
 
+/// `(begin0 <expr> ...)`
+///
+/// Evaluates all provided expressions. The result of the first expression is
+/// returned, once the rest have been evaluated.
+pub fn begin0(list: ConsList<Expression>, env: &mut Context) -> Expression {
+    let mut first_expr = None;
+    for expr in list.tail().unwrap_or_default() {
+        let result = expr.eval(env);
+        if result.is_exception() {
+            return result;
+        }
+        first_expr.get_or_insert(result);
+    }
+    first_expr.unwrap_or_default()
+}
+
+/// `(with-raw-mode <expr> ...)`
+///
+/// Evaluates all provided expressions with the terminal in raw mode, so that
+/// input is available without waiting for a newline and is not echoed. The
+/// terminal's previous mode is restored afterward, even if an exception is
+/// thrown. The result of the last expression is returned.
+#[cfg(feature = "native")]
+pub fn with_raw_mode(
+    list: ConsList<Expression>,
+    env: &mut Context,
+) -> Expression {
+    terminal::with_raw_mode(&mut StdinTerminal::default(), || begin(list, env))
+}
+
+/// `(with-buffered-output <expr> ...)`
+///
+/// Evaluates all provided expressions with their output buffered, flushing
+/// the buffered output once they have been evaluated. The original sink is
+/// then flushed too, unless it is itself buffered. The result of the last
+/// expression is returned.
+pub fn with_buffered_output(
+    list: ConsList<Expression>,
+    env: &mut Context,
+) -> Expression {
+    let sink = env.replace_output(Output::Stdout);
+    env.replace_output(Output::Buffered(BufWriter::new(Box::new(sink))));
+    let res = begin(list, env);
+    let flushed = match env.replace_output(Output::Stdout) {
+        Output::Buffered(writer) => match writer.into_inner() {
+            Ok(sink) => {
+                let mut sink = *sink;
+                let flushed = sink.flush_unbuffered();
+                env.replace_output(sink);
+                flushed.map_err(|_| {
+                    Exception::custom(12, "could not flush stdout")
+                })
+            }
+            Err(err) => {
+                let (sink, _) = err.into_inner().into_parts();
+                env.replace_output(*sink);
+                Err(Exception::custom(12, "could not flush stdout"))
+            }
+        },
+        other => {
+            env.replace_output(other);
+            Ok(())
+        }
+    };
+    match flushed {
+        Err(ex) if !res.is_exception() => Error(Rc::new(ex)),
+        _ => res,
+    }
+}
+
+/// The type of intrinsic functions, such as those that trace another.
+type IntrinsicFn = dyn Fn(&[Expression], &mut Context) -> Expression;
+
+thread_local! {
+    /// The original values of all traced functions, keyed by the address of
+    /// the wrapper that traces them. Each entry holds a weak reference to its
+    /// wrapper, which prevents its address from being reused by another
+    /// function and reveals when the wrapper has been dropped.
+    static TRACED: RefCell<HashMap<usize, (Weak<IntrinsicFn>, Expression)>> =
+        RefCell::new(HashMap::new());
+
+    /// The number of calls to traced functions that are in progress.
+    static TRACE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Produces the key of the specified value in `TRACED`, if it is an intrinsic
+/// function.
+fn trace_key(value: &Expression) -> Option<usize> {
+    match value {
+        Callable(Intrinsic(f)) => Some(Rc::as_ptr(f) as *const u8 as usize),
+        _ => None,
+    }
+}
+
+/// Determines whether or not the specified value traces another function.
+fn is_traced(value: &Expression) -> bool {
+    trace_key(value)
+        .map(|key| TRACED.with(|traced| traced.borrow().contains_key(&key)))
+        .unwrap_or(false)
+}
+
+/// Produces a function that prints each call to the specified function, named
+/// by the specified symbol, along with its result, indented by the number of
+/// traced calls in progress.
+fn trace_fn(name: Sym, f: Expression) -> Rc<IntrinsicFn> {
+    let traced = move |args: &[Expression], ctx: &mut Context| {
+        let depth = TRACE_DEPTH.with(|depth| depth.replace(depth.get() + 1));
+        let indent = "| ".repeat(depth);
+        let call: ConsList<Expression> = Some(Symbol(name.clone()))
+            .into_iter()
+            .chain(args.to_vec())
+            .collect();
+        let entered =
+            write_output(&format!("{}> {}\n", indent, Cons(call)), ctx);
+        let res = if entered.is_exception() {
+            entered
+        } else {
+            call_with(&f, args.iter().cloned(), ctx)
+        };
+        TRACE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+        match write_output(&format!("{}< {}\n", indent, res), ctx) {
+            ex @ Error(_) if !res.is_exception() => ex,
+            _ => res,
+        }
+    };
+    Rc::new(traced)
+}
+
+/// Looks up the function bound to each of the symbols in the specified form,
+/// applying the specified function to its name and binding.
+fn rebind_traced(
+    list: &ConsList<Expression>,
+    ctx: &mut Context,
+    f: impl Fn(&Sym, &mut Expression) -> Result<(), Expression>,
+) -> Expression {
+    for name in list.iter().skip(1) {
+        let name = match name.as_ref() {
+            Symbol(name) => name,
+            other => {
+                return Error(Rc::new(Exception::signature(
+                    "symbol",
+                    other.type_of(),
+                )))
+            }
+        };
+        let binding = match ctx.get_mut(name) {
+            Some(binding) => binding,
+            None => {
+                return Error(Rc::new(Exception::undefined(
+                    name.name().clone(),
+                )))
+            }
+        };
+        if let Err(ex) = f(name, binding) {
+            return ex;
+        }
+    }
+    Expression::default()
+}
+
+/// `(trace <name> ...)`
+///
+/// Replaces each of the specified functions with one that prints its
+/// arguments when it is called, and its result once it returns, before
+/// producing it. Calls made while another traced call is in progress are
+/// indented, such that recursion is shown as nested calls. Functions that are
+/// already traced are left as-is.
+///
+/// # Examples
+/// ```rustlisp
+/// (define (fact n) (if {n = 0} 1 {n * (fact {n - 1})}))
+/// (trace fact)
+/// (fact 1)
+/// ; Prints:
+/// ; > (fact 1)
+/// ; | > (fact 0)
+/// ; | < 1
+/// ; < 1
+/// ```
+pub fn trace(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    rebind_traced(&list, ctx, |name, binding| {
+        if is_traced(binding) {
+            return Ok(());
+        }
+        match binding {
+            Callable(Lambda(_)) | Callable(Intrinsic(_)) => {
+                let wrapper = trace_fn(name.clone(), binding.clone());
+                let traced = Callable(Intrinsic(wrapper.clone()));
+                // Safe to unwrap, as the wrapper is an intrinsic
+                let key = trace_key(&traced).unwrap();
+                let original = std::mem::replace(binding, traced);
+                TRACED.with(|traced| {
+                    let mut traced = traced.borrow_mut();
+                    // Forget the wrappers of functions that were redefined
+                    traced.retain(|_, (wrapper, _)| wrapper.strong_count() > 0);
+                    traced.insert(key, (Rc::downgrade(&wrapper), original));
+                });
+                Ok(())
+            }
+            other => Err(Error(Rc::new(Exception::signature(
+                "function",
+                other.type_of(),
+            )))),
+        }
+    })
+}
+
+/// `(untrace <name> ...)`
+///
+/// Restores each of the specified functions traced by `trace`. Functions that
+/// are not traced are left as-is.
+pub fn untrace(list: ConsList<Expression>, ctx: &mut Context) -> Expression {
+    rebind_traced(&list, ctx, |_, binding| {
+        let original = trace_key(binding).and_then(|key| {
+            TRACED.with(|traced| traced.borrow_mut().remove(&key))
+        });
+        if let Some((_, original)) = original {
+            *binding = original;
+        }
+        Ok(())
+    })
+}
+
 macro_rules! check_arity {
     ($expected:expr, $found:expr) => {{
         use rlisp_interpreter::exception::Exception;
@@ -646,12 +1540,15 @@ pub fn define_syntax_rule(
                 }
             };
 
+            // The head of a call is not matched against the name, as the
+            // macro may also be called through an alias or as a value
             let syntax = [name.clone()];
-            let pattern = Cons(pat.clone());
+            let pattern = Cons(pat.tail().unwrap_or_default());
 
             let defined_macro =
                 move |list: ConsList<Expression>, ctx: &mut Context| {
-                    match pattern_match(&syntax, &pattern, &Cons(list)) {
+                    let args = Cons(list.tail().unwrap_or_default());
+                    match pattern_match(&syntax, &pattern, &args) {
                         Ok(matches) => {
                             let replaced = replace_symbols(&body, &matches);
                             replaced.eval(ctx)
@@ -668,3 +1565,603 @@ pub fn define_syntax_rule(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{init_context, tests::SharedBuffer};
+    use rlisp_parser::Parser;
+
+    /// Parses and evaluates the specified source in the specified context.
+    fn eval_str(src: &str, ctx: &mut Context) -> Expression {
+        Parser::new(src.chars()).parse_all().eval(ctx)
+    }
+
+    #[test]
+    fn test_struct_update() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str("(define p (make-point 1 2))", &mut ctx);
+        let q = eval_str("(struct-update p y 5)", &mut ctx);
+        assert_eq!(q.to_string(), "(make-point 1 5)");
+        assert_eq!(
+            eval_str("(is-point? (struct-update p x 3))", &mut ctx),
+            Bool(true)
+        );
+
+        // The original is unchanged
+        assert_eq!(eval_str("p", &mut ctx).to_string(), "(make-point 1 2)");
+
+        match eval_str("(struct-update p z 5)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 50),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(struct-update 1 x 5)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_match() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str(
+            "(define (describe p)
+                (match p
+                    [(make-point 0 0) \"origin\"]
+                    [(make-point 0 y) (+ y 100)]
+                    [(make-point x y) (+ x y)]
+                    [1 \"one\"]
+                    [_ \"other\"]))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(describe (make-point 0 0))", &mut ctx),
+            Str("origin".into())
+        );
+        assert_eq!(
+            eval_str("(describe (make-point 0 5))", &mut ctx),
+            Num(105.0)
+        );
+        assert_eq!(eval_str("(describe (make-point 3 4))", &mut ctx), Num(7.0));
+        assert_eq!(eval_str("(describe 1)", &mut ctx), Str("one".into()));
+        assert_eq!(eval_str("(describe 2)", &mut ctx), Str("other".into()));
+
+        // Bindings do not escape the matched arm
+        eval_str("(match (make-point 1 2) [(make-point a b) a])", &mut ctx);
+        match eval_str("a", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_match_nested_struct() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str("(define-struct line [start end])", &mut ctx);
+        let result = eval_str(
+            "(match (make-line (make-point 1 2) (make-point 3 4))
+                [(make-line (make-point x1 _) (make-point _ y2)) (* x1 y2)])",
+            &mut ctx,
+        );
+        assert_eq!(result, Num(4.0));
+
+        // Struct patterns only match structs of the same type
+        match eval_str("(match (make-point 1 2) [(make-line a b) a])", &mut ctx)
+        {
+            Error(ex) => assert_eq!(ex.error_code(), 52),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "native")]
+    fn test_with_raw_mode() {
+        let mut ctx = init_context("test");
+        assert_eq!(eval_str("(with-raw-mode 1 2)", &mut ctx), Num(2.0));
+        match eval_str("(with-raw-mode (undefined-fn))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_with_buffered_output() {
+        let mut ctx = init_context("test");
+        let buf = SharedBuffer::default();
+        ctx.set_output(buf.clone());
+
+        let res = eval_str(
+            "(with-buffered-output (display \"a\") (newline) (display 1) 5)",
+            &mut ctx,
+        );
+        assert_eq!(res, Num(5.0));
+        assert_eq!(buf.contents(), "a\n1");
+
+        // The original sink is restored afterward
+        eval_str("(display \"b\")", &mut ctx);
+        assert_eq!(buf.contents(), "a\n1b");
+        match ctx.output() {
+            Output::Writer(_) => {}
+            other => panic!("expected a writer, found {:?}", other),
+        }
+
+        // Output without a trailing newline is flushed through the sink
+        let buf = SharedBuffer::default();
+        ctx.set_output(std::io::LineWriter::new(buf.clone()));
+        eval_str("(with-buffered-output (display \"x\"))", &mut ctx);
+        assert_eq!(buf.contents(), "x");
+    }
+
+    #[test]
+    fn test_delay_force() {
+        let mut ctx = init_context("test");
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        crate::define_intrinsic(&mut ctx, "count-call", move |_, _| {
+            counter.set(counter.get() + 1);
+            Expression::default()
+        });
+
+        eval_str("(define x 20)", &mut ctx);
+        eval_str("(define p (delay (count-call) (+ x 1)))", &mut ctx);
+        assert_eq!(calls.get(), 0);
+        assert_eq!(eval_str("(type-of p)", &mut ctx), Symbol("promise".into()));
+        assert_eq!(eval_str("(force p)", &mut ctx), Num(21.0));
+        assert_eq!(eval_str("(force p)", &mut ctx), Num(21.0));
+        assert_eq!(calls.get(), 1);
+
+        // Forcing a value that is not a promise produces the value
+        assert_eq!(eval_str("(force 5)", &mut ctx), Num(5.0));
+    }
+
+    #[test]
+    fn test_cond_arrow() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define (assoc-lookup key pairs)
+                (cond [(empty? pairs) nil]
+                      [(= (head (head pairs)) key) (tail (head pairs))]
+                      [else (assoc-lookup key (tail pairs))]))",
+            &mut ctx,
+        );
+        eval_str("(define pairs '((a 1) (b 2)))", &mut ctx);
+        assert_eq!(
+            eval_str(
+                "(cond [(assoc-lookup 'b pairs) => (lambda (v) v)]
+                       [else 'none])",
+                &mut ctx
+            )
+            .to_string(),
+            "(2)"
+        );
+        assert_eq!(
+            eval_str(
+                "(cond [(assoc-lookup 'c pairs) => (lambda (v) v)]
+                       [else 'none])",
+                &mut ctx
+            )
+            .to_string(),
+            "none"
+        );
+        assert_eq!(
+            eval_str("(cond [false => (lambda (v) v)] [5 => -])", &mut ctx),
+            Num(-5.0)
+        );
+    }
+
+    #[test]
+    fn test_cond_else() {
+        let mut ctx = init_context("test");
+        eval_str("(define else 5)", &mut ctx);
+        assert_eq!(
+            eval_str("(cond [{else = 4} 'four] [else 'other])", &mut ctx),
+            Symbol("other".into())
+        );
+        assert_eq!(eval_str("else", &mut ctx), Num(5.0));
+
+        // A user binding is not clobbered within a clause either
+        assert_eq!(
+            eval_str("(cond [{else = 5} else] [else 0])", &mut ctx),
+            Num(5.0)
+        );
+    }
+
+    #[test]
+    fn test_cond_nested() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define (classify x y)
+                (cond [{x < 0} (cond [{y < 0} 'both] [else 'x])]
+                      [else (cond [{y < 0} 'y] [else 'neither])]))",
+            &mut ctx,
+        );
+        let cases = [
+            ("(classify -1 -1)", "both"),
+            ("(classify -1 1)", "x"),
+            ("(classify 1 -1)", "y"),
+            ("(classify 1 1)", "neither"),
+        ];
+        for (code, expected) in cases.iter() {
+            assert_eq!(
+                eval_str(code, &mut ctx),
+                Symbol((*expected).into()),
+                "{}",
+                code
+            );
+        }
+
+        // Exceptions from predicates and cases are produced as-is
+        for code in [
+            "(cond [(head '()) 1] [else 2])",
+            "(cond [false 1] [else (head '())])",
+            "(cond [true (cond [else (head '())])])",
+            "(cond [(head '()) => -] [else 2])",
+        ]
+        .iter()
+        {
+            match eval_str(code, &mut ctx) {
+                Error(ex) => assert_eq!(ex.error_code(), 10, "{}", code),
+                ex => panic!("expected an error, found {}", ex),
+            }
+        }
+        assert_eq!(eval_str("(cond [else => not])", &mut ctx), Bool(false));
+
+        // Nothing is left bound once evaluation has finished
+        match eval_str("else", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_begin0() {
+        let mut ctx = init_context("test");
+        eval_str("(define log (box '()))", &mut ctx);
+        assert_eq!(
+            eval_str(
+                "(begin0 1 (set-box! log (cons 'second (unbox log))) 3)",
+                &mut ctx
+            ),
+            Num(1.0)
+        );
+        assert_eq!(eval_str("(unbox log)", &mut ctx).to_string(), "(second)");
+        assert!(
+            eval_str("(begin0 1 (undefined-fn) 3)", &mut ctx).is_exception()
+        );
+        assert_eq!(eval_str("(begin0)", &mut ctx), Expression::default());
+    }
+
+    /// Defines the `error` struct, as the standard library does.
+    fn define_error(ctx: &mut Context) {
+        eval_str(include_str!("../../rlisp-lib/error.rl"), ctx);
+    }
+
+    #[test]
+    fn test_error_accessors() {
+        let mut ctx = init_context("test");
+        define_error(&mut ctx);
+        eval_str("(define caught (catch (head '())))", &mut ctx);
+        assert_eq!(eval_str("(error? caught)", &mut ctx), Bool(true));
+        assert_eq!(eval_str("(error? '())", &mut ctx), Bool(false));
+        assert_eq!(eval_str("(error-code caught)", &mut ctx), Num(10.0));
+        assert_eq!(
+            eval_str("(error-message caught)", &mut ctx),
+            eval_str("(error-description caught)", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(error-message caught)", &mut ctx),
+            Str("cannot get the head of an empty list".into())
+        );
+        assert_eq!(
+            eval_str("(error-stack caught)", &mut ctx),
+            eval_str("'((head '()))", &mut ctx)
+        );
+
+        // The accessors are ordinary functions
+        eval_str("(define errors (list->vector (cons caught '())))", &mut ctx);
+        assert_eq!(
+            eval_str("(vector-map error-code errors)", &mut ctx),
+            eval_str("(list->vector '(10))", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(count-if error? (cons caught '(1 2)))", &mut ctx),
+            Num(1.0)
+        );
+
+        // The struct caught by `try` is the same
+        assert_eq!(
+            eval_str("(try (head '()) (lambda (e) e))", &mut ctx),
+            eval_str("caught", &mut ctx)
+        );
+        assert_eq!(
+            eval_str("(error? (make-error 1 \"a\" '()))", &mut ctx),
+            Bool(true)
+        );
+
+        // Values that are not thrown are produced as they are
+        assert_eq!(eval_str("(catch {1 + 2})", &mut ctx), Num(3.0));
+        match eval_str("(error-message 5)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_with_handlers() {
+        let mut ctx = init_context("test");
+        define_error(&mut ctx);
+        eval_str(
+            "(define (guarded f)
+                (with-handlers ([10 (lambda (e) 'caught)]
+                                [(lambda (e) {(error-code e) = 9})
+                                 (lambda (e) 'signature)])
+                    (f)))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(guarded (lambda () (head '())))", &mut ctx),
+            Symbol("caught".into())
+        );
+        assert_eq!(
+            eval_str("(guarded (lambda () (head 1)))", &mut ctx),
+            Symbol("signature".into())
+        );
+        assert_eq!(
+            eval_str("(guarded (lambda () (+ 1 2)))", &mut ctx),
+            Num(3.0)
+        );
+
+        // Exceptions matching no clause are rethrown
+        match eval_str("(guarded (lambda () undefined-symbol))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(with-handlers ([1]) (head '()))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 65),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut ctx = init_context("test");
+        let buf = SharedBuffer::default();
+        ctx.set_output(buf.clone());
+        eval_str(
+            "(define (fact n) (if {n = 0} 1 {n * (fact {n - 1})}))",
+            &mut ctx,
+        );
+
+        eval_str("(trace fact)", &mut ctx);
+        eval_str("(trace fact)", &mut ctx);
+        assert_eq!(eval_str("(fact 2)", &mut ctx), Num(2.0));
+        assert_eq!(
+            buf.contents(),
+            "> (fact 2)\n\
+             | > (fact 1)\n\
+             | | > (fact 0)\n\
+             | | < 1\n\
+             | < 1\n\
+             < 2\n"
+        );
+
+        // The original function is restored by untrace
+        eval_str("(untrace fact)", &mut ctx);
+        assert_eq!(eval_str("(fact 3)", &mut ctx), Num(6.0));
+        assert_eq!(buf.contents().lines().count(), 6);
+
+        // Redefining a traced function forgets that it was traced, even once
+        // another function takes the address of its wrapper
+        eval_str("(trace fact)", &mut ctx);
+        eval_str("(define (fact n) n)", &mut ctx);
+        let name = Sym::from("fact");
+        let wrappers: Vec<_> = (0..16)
+            .map(|_| Callable(Intrinsic(trace_fn(name.clone(), nil()))))
+            .collect();
+        assert!(!wrappers.iter().any(is_traced));
+        ctx.insert("fact", wrappers[0].clone());
+        eval_str("(untrace fact)", &mut ctx);
+        assert_eq!(ctx.get("fact"), Some(&wrappers[0]));
+
+        match eval_str("(trace undefined-fn)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        match eval_str("(begin (define x 1) (trace x))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_with_cleanup() {
+        let mut ctx = init_context("test");
+        eval_str("(define events (box '()))", &mut ctx);
+        eval_str(
+            "(define (note e) (set-box! events (cons e (unbox events))))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(with-cleanup (note 'before) 5 (note 'after))", &mut ctx),
+            Num(5.0)
+        );
+        assert_eq!(
+            eval_str("(unbox events)", &mut ctx).to_string(),
+            "(after before)"
+        );
+
+        eval_str("(set-box! events '())", &mut ctx);
+        match eval_str(
+            "(with-cleanup (note 'before) (undefined-fn) (note 'after))",
+            &mut ctx,
+        ) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            ex => panic!("expected an error, found {}", ex),
+        }
+        assert_eq!(
+            eval_str("(unbox events)", &mut ctx).to_string(),
+            "(after before)"
+        );
+
+        // Nothing else is evaluated if the before expression fails
+        eval_str("(set-box! events '())", &mut ctx);
+        assert!(eval_str(
+            "(with-cleanup (undefined-fn) 5 (note 'after))",
+            &mut ctx
+        )
+        .is_exception());
+        assert_eq!(eval_str("(unbox events)", &mut ctx).to_string(), "()");
+    }
+
+    #[test]
+    fn test_do_loop() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str(
+                "(do ([i 1 (+ i 1)]
+                      [sum 0 (+ sum i)])
+                     ({i > 10} sum))",
+                &mut ctx
+            ),
+            Num(55.0)
+        );
+
+        // Steps are evaluated before any variable is rebound
+        assert_eq!(
+            eval_str(
+                "(do ([a 0 b] [b 1 (+ a b)] [n 0 (+ n 1)])
+                     ({n = 10} a))",
+                &mut ctx
+            ),
+            Num(55.0)
+        );
+
+        // The body is evaluated for its side effects
+        eval_str("(define total (box 0))", &mut ctx);
+        assert_eq!(
+            eval_str(
+                "(do ([i 0 (+ i 1)])
+                     ({i = 4})
+                     (set-box! total (+ (unbox total) i)))",
+                &mut ctx
+            ),
+            Expression::default()
+        );
+        assert_eq!(eval_str("(unbox total)", &mut ctx), Num(6.0));
+
+        assert!(eval_str(
+            "(do ([i 0 (+ i 1)]) ({i = 2} (undefined-fn)))",
+            &mut ctx
+        )
+        .is_exception());
+        match eval_str("(do ([i 0]) (1))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 9),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_typecase() {
+        let mut ctx = init_context("test");
+        eval_str("(define-struct point [x y])", &mut ctx);
+        eval_str(
+            "(define (describe x)
+                (typecase x
+                    [(num n) (+ n 1)]
+                    [(string s) (string-concat s \"!\")]
+                    [point 'point]
+                    [else 'other]))",
+            &mut ctx,
+        );
+        assert_eq!(eval_str("(describe 1)", &mut ctx), Num(2.0));
+        assert_eq!(eval_str("(describe \"hi\")", &mut ctx), Str("hi!".into()));
+        assert_eq!(
+            eval_str("(describe (make-point 1 2))", &mut ctx),
+            Symbol("point".into())
+        );
+        assert_eq!(
+            eval_str("(describe '(1))", &mut ctx),
+            Symbol("other".into())
+        );
+
+        // The body may contain several expressions, and no match is nil
+        assert_eq!(
+            eval_str(
+                "(typecase (make-point 1 2) [(point p) 0 (point-y p)])",
+                &mut ctx
+            ),
+            Num(2.0)
+        );
+        assert_eq!(
+            eval_str("(typecase 1 [string 0])", &mut ctx),
+            Expression::default()
+        );
+
+        match eval_str("(typecase 1 [num])", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 64),
+            ex => panic!("expected an error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_let_values() {
+        let mut ctx = init_context("test");
+        eval_str(
+            "(define (div-mod a b) (values (floor (/ a b)) (% a b)))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str(
+                "(let-values ([(q r) (div-mod 7 2)]) (+ (* q 10) r))",
+                &mut ctx
+            ),
+            Num(31.0)
+        );
+        assert_eq!(
+            eval_str(
+                "(let-values ([(a b) (values 1 2)] [(c) 3]) (+ a b c))",
+                &mut ctx
+            ),
+            Num(6.0)
+        );
+
+        // A struct named `values` is a single value
+        eval_str("(define-struct values [a b])", &mut ctx);
+        assert_eq!(
+            eval_str(
+                "(let-values ([(v) (make-values 1 2)]) (values-b v))",
+                &mut ctx
+            ),
+            Num(2.0)
+        );
+        assert_eq!(
+            eval_str("(type-of (values 1 2))", &mut ctx).to_string(),
+            "values"
+        );
+
+        // Mismatched binding counts are an error
+        let ex = eval_str("(let-values ([(a b c) (values 1 2)]) a)", &mut ctx);
+        match ex {
+            Error(ex) => assert_eq!(ex.error_code(), 56),
+            other => panic!("expected an error, found {}", other),
+        }
+    }
+
+    #[test]
+    fn test_define_macro_rule_alias() {
+        let mut ctx = init_context("test");
+        eval_str("(define-macro-rule (add-one x) (+ x 1))", &mut ctx);
+        assert_eq!(eval_str("(add-one 2)", &mut ctx), Num(3.0));
+
+        // The macro may be called through another name
+        eval_str("(define increment add-one)", &mut ctx);
+        assert_eq!(eval_str("(increment 2)", &mut ctx), Num(3.0));
+        match eval_str("(increment 1 2)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 42),
+            other => panic!("expected an error, found {}", other),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! This module converts values to and from rlisp source text, so that they
+//! may cross boundaries that reference-counted values cannot, such as calls
+//! into the host environment or into another thread.
+//!
+//! Data is marshaled as a literal that parses back to an equal value. Lambdas
+//! may additionally be marshaled as source that evaluates to an equivalent
+//! lambda, with its captured values bound around it. Intrinsics are never
+//! marshaled, and are instead expected to be defined wherever the source is
+//! evaluated. Foreign values may only be captured if they may be shared with
+//! another thread, in which case they are carried alongside the source.
+
+use rlisp_interpreter::{
+    exception::Exception,
+    expression::{
+        Callable::*,
+        Expression::{self, *},
+        LambdaData,
+    },
+    foreign::Shared,
+    util::nil,
+};
+use rlisp_parser::Parser;
+use std::{borrow::Borrow, fmt::Write};
+
+/// Marshals the specified value into a literal.
+pub fn marshal(expr: &Expression) -> Result<String, Exception> {
+    let mut buf = String::new();
+    write_data(expr, &mut buf)?;
+    Ok(buf)
+}
+
+/// A lambda marshaled into source text, along with the foreign values it
+/// captured. The source refers to each foreign value by the name produced by
+/// `foreign_name` for its index, which must be bound before it is evaluated.
+pub struct MarshaledLambda {
+    pub src: String,
+    pub foreign: Vec<Shared>,
+}
+
+/// Produces the name by which marshaled source refers to the foreign value
+/// with the specified index.
+pub fn foreign_name(index: usize) -> String {
+    format!("__foreign-{}", index)
+}
+
+/// Marshals the specified lambda into source text that evaluates to an
+/// equivalent lambda.
+pub fn marshal_lambda(expr: &Expression) -> Result<MarshaledLambda, Exception> {
+    let mut lambda = MarshaledLambda {
+        src: String::new(),
+        foreign: Vec::new(),
+    };
+    match expr {
+        Callable(Lambda(data)) => write_lambda(data, &mut lambda)?,
+        other => return Err(unmarshalable(other)),
+    }
+    Ok(lambda)
+}
+
+/// Unmarshals the specified text into a value. Empty text produces `nil`.
+pub fn unmarshal(text: &str) -> Result<Expression, Exception> {
+    Parser::new(text.chars())
+        .try_parse_expr()
+        .map(|expr| expr.unwrap_or_else(nil))
+}
+
+fn unmarshalable(expr: &Expression) -> Exception {
+    Exception::custom(
+        55,
+        format!("cannot marshal a value of type `{}`", expr.type_of()),
+    )
+}
+
+fn write_list(
+    exprs: impl Iterator<Item = impl Borrow<Expression>>,
+    buf: &mut String,
+) -> Result<(), Exception> {
+    buf.push('(');
+    for (i, expr) in exprs.enumerate() {
+        if i > 0 {
+            buf.push(' ');
+        }
+        write_data(expr.borrow(), buf)?;
+    }
+    buf.push(')');
+    Ok(())
+}
+
+fn write_data(expr: &Expression, buf: &mut String) -> Result<(), Exception> {
+    match expr {
+        Bool(_) | Num(_) | Symbol(_) | Callable(Quote)
+        | Callable(Quasiquote) | Callable(Unquote) => {
+            let _ = write!(buf, "{}", expr);
+        }
+        Str(s) => {
+            buf.push('"');
+            for ch in s.chars() {
+                match ch {
+                    '"' => buf.push_str("\\\""),
+                    '\\' => buf.push_str("\\\\"),
+                    '\n' => buf.push_str("\\n"),
+                    '\r' => buf.push_str("\\r"),
+                    '\t' => buf.push_str("\\t"),
+                    ch => buf.push(ch),
+                }
+            }
+            buf.push('"');
+        }
+        Cons(list) => write_list(list.iter(), buf)?,
+        Vector(xs) => {
+            buf.push('#');
+            write_list(xs.iter(), buf)?;
+        }
+        Error(ex) => return Err(ex.as_ref().clone()),
+        other => return Err(unmarshalable(other)),
+    }
+    Ok(())
+}
+
+fn write_lambda(
+    data: &LambdaData,
+    lambda: &mut MarshaledLambda,
+) -> Result<(), Exception> {
+    let LambdaData {
+        params,
+        body,
+        capture,
+    } = data;
+    let captures: Vec<_> = capture
+        .iter()
+        .flat_map(|capture| capture.iter())
+        .filter(|(_, value)| {
+            !matches!(value, Callable(Intrinsic(_)) | Callable(Macro(_)))
+        })
+        .collect();
+
+    if !captures.is_empty() {
+        lambda.src.push_str("(let (");
+        for (name, value) in captures.iter() {
+            let _ = write!(lambda.src, "[{} ", name);
+            match value {
+                Callable(Lambda(data)) => write_lambda(data, lambda)?,
+                Foreign(foreign) => match foreign.share() {
+                    Some(shared) => {
+                        let name = foreign_name(lambda.foreign.len());
+                        lambda.src.push_str(&name);
+                        lambda.foreign.push(shared);
+                    }
+                    None => return Err(unmarshalable(value)),
+                },
+                value => {
+                    lambda.src.push_str("(quote ");
+                    write_data(value, &mut lambda.src)?;
+                    lambda.src.push(')');
+                }
+            }
+            lambda.src.push(']');
+        }
+        lambda.src.push_str(") ");
+    }
+
+    lambda.src.push_str("(lambda (");
+    for (i, param) in params.iter().enumerate() {
+        if i > 0 {
+            lambda.src.push(' ');
+        }
+        lambda.src.push_str(&param);
+    }
+    lambda.src.push_str(") ");
+    write_data(body, &mut lambda.src)?;
+    lambda.src.push(')');
+
+    if !captures.is_empty() {
+        lambda.src.push(')');
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init_context;
+    use rlisp_interpreter::context::Context;
+
+    /// Parses and evaluates the specified source in the specified context.
+    fn eval_str(src: &str, ctx: &mut Context) -> Expression {
+        Parser::new(src.chars()).parse_all().eval(ctx)
+    }
+
+    #[test]
+    fn test_marshal() {
+        let mut ctx = init_context("test");
+        let value =
+            eval_str(r#"(quote (1.5 true "say \"hi\"\n" (a 2)))"#, &mut ctx);
+        let text = marshal(&value).unwrap();
+        assert_eq!(text, r#"(1.5 true "say \"hi\"\n" (a 2))"#);
+        assert_eq!(unmarshal(&text).unwrap(), value);
+        assert_eq!(unmarshal("").unwrap().to_string(), "()");
+
+        let lambda = eval_str("(lambda (x) x)", &mut ctx);
+        match marshal(&lambda) {
+            Err(ex) => assert_eq!(ex.error_code(), 55),
+            Ok(text) => panic!("expected an error, found {}", text),
+        }
+    }
+
+    #[test]
+    fn test_marshal_lambda() {
+        let mut ctx = init_context("test");
+        eval_str("(define offset 10)", &mut ctx);
+        eval_str("(define (add-offset x) (+ x offset))", &mut ctx);
+        let lambda = eval_str("(lambda (x) (add-offset (* x 2)))", &mut ctx);
+        let text = marshal_lambda(&lambda).unwrap().src;
+
+        // The lambda and everything it captures survive in a fresh context
+        let mut fresh = init_context("test");
+        let lambda = eval_str(&text, &mut fresh);
+        fresh.insert("f", lambda);
+        assert_eq!(eval_str("(f 5)", &mut fresh), Num(20.0));
+    }
+}
@@ -0,0 +1,119 @@
+//! This module provides control over the input mode of the terminal attached
+//! to standard input.
+
+/// A terminal whose input mode may be switched into raw mode and back.
+pub trait TerminalMode {
+    /// Switches the terminal into raw mode, in which input is available
+    /// without waiting for a newline and is not echoed. Produces whether or
+    /// not the mode was changed.
+    fn enable_raw_mode(&mut self) -> bool;
+
+    /// Restores the mode that the terminal was in before raw mode was enabled.
+    fn restore_mode(&mut self);
+}
+
+/// The terminal attached to standard input.
+#[derive(Default)]
+pub struct StdinTerminal {
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+}
+
+#[cfg(unix)]
+impl TerminalMode for StdinTerminal {
+    fn enable_raw_mode(&mut self) -> bool {
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return false;
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) }
+            != 0
+        {
+            return false;
+        }
+        self.original = Some(original);
+        true
+    }
+
+    fn restore_mode(&mut self) {
+        if let Some(original) = self.original.take() {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original)
+            };
+        }
+    }
+}
+
+#[cfg(not(unix))]
+impl TerminalMode for StdinTerminal {
+    fn enable_raw_mode(&mut self) -> bool {
+        false
+    }
+
+    fn restore_mode(&mut self) {}
+}
+
+/// Runs the specified function with the specified terminal in raw mode,
+/// restoring its previous mode afterward.
+pub fn with_raw_mode<T>(
+    term: &mut impl TerminalMode,
+    f: impl FnOnce() -> T,
+) -> T {
+    let enabled = term.enable_raw_mode();
+    let res = f();
+    if enabled {
+        term.restore_mode();
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A terminal that records the mode changes made to it.
+    #[derive(Default)]
+    struct MockTerminal {
+        supported: bool,
+        raw: bool,
+        changes: Vec<&'static str>,
+    }
+
+    impl TerminalMode for MockTerminal {
+        fn enable_raw_mode(&mut self) -> bool {
+            if self.supported {
+                self.raw = true;
+                self.changes.push("raw");
+            }
+            self.supported
+        }
+
+        fn restore_mode(&mut self) {
+            self.raw = false;
+            self.changes.push("restore");
+        }
+    }
+
+    #[test]
+    fn test_with_raw_mode() {
+        let mut term = MockTerminal {
+            supported: true,
+            ..MockTerminal::default()
+        };
+        let res: Result<(), &str> = with_raw_mode(&mut term, || Err("failed"));
+        assert_eq!(res, Err("failed"));
+        assert!(!term.raw);
+        assert_eq!(term.changes, vec!["raw", "restore"]);
+    }
+
+    #[test]
+    fn test_with_raw_mode_unsupported() {
+        let mut term = MockTerminal::default();
+        assert_eq!(with_raw_mode(&mut term, || 5), 5);
+        assert!(term.changes.is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! This module provides threads for rlisp programs, and channels through
+//! which they may send each other values.
+//!
+//! Values are reference counted, and so cannot be shared between threads.
+//! Instead, a spawned lambda is marshaled to source text, and evaluated in a
+//! fresh context on the new thread, and its result is marshaled back when the
+//! thread is joined. This places some constraints on spawned lambdas:
+//! * Only intrinsics and the values the lambda captured when it was created
+//!   are available. Library functions and later definitions are not.
+//! * Captured values must be marshalable, so they may not be structs, boxes,
+//!   or other mutable values. Mutating a captured value on the new thread
+//!   does not affect the spawning thread.
+//! * The result of the lambda must likewise be marshalable.
+//!
+//! A channel captured by a spawned lambda is carried to the new thread
+//! alongside the source of the lambda, where it refers to the same channel.
+//! Values sent through a channel are marshaled in the same way.
+
+use crate::{
+    init_context,
+    marshal::{
+        foreign_name, marshal, marshal_lambda, unmarshal, MarshaledLambda,
+    },
+};
+use rlisp_interpreter::{
+    exception::{ErrorCode, Exception},
+    expression::Expression,
+    foreign::{Foreign, Shared},
+    intern::Sym,
+    util::Str,
+};
+use rlisp_parser::Parser;
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::VecDeque,
+    rc::Rc,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+};
+
+/// The marshaled result of a thread, or the code and description of the
+/// exception it produced.
+type ThreadResult = Result<String, (ErrorCode, String)>;
+
+/// A handle to a spawned thread.
+pub struct ThreadHandle {
+    handle: RefCell<Option<JoinHandle<ThreadResult>>>,
+    result: RefCell<Option<ThreadResult>>,
+}
+
+impl ThreadHandle {
+    /// Waits for the thread to finish, producing its result. The result is
+    /// kept, so that the thread may be joined more than once.
+    pub fn join(&self) -> Result<Expression, Exception> {
+        if let Some(handle) = self.handle.borrow_mut().take() {
+            let result = handle.join().unwrap_or_else(|_| {
+                Err((57, "spawned thread panicked".to_string()))
+            });
+            *self.result.borrow_mut() = Some(result);
+        }
+        match self.result.borrow().as_ref() {
+            Some(Ok(text)) => unmarshal(text),
+            Some(Err((code, description))) => {
+                Err(Exception::custom(*code, description.as_str()))
+            }
+            None => unreachable!("thread has neither a handle nor a result"),
+        }
+    }
+}
+
+impl Foreign for ThreadHandle {
+    fn type_name(&self) -> Str {
+        "thread".into()
+    }
+
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign> {
+        // A copy refers to the same thread
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Spawns a thread calling the specified lambda with no arguments. The
+/// `version` of the fresh context is set to the specified version. If the
+/// lambda refers to itself by name, as functions defined with `define` do,
+/// that name is bound to it for the duration of the call.
+pub fn spawn(
+    f: &Expression,
+    name: Option<&Sym>,
+    version: String,
+) -> Result<ThreadHandle, Exception> {
+    let MarshaledLambda { src, foreign } = marshal_lambda(f)?;
+    let src = match name {
+        Some(name) => format!("(let ([{} {}]) ({}))", name, src, name),
+        None => format!("({})", src),
+    };
+    let handle = thread::spawn(move || run(&src, foreign, version));
+    Ok(ThreadHandle {
+        handle: RefCell::new(Some(handle)),
+        result: RefCell::new(None),
+    })
+}
+
+/// Evaluates the specified source in a fresh context, with the specified
+/// foreign values bound, marshaling its result.
+fn run(src: &str, foreign: Vec<Shared>, version: String) -> ThreadResult {
+    let mut ctx = init_context("");
+    ctx.insert("version", version);
+    for (i, value) in foreign.into_iter().enumerate() {
+        ctx.insert(foreign_name(i), Expression::Foreign(value()));
+    }
+    let result = Parser::new(src.chars()).parse_all().eval(&mut ctx);
+    match result {
+        Expression::Error(ex) => Err(ex.as_ref().clone()),
+        result => marshal(&result),
+    }
+    .map_err(|ex| (ex.error_code(), ex.to_string()))
+}
+
+/// The state of a channel, shared by each of its handles.
+struct ChannelState {
+    inner: Mutex<ChannelInner>,
+
+    /// Notified whenever a value is sent or a handle is dropped.
+    changed: Condvar,
+}
+
+struct ChannelInner {
+    /// The marshaled values that have been sent but not yet received.
+    queue: VecDeque<String>,
+
+    /// The number of handles to the channel that have not been dropped.
+    handles: usize,
+}
+
+/// A handle to a channel. Each thread holds its own handle to a channel, which
+/// is dropped along with the last value referring to it on that thread.
+pub struct Channel {
+    state: Arc<ChannelState>,
+}
+
+impl Channel {
+    /// Opens a new channel.
+    pub fn open() -> Channel {
+        let inner = ChannelInner {
+            queue: VecDeque::new(),
+            handles: 1,
+        };
+        Channel {
+            state: Arc::new(ChannelState {
+                inner: Mutex::new(inner),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Produces another handle to the channel.
+    fn handle(&self) -> Channel {
+        self.state.inner.lock().unwrap().handles += 1;
+        Channel {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Sends the specified value through the channel.
+    pub fn send(&self, value: &Expression) -> Result<(), Exception> {
+        let text = marshal(value)?;
+        self.state.inner.lock().unwrap().queue.push_back(text);
+        self.state.changed.notify_all();
+        Ok(())
+    }
+
+    /// Waits for a value to be sent through the channel, producing it. If the
+    /// channel is empty and every other handle to it has been dropped, no
+    /// value can ever arrive, and so an exception is produced instead.
+    pub fn recv(&self) -> Result<Expression, Exception> {
+        let mut inner = self.state.inner.lock().unwrap();
+        loop {
+            if let Some(text) = inner.queue.pop_front() {
+                return unmarshal(&text);
+            }
+            if inner.handles == 1 {
+                return Err(Exception::custom(58, "channel is disconnected"));
+            }
+            inner = self.state.changed.wait(inner).unwrap();
+        }
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.state.inner.lock().unwrap().handles -= 1;
+        self.state.changed.notify_all();
+    }
+}
+
+impl Foreign for Channel {
+    fn type_name(&self) -> Str {
+        "channel".into()
+    }
+
+    fn copy(self: Rc<Self>) -> Rc<dyn Foreign> {
+        // A copy refers to the same channel
+        self
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn share(&self) -> Option<Shared> {
+        let handle = self.handle();
+        Some(Box::new(move || Rc::new(handle)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlisp_interpreter::{context::Context, expression::Expression::*};
+
+    /// Parses and evaluates the specified source in the specified context.
+    fn eval_str(src: &str, ctx: &mut Context) -> Expression {
+        Parser::new(src.chars()).parse_all().eval(ctx)
+    }
+
+    #[test]
+    fn test_spawn_join() {
+        let mut ctx = init_context("test");
+        eval_str("(define n 20)", &mut ctx);
+        eval_str("(define t (spawn (lambda () (* n 2))))", &mut ctx);
+        assert_eq!(eval_str("(type-of t)", &mut ctx).to_string(), "thread");
+        assert_eq!(eval_str("(join t)", &mut ctx), Num(40.0));
+        assert_eq!(eval_str("(join t)", &mut ctx), Num(40.0));
+
+        eval_str(
+            "(define t2 (spawn (lambda () (quote (\"done\" 1)))))",
+            &mut ctx,
+        );
+        assert_eq!(
+            eval_str("(join t2)", &mut ctx),
+            eval_str("(quote (\"done\" 1))", &mut ctx)
+        );
+    }
+
+    #[test]
+    fn test_spawn_recursive() {
+        let mut ctx = init_context("test");
+        eval_str("(define ch (make-channel))", &mut ctx);
+        eval_str(
+            "(define (sum-until-zero)
+                (let ([x (channel-recv ch)])
+                    (if {x = 0} 0 {x + (sum-until-zero)})))",
+            &mut ctx,
+        );
+        for x in &["1", "2", "3", "0"] {
+            eval_str(&format!("(channel-send ch {})", x), &mut ctx);
+        }
+        eval_str("(define t (spawn sum-until-zero))", &mut ctx);
+        assert_eq!(eval_str("(join t)", &mut ctx), Num(6.0));
+    }
+
+    #[test]
+    fn test_spawn_error() {
+        let mut ctx = init_context("test");
+        eval_str("(define t (spawn (lambda () (undefined-fn))))", &mut ctx);
+        match eval_str("(join t)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 1),
+            other => panic!("expected an error, found {}", other),
+        }
+
+        eval_str("(define b (box 1))", &mut ctx);
+        match eval_str("(spawn (lambda () (unbox b)))", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 55),
+            other => panic!("expected an error, found {}", other),
+        }
+    }
+
+    #[test]
+    fn test_channel() {
+        let mut ctx = init_context("test");
+        eval_str("(define results (make-channel))", &mut ctx);
+        eval_str("(define requests (make-channel))", &mut ctx);
+        eval_str(
+            "(define t (spawn (lambda ()
+                (channel-send results 1)
+                (channel-send results \"two\")
+                (channel-send results (quote (3)))
+                (channel-recv requests))))",
+            &mut ctx,
+        );
+        assert_eq!(eval_str("(channel-recv results)", &mut ctx), Num(1.0));
+        assert_eq!(
+            eval_str("(channel-recv results)", &mut ctx),
+            Str("two".into())
+        );
+        assert_eq!(
+            eval_str("(channel-recv results)", &mut ctx).to_string(),
+            "(3)"
+        );
+
+        // The spawned thread may also receive values
+        eval_str("(channel-send requests 4)", &mut ctx);
+        assert_eq!(eval_str("(join t)", &mut ctx), Num(4.0));
+        assert_eq!(
+            eval_str("(type-of results)", &mut ctx).to_string(),
+            "channel"
+        );
+    }
+
+    #[test]
+    fn test_channel_disconnected() {
+        let mut ctx = init_context("test");
+        eval_str("(define ch (make-channel))", &mut ctx);
+        match eval_str("(channel-recv ch)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 58),
+            other => panic!("expected an error, found {}", other),
+        }
+
+        // Once the only other handle is dropped with its thread, the values
+        // it sent may still be received, but nothing more
+        eval_str("(join (spawn (lambda () (channel-send ch 1))))", &mut ctx);
+        assert_eq!(eval_str("(channel-recv ch)", &mut ctx), Num(1.0));
+        match eval_str("(channel-recv ch)", &mut ctx) {
+            Error(ex) => assert_eq!(ex.error_code(), 58),
+            other => panic!("expected an error, found {}", other),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! These tests count the allocations made while evaluating expressions, in
+//! their own binary so that a counting allocator may be installed.
+
+use rlisp_interpreter::{context::Context, expression::Expression};
+use rlisp_intrinsics::init_context;
+use rlisp_parser::Parser;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+/// Counts the allocations made by each thread.
+struct CountingAllocator;
+
+/// The number of allocations made, and the total number of bytes allocated.
+#[derive(Clone, Copy)]
+struct Allocations {
+    count: usize,
+    bytes: usize,
+}
+
+thread_local! {
+    static ALLOCATIONS: Cell<Allocations> =
+        const { Cell::new(Allocations { count: 0, bytes: 0 }) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| {
+            let Allocations { count, bytes } = allocations.get();
+            allocations.set(Allocations {
+                count: count + 1,
+                bytes: bytes + layout.size(),
+            });
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Produces the allocations made by the current thread while calling the
+/// specified function.
+fn count_allocations(f: impl FnOnce()) -> Allocations {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    let after = ALLOCATIONS.with(Cell::get);
+    Allocations {
+        count: after.count - before.count,
+        bytes: after.bytes - before.bytes,
+    }
+}
+
+/// Parses and evaluates the specified source in the specified context,
+/// producing the parsed expression so that it may be evaluated again.
+fn parse_eval(src: &str, ctx: &mut Context) -> Expression {
+    let expr = Parser::new(src.chars()).parse_all();
+    expr.eval(ctx);
+    expr
+}
+
+#[test]
+fn test_call_allocations() {
+    let mut ctx = init_context("test");
+    parse_eval("(define (one a) a)", &mut ctx);
+    parse_eval("(define (four a b c d) a)", &mut ctx);
+    let one = parse_eval("(one 1)", &mut ctx);
+    let four = parse_eval("(four 1 2 3 4)", &mut ctx);
+    let mut per_call = |expr: &Expression| {
+        count_allocations(|| {
+            for _ in 0..100 {
+                expr.eval(&mut ctx);
+            }
+        })
+        .count
+            / 100
+    };
+    let one = per_call(&one);
+    let four = per_call(&four);
+
+    // Arguments are evaluated into a single buffer and bound directly, so
+    // passing more of them does not allocate once for each
+    assert!(one <= 3, "{} allocations for one argument", one);
+    assert!(four <= one + 1, "{} allocations for four arguments", four);
+}
+
+#[test]
+fn test_lookup_allocations() {
+    let mut ctx = init_context("test");
+    let long_name = "a-parameter-whose-name-is-much-longer-than-the-other";
+    parse_eval("(define (short a) a)", &mut ctx);
+    parse_eval(&format!("(define (long {0}) {0})", long_name), &mut ctx);
+    let short = parse_eval("(short 1)", &mut ctx);
+    let long = parse_eval("(long 1)", &mut ctx);
+    let mut bytes_per_call = |expr: &Expression| {
+        count_allocations(|| {
+            for _ in 0..100 {
+                expr.eval(&mut ctx);
+            }
+        })
+        .bytes
+            / 100
+    };
+
+    // Symbols are bound and looked up by ID, so their names are never copied
+    assert_eq!(bytes_per_call(&short), bytes_per_call(&long));
+}
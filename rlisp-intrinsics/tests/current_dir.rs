@@ -0,0 +1,51 @@
+//! This test changes the current directory of the process, and so runs in its
+//! own binary, where no other test may be resolving relative paths.
+
+#![cfg(feature = "native")]
+
+use rlisp_interpreter::{
+    context::Context,
+    expression::Expression::{self, Str},
+};
+use rlisp_intrinsics::init_context;
+use rlisp_parser::Parser;
+use std::{env, fs, path::PathBuf};
+
+/// Parses and evaluates the specified source in the specified context.
+fn eval_str(src: &str, ctx: &mut Context) -> Expression {
+    Parser::new(src.chars()).parse_all().eval(ctx)
+}
+
+/// Restores the current directory when dropped, even if the test panics.
+struct RestoreDir(PathBuf);
+
+impl Drop for RestoreDir {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(&self.0);
+    }
+}
+
+#[test]
+fn test_current_dir() {
+    let dir = env::temp_dir()
+        .join(format!("rlisp-test-current-dir-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let _restore = RestoreDir(env::current_dir().unwrap());
+
+    let mut ctx = init_context("test");
+    let prev = env::current_dir().unwrap();
+    let found = eval_str("(current-dir)", &mut ctx);
+    assert_eq!(found, Str(prev.to_string_lossy().as_ref().into()));
+
+    let src = format!("(set-current-dir \"{}\")", dir.to_string_lossy());
+    assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+    let found = eval_str("(current-dir)", &mut ctx);
+    let expected = dir.canonicalize().unwrap();
+    assert_eq!(found, Str(expected.to_string_lossy().as_ref().into()));
+
+    let src = format!("(set-current-dir \"{}\")", prev.to_string_lossy());
+    assert_eq!(eval_str(&src, &mut ctx), Expression::default());
+    assert_eq!(env::current_dir().unwrap(), prev);
+    fs::remove_dir_all(&dir).unwrap();
+}
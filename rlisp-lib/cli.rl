@@ -1,5 +1,5 @@
 (define (handle-args types fn)
-  (define argv (tail (args)))
+  (define argv (args))
   (define parsed-args (map parse argv))
   (define parsed-types (map type-of parsed-args))
   (if {parsed-types = types}
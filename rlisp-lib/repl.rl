@@ -36,17 +36,24 @@
     (flush-stdout))
 
 ; repl :: -> nil
+; Reads, evaluates, and prints expressions until the end of the input.
 (define (repl)
     (prompt PROMPT)
-    (try
+    (define line (readline))
+    (if {line = nil}
         (begin
-            (define value (eval (parse (readline))))
-            (if {value /= empty}
-                (printfln "#{value}")
-                nil)
-            (eval (parse "(set! _ value)")))
-        print-error)
-    (repl))
+            (newline)
+            (exit))
+        (begin
+            (try
+                (begin
+                    (define value (eval (parse line)))
+                    (if {value /= empty}
+                        (printfln "#{value}")
+                        nil)
+                    (eval (parse "(set! _ value)")))
+                print-error)
+            (repl))))
 
 ; help :: -> nil
 (define (help)
@@ -1,8 +1,6 @@
 (define print display)
 
-(define (println x)
-    (display x)
-    (newline))
+(define println displayln)
 
 (define printf (compose print format))
 
@@ -24,13 +24,6 @@
     (cond [(empty? xs) nil]
           [else (cons (f (head xs)) (map f (tail xs)))]))
 
-; for-each :: (a -> b) (list a) -> nil
-(define (for-each f xs)
-    (cond [(empty? xs) nil]
-          [else (begin
-            (f (head xs))
-            (for-each f (tail xs)))]))
-
 ; sum :: (list num) -> num
 (define (sum xs)
     (apply + xs))
@@ -4,22 +4,36 @@
 //!
 //! As an example, infix function calls are allowed, provided they
 //! are delimited with `'{'` and `'}``. Within an infix function call, every
-//! other expression is considered to be the "function."
+//! other expression is considered to be the "function." Arithmetic and
+//! comparison operators may be mixed, and are applied by precedence.
 
 use rlisp_interpreter::{
-    exception::Exception,
+    exception::{ErrorCode, Exception},
     expression::{
         Callable::*,
         Expression::{self, *},
     },
     im::ConsList,
+    intern::intern,
+    location::{set_position, set_symbol_position},
     quat::Quat,
     util::{nil, wrap_begin},
 };
-use std::rc::Rc;
+use std::{iter::Peekable, rc::Rc};
 
 pub mod preprocessor;
 
+pub use rlisp_interpreter::location::Position;
+
+/// Produces a syntax error with the specified code and description, located
+/// at the specified position.
+fn syntax_error(code: ErrorCode, desc: &str, pos: Position) -> Exception {
+    Exception::syntax(
+        code,
+        format!("{} at line {}, col {}", desc, pos.line, pos.col),
+    )
+}
+
 /// Stores information regarding the current state of the parser, in particular
 /// its progress within whatever it is parsing, and a stack of characters to be
 /// re-read.
@@ -29,6 +43,11 @@ where
 {
     iter: I::IntoIter,
     stack: Vec<char>,
+    pos: Position,
+
+    /// The lengths of all completed lines, used to restore the column when a
+    /// newline is unread.
+    line_lengths: Vec<usize>,
 }
 
 impl<I> Parser<I>
@@ -40,9 +59,16 @@ where
         Self {
             iter: iter.into_iter(),
             stack: Vec::new(),
+            pos: Position::default(),
+            line_lengths: Vec::new(),
         }
     }
 
+    /// Produces the position of the next char to be read by the parser.
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+
     /// Produces the next char in the parser, if it is present. Otherwise,
     /// `None` is produced.
     fn next_char(&mut self) -> Option<char> {
@@ -52,6 +78,16 @@ where
             self.iter.next()
         };
 
+        match ch {
+            Some('\n') => {
+                self.line_lengths.push(self.pos.col);
+                self.pos.line += 1;
+                self.pos.col = 1;
+            }
+            Some(_) => self.pos.col += 1,
+            None => (),
+        }
+
         ch
     }
 
@@ -65,80 +101,134 @@ where
     /// "Unreads" the specified character. Returning it to the stack of unread
     /// characters.
     fn unread(&mut self, ch: char) {
+        if ch == '\n' {
+            self.pos.line -= 1;
+            self.pos.col = self.line_lengths.pop().unwrap_or(1);
+        } else {
+            self.pos.col -= 1;
+        }
         self.stack.push(ch)
     }
 
     /// Parses all whitespace-separated expressions into a `begin` expression,
     /// such that all will be evaulated, and the last returned.
     pub fn parse_all(&mut self) -> Expression {
-        let mut exprs = ConsList::new();
-        while let Some(expr) = self.parse_expr() {
-            if let ex @ Error(_) = expr {
-                return ex;
-            } else {
-                exprs = exprs + ConsList::singleton(expr);
-            }
+        match self.try_parse_all() {
+            Ok(exprs) => wrap_begin(ConsList::from(exprs)),
+            Err(ex) => Error(Rc::new(ex)),
         }
-        wrap_begin(exprs)
+    }
+
+    /// Parses all whitespace-separated expressions, producing them in order or
+    /// the first syntax error encountered.
+    pub fn try_parse_all(&mut self) -> Result<Vec<Expression>, Exception> {
+        let mut exprs = Vec::new();
+        while let Some(expr) = self.try_parse_expr()? {
+            exprs.push(expr);
+        }
+        Ok(exprs)
     }
 
     /// Parses the next expression in the parser, producing it or `None` if no
     /// expression is found.
     pub fn parse_expr(&mut self) -> Option<Expression> {
+        match self.try_parse_expr() {
+            Ok(expr) => expr,
+            Err(ex) => Some(Error(Rc::new(ex))),
+        }
+    }
+
+    /// Parses the next expression in the parser, producing it or `None` if no
+    /// expression is found. If the expression is malformed, the syntax error
+    /// is produced instead.
+    pub fn try_parse_expr(&mut self) -> Result<Option<Expression>, Exception> {
+        Ok(self.try_parse_located()?.map(|(_, expr)| expr))
+    }
+
+    /// Parses the next expression in the parser, producing it along with the
+    /// position at which it begins, or `None` if no expression is found. If
+    /// the expression is malformed, the syntax error is produced instead.
+    pub fn try_parse_located(
+        &mut self,
+    ) -> Result<Option<(Position, Expression)>, Exception> {
         // Ignore whitespace
         self.read_to(|ch| !ch.is_whitespace());
+        let start = self.position();
 
         // Look at char
-        self.next_char().and_then(|ch| match ch {
-            '\'' => self.parse_expr().map(quote),
-            '`' => self.parse_expr().map(quasiquote),
-            ',' => self.parse_expr().map(unquote),
-            '(' => self.parse_cons(')'),
-            '[' => self.parse_cons(']'),
+        let ch = match self.next_char() {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+        let expr = match ch {
+            '\'' => self.try_parse_expr()?.map(quote),
+            '`' => self.try_parse_expr()?.map(quasiquote),
+            ',' => self.try_parse_expr()?.map(unquote),
+            '(' => Some(self.parse_cons(')', start)?),
+            '[' => Some(self.parse_cons(']', start)?),
             '#' => {
+                if let Some('(') = self.peek_char() {
+                    self.next_char();
+                    return Ok(Some((start, self.parse_vector(start)?)));
+                }
                 if let Some('|') = self.peek_char() {
                     self.next_char();
 
-                    // Begin block comment
+                    // Begin block comment, which may contain nested block
+                    // comments
+                    let mut depth = 1usize;
                     let completed = loop {
-                        if let Some(ch) = self.next_char() {
-                            if ch == '|' {
-                                if let Some('#') = self.next_char() {
+                        match self.next_char() {
+                            Some('|') if self.peek_char() == Some('#') => {
+                                self.next_char();
+                                depth -= 1;
+                                if depth == 0 {
                                     break true;
                                 }
                             }
-                        } else {
-                            break false;
+                            Some('#') if self.peek_char() == Some('|') => {
+                                self.next_char();
+                                depth += 1;
+                            }
+                            Some(_) => (),
+                            None => break false,
                         }
                     };
                     if !completed {
-                        return Some(Error(Rc::new(Exception::syntax(
+                        return Err(syntax_error(
                             42,
                             "unclosed block comment",
-                        ))));
+                            start,
+                        ));
                     }
                 }
-                self.parse_expr()
+                return self.try_parse_located();
+            }
+            '"' => Some(self.parse_str(start)?),
+            ')' | ']' | '}' => {
+                return Err(syntax_error(5, "unexpected list close", start))
             }
-            '"' => self.parse_str(),
-            ')' | ']' | '}' => Some(Error(Rc::new(Exception::syntax(
-                5,
-                format!("unexpected list close"),
-            )))),
             ';' => {
                 self.read_to(|ch| ch == '\n');
-                self.parse_expr()
+                return self.try_parse_located();
             }
-            '{' => self.parse_infix(),
+            '{' => Some(self.parse_infix(start)?),
             ch => {
                 self.unread(ch);
                 self.parse_atom()
             }
-        })
+        };
+
+        // Record where lists begin, so that exceptions may refer to them
+        if let Some(Cons(list)) = &expr {
+            set_position(list, start);
+        }
+        Ok(expr.map(|expr| (start, expr)))
     }
 
     /// Parses an infix function list. Every other element of the list is
-    /// considered to be the first element of the list. As an example:
+    /// considered to be an operator. If every operator is the same, they are
+    /// all applied at once. As an example:
     /// ```rustlisp
     /// {1 + 2 + 3 + 4}
     /// ```
@@ -146,10 +236,18 @@ where
     /// ```rustlisp
     /// (+ 1 2 3 4)
     /// ```
-    fn parse_infix(&mut self) -> Option<Expression> {
-        let mut buf: Vec<Expression> = Vec::new();
-        let mut is_op = false;
-        let mut op: Option<Expression> = None;
+    /// Otherwise, the operators must all be arithmetic or comparison
+    /// operators, which are applied according to their precedence, such that
+    /// `{1 + 2 * 3 < 10}` is parsed as `(< (+ 1 (* 2 3)) 10)`. Operands may
+    /// be preceded by a sign, such that `{- x + 1}` is parsed as
+    /// `(+ (- x) 1)`.
+    fn parse_infix(
+        &mut self,
+        start: Position,
+    ) -> Result<Expression, Exception> {
+        let mut operands: Vec<Expression> = Vec::new();
+        let mut ops: Vec<(Expression, Position)> = Vec::new();
+        let mut signs: Vec<Expression> = Vec::new();
 
         while let Some(ch) = self.next_char() {
             match ch {
@@ -157,42 +255,74 @@ where
                 '}' => break,
                 ch => {
                     self.unread(ch);
-                    match self.parse_expr() {
+                    let pos = self.position();
+                    match self.try_parse_expr()? {
+                        Some(expr) if operands.len() > ops.len() => {
+                            ops.push((expr, pos))
+                        }
+                        Some(expr) if is_sign(&expr) => signs.push(expr),
                         Some(expr) => {
-                            if is_op {
-                                if op.is_none() {
-                                    op = Some(expr);
-                                } else {
-                                    // Ensure that different operators are not used in infix lists
-                                    if Some(expr) != op {
-                                        return Some(Error(Rc::new(Exception::syntax(
-                      6,
-                      "infix list operators must be equal",
-                    ))));
-                                    }
-                                }
-                            } else {
-                                buf.push(expr);
-                            }
-                            is_op = !is_op;
+                            // Apply any unary signs, innermost first
+                            let operand = signs.drain(..).rev().fold(
+                                expr,
+                                |expr, sign| match &sign {
+                                    Symbol(sym) if &**sym == "-" => Cons(
+                                        ConsList::singleton(expr).cons(sign),
+                                    ),
+                                    _ => expr,
+                                },
+                            );
+                            operands.push(operand);
                         }
                         None => {
-                            return Some(Error(Rc::new(Exception::syntax(
+                            return Err(syntax_error(
                                 7,
                                 "unclosed infix list",
-                            ))));
+                                start,
+                            ));
                         }
                     }
                 }
             }
         }
 
-        match buf.len() {
-            0 => Some(Expression::default()),
-            1 => Some((&buf[0]).clone()),
-            _ => Some(Cons(
-                ConsList::from(buf).cons(op.expect("this should not fail")),
-            )),
+        if !signs.is_empty() || (!ops.is_empty() && operands.len() == ops.len())
+        {
+            return Err(syntax_error(
+                7,
+                "infix list is missing a final operand",
+                start,
+            ));
+        }
+
+        match ops.first() {
+            None => Ok(operands.pop().unwrap_or_default()),
+            Some((op, _)) if ops.iter().all(|(other, _)| other == op) => {
+                Ok(Cons(ConsList::from(operands).cons(op.clone())))
+            }
+            Some(_) => {
+                // Resolve the precedence of every operator before applying any
+                let ops = ops
+                    .into_iter()
+                    .map(|(op, pos)| match precedence(&op) {
+                        Some(prec) => Ok((op, prec)),
+                        None => Err(syntax_error(
+                            6,
+                            &format!(
+                                "infix operator `{}` cannot be mixed with \
+                                 other operators",
+                                op
+                            ),
+                            pos,
+                        )),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(apply_infix(
+                    &mut operands.into_iter(),
+                    &mut ops.into_iter().peekable(),
+                    0,
+                ))
+            }
         }
     }
 
@@ -216,68 +346,122 @@ where
     }
 
     /// Parses a list of expressions until a specified end delimiter, usually
-    /// `')'`, `']'`, or `'}'`, is reached.
-    fn parse_cons(&mut self, end: char) -> Option<Expression> {
+    /// `')'`, `']'`, or `'}'`, is reached. As lists are always proper, a lone
+    /// `.` within a list, as in the dotted pair `(a . b)`, is rejected.
+    fn parse_cons(
+        &mut self,
+        end: char,
+        start: Position,
+    ) -> Result<Expression, Exception> {
         let mut list = ConsList::new();
-        let mut closed = false;
         while let Some(ch) = self.next_char() {
             match ch {
                 // Skip whitespace
                 ch if ch.is_whitespace() => (),
-                ch if ch == end => {
-                    closed = true;
-                    break;
-                }
+                ch if ch == end => return Ok(Cons(list)),
                 ch => {
                     self.unread(ch);
-                    match self.parse_expr() {
-                        Some(ref expr) if expr.is_exception() => {
-                            return Some(expr.clone());
+                    let pos = self.position();
+                    match self.try_parse_expr()? {
+                        Some(Symbol(ref s)) if &**s == "." => {
+                            return Err(syntax_error(
+                                45,
+                                "dotted pairs are not supported",
+                                pos,
+                            ));
                         }
-                        Some(expr) => list = list + ConsList::singleton(expr),
-                        None => {
-                            return Some(Error(Rc::new(Exception::syntax(
-                                6,
-                                "unclosed list",
-                            ))));
+                        Some(expr) => {
+                            let elem = ConsList::singleton(expr);
+                            let head = elem.head().unwrap();
+                            if let Symbol(_) = *head {
+                                set_symbol_position(&head, pos);
+                            }
+                            list = list + elem;
                         }
+                        None => break,
                     }
                 }
             }
         }
-        if closed {
-            Some(Cons(list))
-        } else {
-            Some(Error(Rc::new(Exception::syntax(6, "unclosed list"))))
+        Err(syntax_error(6, "unclosed list", start))
+    }
+
+    /// Parses a vector literal, such as `#(1 2 3)`, after its opening `#(`
+    /// has been read.
+    fn parse_vector(
+        &mut self,
+        start: Position,
+    ) -> Result<Expression, Exception> {
+        match self.parse_cons(')', start)? {
+            Cons(list) => Ok(Vector(Rc::new(
+                list.iter().map(|expr| expr.as_ref().clone()).collect(),
+            ))),
+            _ => unreachable!("parse_cons always produces a list"),
         }
     }
 
-    /// Parses a string.
-    fn parse_str(&mut self) -> Option<Expression> {
+    /// Parses a string. The escape sequences `\r`, `\n`, `\t`, `\\`, and
+    /// `\u{...}`, where `...` is the hexadecimal value of a unicode code
+    /// point, are supported. Any other escaped character is produced as-is.
+    fn parse_str(&mut self, start: Position) -> Result<Expression, Exception> {
         let mut buf = String::new();
-        while let Some(ch) = self.next_char() {
-            match ch {
-                '\\' => match self.next_char() {
+        loop {
+            let pos = self.position();
+            match self.next_char() {
+                Some('\\') => match self.next_char() {
                     Some(ch) => match ch {
                         'r' => buf.push('\r'),
                         'n' => buf.push('\n'),
                         't' => buf.push('\t'),
+                        '\\' => buf.push('\\'),
+                        'u' => buf.push(self.parse_unicode_escape(pos)?),
                         ch => buf.push(ch),
                     },
                     None => (),
                 },
-                '"' => return Some(Str(buf.into())),
-                ch => buf.push(ch),
+                Some('"') => return Ok(Str(buf.into())),
+                Some(ch) => buf.push(ch),
+                None => break,
             }
         }
-        Some(Error(Rc::new(Exception::syntax(
-            8,
-            "unclosed string literal",
-        ))))
+        Err(syntax_error(8, "unclosed string literal", start))
+    }
+
+    /// Parses the `{...}` part of a unicode escape sequence, after its leading
+    /// `\u` has been read, producing the escaped character.
+    fn parse_unicode_escape(
+        &mut self,
+        start: Position,
+    ) -> Result<char, Exception> {
+        let invalid = || syntax_error(46, "invalid unicode escape", start);
+        if self.next_char() != Some('{') {
+            return Err(invalid());
+        }
+        let mut digits = String::new();
+        loop {
+            match self.next_char() {
+                Some('}') => break,
+                Some(ch) if ch.is_ascii_hexdigit() && digits.len() < 6 => {
+                    digits.push(ch)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(invalid)
     }
 
     /// Parses an atom, which is a boolean value, quote, quasiquote, unquote, a
-    /// number, or a symbol.
+    /// number, or a symbol. Atoms are resolved in the following order:
+    ///
+    /// 1. Keywords, such as `true` or `nil`.
+    /// 2. Numeric literals, which begin with an optional sign followed by a
+    ///    digit or a `.` and a digit, are parsed as real numbers and then as
+    ///    quaternions, such that `-5` and `-5i` are both numbers.
+    /// 3. Everything else is a symbol, including a lone `-` and identifiers
+    ///    such as `-foo`.
     fn parse_atom(&mut self) -> Option<Expression> {
         self.read_to(|ch| ch.is_whitespace() || !is_valid_ident(ch))
             .map(|s| {
@@ -288,24 +472,90 @@ where
                     "quote" => Callable(Quote),
                     "quasiquote" => Callable(Quasiquote),
                     "unquote" => Callable(Unquote),
-                    _ => {
-                        // Attempt to parse quaternion
-                        if let Ok(q) = s.parse::<Quat>() {
-                            return Quaternion(Rc::new(q));
-                        }
-
+                    s if is_numeric(s) => {
                         // Attempt to parse number
                         if let Ok(num) = s.parse::<f64>() {
                             return Num(num);
                         }
 
-                        Symbol(s.into())
+                        // Attempt to parse quaternion
+                        if let Ok(q) = s.parse::<Quat>() {
+                            return Quaternion(Rc::new(q));
+                        }
+
+                        Symbol(intern(s))
                     }
+                    _ => Symbol(intern(&s)),
                 }
             })
     }
 }
 
+/// Produces the precedence of the specified infix operator, if it is an
+/// arithmetic or comparison operator. Operators with a higher precedence are
+/// applied first.
+fn precedence(op: &Expression) -> Option<u8> {
+    match op {
+        Symbol(sym) => match sym.as_ref() {
+            "*" | "/" | "%" => Some(3),
+            "+" | "-" => Some(2),
+            "=" | "/=" | "<" | "<=" | ">" | ">=" => Some(1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Determines whether or not the specified expression is a sign, `+` or `-`,
+/// which may be applied to an operand of an infix list.
+fn is_sign(expr: &Expression) -> bool {
+    match expr {
+        Symbol(sym) => &**sym == "+" || &**sym == "-",
+        _ => false,
+    }
+}
+
+/// Applies the specified infix operators to the specified operands, by
+/// precedence climbing. Only operators of at least the specified precedence
+/// are applied. Consecutive uses of the same operator are applied at once,
+/// such that `{1 + 2 + 3 * 4}` is parsed as `(+ 1 2 (* 3 4))`.
+fn apply_infix(
+    operands: &mut impl Iterator<Item = Expression>,
+    ops: &mut Peekable<impl Iterator<Item = (Expression, u8)>>,
+    min_prec: u8,
+) -> Expression {
+    // The caller ensures that there is an operand for each operator
+    let mut lhs = operands.next().unwrap_or_default();
+    while let Some((op, prec)) =
+        ops.peek().filter(|(_, prec)| *prec >= min_prec).cloned()
+    {
+        ops.next();
+        let mut args = vec![lhs, apply_infix(operands, ops, prec + 1)];
+        while matches!(ops.peek(), Some((next, _)) if *next == op) {
+            ops.next();
+            args.push(apply_infix(operands, ops, prec + 1));
+        }
+        lhs = Cons(ConsList::from(args).cons(op));
+    }
+    lhs
+}
+
+/// Determines whether or not the specified token begins like a numeric
+/// literal, that is with an optional sign followed by either a digit or a `.`
+/// and a digit.
+fn is_numeric(s: &str) -> bool {
+    let unsigned = if s.starts_with('-') || s.starts_with('+') {
+        &s[1..]
+    } else {
+        s
+    };
+    let mut chars = unsigned.chars();
+    match (chars.next(), chars.next()) {
+        (Some('.'), Some(ch)) | (Some(ch), _) => ch.is_ascii_digit(),
+        _ => false,
+    }
+}
+
 /// Determines whether or not the specified character is a valid identifier.
 fn is_valid_ident(ch: char) -> bool {
     match ch {
@@ -382,6 +632,46 @@ mod tests {
         assert_eq!(&found, &expected);
     }
 
+    #[test]
+    fn test_parse_infix() {
+        fn parse(input: &str) -> String {
+            Parser::new(input.chars()).parse_expr().unwrap().to_string()
+        }
+        assert_eq!(parse("{1 + 2 + 3}"), "(+ 1 2 3)");
+        assert_eq!(parse("{xs append ys}"), "(append xs ys)");
+        assert_eq!(parse("{1 + 2 * 3}"), "(+ 1 (* 2 3))");
+        assert_eq!(parse("{1 * 2 + 3}"), "(+ (* 1 2) 3)");
+        assert_eq!(parse("{1 + 2 + 3 * 4 * 5}"), "(+ 1 2 (* 3 4 5))");
+        assert_eq!(parse("{1 - 2 + 3}"), "(+ (- 1 2) 3)");
+        assert_eq!(parse("{a + 1 < b * 2}"), "(< (+ a 1) (* b 2))");
+        assert_eq!(parse("{a = b % 2 + 1}"), "(= a (+ (% b 2) 1))");
+
+        // Parenthesized and nested subexpressions are single operands
+        assert_eq!(parse("{(f 1 2) * 3 + 4}"), "(+ (* (f 1 2) 3) 4)");
+        assert_eq!(parse("{{1 + 2} * 3 - 4}"), "(- (* (+ 1 2) 3) 4)");
+        assert_eq!(parse("{}"), "()");
+
+        // Operands may be preceded by signs
+        assert_eq!(parse("{-3 + 4}"), "(+ -3 4)");
+        assert_eq!(parse("{- x + 4}"), "(+ (- x) 4)");
+        assert_eq!(parse("{+ x * - y}"), "(* x (- y))");
+        assert_eq!(parse("{- - x}"), "(- (- x))");
+        assert_eq!(parse("{(+ 1 2) * 3}"), "(* (+ 1 2) 3)");
+        assert_eq!(parse("{5}"), "5");
+    }
+
+    #[test]
+    fn test_parse_infix_errors() {
+        assert_eq!(
+            syntax_desc("{1 + 2 max 3}"),
+            "syntax error: infix operator `max` cannot be mixed with other \
+             operators at line 1, col 8"
+        );
+        assert!(syntax_desc("{1 + 2 * }").contains("missing a final operand"));
+        assert!(syntax_desc("{1 + }").contains("missing a final operand"));
+        assert!(syntax_desc("{1 + -}").contains("missing a final operand"));
+    }
+
     #[test]
     fn test_parse_num() {
         let input = "4.73".chars();
@@ -399,4 +689,223 @@ mod tests {
         let expected = Some(Expression::Str("Hello, world!".into()));
         assert_eq!(&found, &expected);
     }
+
+    /// Produces the description of the syntax error parsed from the specified
+    /// input.
+    fn syntax_desc(input: &str) -> String {
+        match Parser::new(input.chars()).parse_all() {
+            Error(ex) => format!("{}", ex),
+            ex => panic!("expected a syntax error, found {}", ex),
+        }
+    }
+
+    #[test]
+    fn test_error_position() {
+        assert_eq!(
+            syntax_desc("(+ 1 2))"),
+            "syntax error: unexpected list close at line 1, col 8"
+        );
+        assert_eq!(
+            syntax_desc("(define x 1)\n(display\n   (+ x 1)"),
+            "syntax error: unclosed list at line 2, col 1"
+        );
+        assert_eq!(
+            syntax_desc("foo\n  \"bar"),
+            "syntax error: unclosed string literal at line 2, col 3"
+        );
+    }
+
+    #[test]
+    fn test_position_unread() {
+        let mut parser = Parser::new("foo\nbar".chars());
+        parser.parse_expr();
+        assert_eq!(parser.position(), Position { line: 1, col: 4 });
+        parser.parse_expr();
+        assert_eq!(parser.position(), Position { line: 2, col: 4 });
+    }
+
+    #[test]
+    fn test_try_parse() {
+        let mut parser = Parser::new("(1 2) \"abc\"".chars());
+        assert_eq!(
+            parser.try_parse_all().map_err(|ex| ex.error_code()),
+            Ok(vec![
+                Cons(ConsList::from(vec![Num(1.0), Num(2.0)])),
+                Str("abc".into())
+            ])
+        );
+
+        let mut parser = Parser::new("(1 (2 3)".chars());
+        match parser.try_parse_expr() {
+            Err(ex) => assert_eq!(ex.error_code(), 6),
+            res => panic!("expected an error, found {:?}", res),
+        }
+
+        let mut parser = Parser::new("'(1 2".chars());
+        match parser.try_parse_all() {
+            Err(ex) => assert_eq!(ex.error_code(), 6),
+            res => panic!("expected an error, found {:?}", res),
+        }
+
+        let mut parser = Parser::new("".chars());
+        assert!(parser.try_parse_expr().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dotted_pair() {
+        assert_eq!(
+            syntax_desc("(1 . 2)"),
+            "syntax error: dotted pairs are not supported at line 1, col 4"
+        );
+        assert_eq!(
+            syntax_desc("'(a (b . c))"),
+            "syntax error: dotted pairs are not supported at line 1, col 8"
+        );
+
+        // Symbols merely containing a dot are unaffected
+        let mut parser = Parser::new("(a .b .5)".chars());
+        assert_eq!(
+            parser.parse_expr(),
+            Some(Cons(ConsList::from(vec![
+                Symbol("a".into()),
+                Symbol(".b".into()),
+                Num(0.5)
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_parse_vector() {
+        let mut parser = Parser::new("#(1 2 3)".chars());
+        assert_eq!(
+            parser.parse_expr(),
+            Some(Vector(Rc::new(vec![Num(1.0), Num(2.0), Num(3.0)])))
+        );
+
+        let mut parser = Parser::new("#()".chars());
+        assert_eq!(parser.parse_expr(), Some(Vector(Rc::new(vec![]))));
+
+        let mut parser = Parser::new("#(a #(b)) #| comment |# 4".chars());
+        assert_eq!(
+            parser.try_parse_all().map_err(|ex| ex.error_code()),
+            Ok(vec![
+                Vector(Rc::new(vec![
+                    Symbol("a".into()),
+                    Vector(Rc::new(vec![Symbol("b".into())]))
+                ])),
+                Num(4.0)
+            ])
+        );
+
+        assert_eq!(
+            syntax_desc("#(1 2"),
+            "syntax error: unclosed list at line 1, col 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_sign() {
+        let parse = |s: &str| Parser::new(s.chars()).parse_expr().unwrap();
+        assert_eq!(parse("-5"), Num(-5.0));
+        assert_eq!(parse("+.5"), Num(0.5));
+        assert_eq!(parse("-"), Symbol("-".into()));
+        assert_eq!(parse("-foo"), Symbol("-foo".into()));
+        assert_eq!(parse("-inf"), Symbol("-inf".into()));
+        assert_eq!(parse("vec2i"), Symbol("vec2i".into()));
+        assert_eq!(
+            parse("-5i"),
+            Quaternion(Rc::new(Quat(0.0, -5.0, 0.0, 0.0)))
+        );
+        assert_eq!(
+            parse("(- 1 2)"),
+            Cons(ConsList::from(vec![Symbol("-".into()), Num(1.0), Num(2.0)]))
+        );
+    }
+
+    #[test]
+    fn test_parse_unicode_escape() {
+        let parse = |s: &str| Parser::new(s.chars()).parse_expr().unwrap();
+        assert_eq!(parse(r#""\u{1F600}""#), Str("\u{1F600}".into()));
+        assert_eq!(parse(r#""a\u{e9}b""#), Str("a\u{e9}b".into()));
+        assert_eq!(parse(r#""back\\slash""#), Str("back\\slash".into()));
+        assert_eq!(
+            syntax_desc(r#""\u{110000}""#),
+            "syntax error: invalid unicode escape at line 1, col 2"
+        );
+        assert_eq!(
+            syntax_desc(r#""ok" "\u{D800}""#),
+            "syntax error: invalid unicode escape at line 1, col 7"
+        );
+        assert_eq!(
+            syntax_desc(r#""\u1234""#),
+            "syntax error: invalid unicode escape at line 1, col 2"
+        );
+        assert_eq!(
+            syntax_desc(r#""\u{}""#),
+            "syntax error: invalid unicode escape at line 1, col 2"
+        );
+    }
+
+    #[test]
+    fn test_nested_block_comment() {
+        let mut parser =
+            Parser::new("#| outer #| inner |# still commented |# 1".chars());
+        assert_eq!(parser.parse_expr(), Some(Num(1.0)));
+
+        let mut parser = Parser::new("#| a ||# 2".chars());
+        assert_eq!(parser.parse_expr(), Some(Num(2.0)));
+
+        assert_eq!(
+            syntax_desc("1\n#| outer #| inner |# 2"),
+            "syntax error: unclosed block comment at line 2, col 1"
+        );
+    }
+
+    #[test]
+    fn test_list_positions() {
+        use rlisp_interpreter::location::{position_of, symbol_position_of};
+
+        let mut parser = Parser::new("(a\n  [b c] {1 + 2})".chars());
+        let list = match parser.parse_expr() {
+            Some(Cons(list)) => list,
+            other => panic!("expected a list, found {:?}", other),
+        };
+        assert_eq!(position_of(&list), Some(Position { line: 1, col: 1 }));
+        assert_eq!(
+            symbol_position_of(&list.head().unwrap()),
+            Some(Position { line: 1, col: 2 })
+        );
+        let positions: Vec<_> = list
+            .iter()
+            .skip(1)
+            .map(|expr| match expr.as_ref() {
+                Cons(list) => position_of(list),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                Some(Position { line: 2, col: 3 }),
+                Some(Position { line: 2, col: 9 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_located() {
+        let mut parser = Parser::new("; comment\n  (a b)\n#| c |# 'x".chars());
+        assert_eq!(
+            parser.try_parse_located().unwrap().map(|(pos, _)| pos),
+            Some(Position { line: 2, col: 3 })
+        );
+        assert_eq!(
+            parser
+                .try_parse_located()
+                .unwrap()
+                .map(|(pos, expr)| (pos, expr.to_string())),
+            Some((Position { line: 3, col: 9 }, "'x".to_string()))
+        );
+        assert_eq!(parser.try_parse_located().unwrap(), None);
+    }
 }
@@ -13,58 +13,127 @@
 //!   (* x x))
 //! ```
 
+use rlisp_interpreter::exception::Exception;
+
 /// Runs the first pass of the preprocessor on the specified string. The first
-/// pass strips comments and adds parentheses as needed based on colons.
+/// pass strips comments and adds parentheses as needed based on colons. The
+/// contents of string literals are left untouched.
 pub fn first_pass(s: String) -> String {
     let mut buf = String::with_capacity(s.len());
     let mut iter = s.chars();
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut colon_open = false;
     while let Some(ch) = iter.next() {
+        if in_str {
+            buf.push(ch);
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == '"' {
+                in_str = false;
+            }
+            continue;
+        }
         match ch {
+            '"' => {
+                in_str = true;
+                buf.push(ch);
+            }
             ';' => {
+                // Skip to the end of the line, which is handled as usual
                 while let Some(ch) = iter.next() {
-                    match ch {
-                        ch if ch == '\n' => {
-                            buf.push(ch);
-                            break;
+                    if ch == '\n' {
+                        if colon_open {
+                            buf.push(')');
+                            colon_open = false;
                         }
-                        _ => (),
+                        buf.push(ch);
+                        break;
                     }
                 }
             }
-            ':' => {
+            ':' if !colon_open => {
                 buf.push('(');
-                while let Some(ch) = iter.next() {
-                    match ch {
-                        ch if ch == '\n' => {
-                            buf.push(')');
-                            buf.push(ch);
-                            break;
-                        }
-                        ch => buf.push(ch),
-                    }
+                colon_open = true;
+            }
+            '\n' => {
+                if colon_open {
+                    buf.push(')');
+                    colon_open = false;
                 }
+                buf.push(ch);
             }
             ch => buf.push(ch),
         }
     }
+    if colon_open {
+        buf.push(')');
+    }
     buf
 }
 
+/// The number of spaces that a tab is treated as when measuring indentation,
+/// unless otherwise specified.
+pub const DEFAULT_TAB_WIDTH: u32 = 4;
+
 /// Runs the second pass of the preprocessor over the specified string.
-/// Parentheses are inserted based on indentation.
-pub fn second_pass(s: String) -> String {
-    let mut buf = String::with_capacity(s.len());
-    let indentations = s.lines().map(|line| {
-        let mut indents = 0;
-        for ch in line.chars() {
-            if ch.is_whitespace() {
+/// Parentheses are inserted based on indentation, with tabs treated as
+/// `DEFAULT_TAB_WIDTH` spaces.
+pub fn second_pass(s: String) -> Result<String, Exception> {
+    second_pass_with_tab_width(s, DEFAULT_TAB_WIDTH)
+}
+
+/// Measures the indentation of the specified line, treating tabs as the
+/// specified number of spaces. Lines indented with both tabs and spaces are
+/// ambiguous, so an exception is produced for them.
+fn indentation(
+    line_num: usize,
+    line: &str,
+    tab_width: u32,
+) -> Result<u32, Exception> {
+    let mut indents = 0;
+    let (mut has_tabs, mut has_spaces) = (false, false);
+    for ch in line.chars() {
+        match ch {
+            '\t' => {
+                has_tabs = true;
+                indents += tab_width;
+            }
+            ch if ch.is_whitespace() => {
+                has_spaces = true;
                 indents += 1;
-            } else {
-                break;
             }
+            _ => break,
         }
-        indents
-    });
+    }
+    if has_tabs && has_spaces && !line.trim().is_empty() {
+        Err(Exception::syntax(
+            47,
+            format!(
+                "mixed tabs and spaces in indentation at line {}",
+                line_num
+            ),
+        ))
+    } else {
+        Ok(indents)
+    }
+}
+
+/// Runs the second pass of the preprocessor over the specified string.
+/// Parentheses are inserted based on indentation, with tabs treated as the
+/// specified number of spaces.
+pub fn second_pass_with_tab_width(
+    s: String,
+    tab_width: u32,
+) -> Result<String, Exception> {
+    let mut buf = String::with_capacity(s.len());
+    let indentations = s
+        .lines()
+        .enumerate()
+        .map(|(i, line)| indentation(i + 1, line, tab_width))
+        .collect::<Result<Vec<_>, _>>()?;
 
     let lines = s.lines().map(|line| line.trim());
 
@@ -101,5 +170,60 @@ pub fn second_pass(s: String) -> String {
         indent_layers = indent_layers2;
     }
 
-    buf
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_pass_strings() {
+        let s = "(display \"ratio 3:1\")\n(display \"foo ; bar\")\n";
+        assert_eq!(first_pass(s.to_string()), s);
+
+        let s = "display : \"a:b;c\" ; comment\nx\n";
+        assert_eq!(first_pass(s.to_string()), "display ( \"a:b;c\" )\nx\n");
+
+        // Escaped quotes do not end strings
+        let s = "\"say \\\"hi:\\\"\" ; comment";
+        assert_eq!(first_pass(s.to_string()), "\"say \\\"hi:\\\"\" ");
+    }
+
+    #[test]
+    fn test_first_pass_colons() {
+        assert_eq!(
+            first_pass("define : square x\n  * x x\n".to_string()),
+            "define ( square x)\n  * x x\n"
+        );
+        assert_eq!(first_pass("a : b : c".to_string()), "a ( b : c)");
+    }
+
+    #[test]
+    fn test_tab_indentation() {
+        let spaces = "define : square x\n    * x x\n".to_string();
+        let tabs = "define : square x\n\t* x x\n".to_string();
+        let expected = second_pass(first_pass(spaces)).unwrap();
+        assert_eq!(expected, " (define ( square x) (* x x))");
+        assert_eq!(second_pass(first_pass(tabs)).unwrap(), expected);
+
+        // Tabs and spaces may be used on separate lines
+        let s = "a\n\tb\n    c\n".to_string();
+        assert_eq!(second_pass(s.clone()).unwrap(), " (a (b) (c))");
+        assert_eq!(second_pass_with_tab_width(s, 2).unwrap(), " (a (b (c)))");
+    }
+
+    #[test]
+    fn test_mixed_indentation() {
+        let s = "a\n  b\n \tc\n".to_string();
+        let err = second_pass(s).unwrap_err();
+        assert_eq!(err.error_code(), 47);
+        assert_eq!(
+            err.to_string(),
+            "syntax error: mixed tabs and spaces in indentation at line 3"
+        );
+
+        // Blank lines are not checked
+        assert!(second_pass("a\n \t\n  b\n".to_string()).is_ok());
+    }
 }
@@ -2,12 +2,39 @@ use crate::repl::run_repl;
 use clap::{App, Arg, ArgMatches};
 use std::env;
 use std::path::Path;
+use std::process;
 
-use rlisp_interpreter::{expression::Expression::*, util::print_stack_trace};
-use rlisp_intrinsics::{functions::import, init_context};
+use rlisp_interpreter::{
+    context::Context,
+    expression::Expression::{self, *},
+    util::print_stack_trace,
+};
+use rlisp_intrinsics::{
+    functions::{check_path, import},
+    init_context,
+};
+use rlisp_parser::Parser;
 
-fn rlisp_home() -> String {
-    env::var("RLISP_HOME").expect("RLISP_HOME not defined")
+/// Resolves the location of the standard library loader, preferring the
+/// location explicitly specified with `--lib` over the `loader.rl` file within
+/// the specified `RLISP_HOME` directory. An error message is produced if
+/// neither is present.
+fn lib_location(
+    lib_loc: Option<&str>,
+    rlisp_home: Option<String>,
+) -> Result<String, String> {
+    match (lib_loc, rlisp_home) {
+        (Some(lib_loc), _) => Ok(lib_loc.to_string()),
+        (None, Some(home)) => {
+            let mut home_path = Path::new(&home).to_path_buf();
+            home_path.push("loader.rl");
+            Ok(home_path.to_string_lossy().into_owned())
+        }
+        (None, None) => Err("RLISP_HOME is not defined; set it to the \
+             directory containing the standard library, or specify the \
+             loader file with --lib"
+            .to_string()),
+    }
 }
 
 fn create_app<'a>() -> ArgMatches<'a> {
@@ -26,6 +53,19 @@ fn create_app<'a>() -> ArgMatches<'a> {
             .help("Sets the input file to interpret")
             .required(false)
             .index(1))
+        .arg(Arg::with_name("eval")
+            .short("e")
+            .long("eval")
+            .value_name("EXPR")
+            .help("Evaluates the specified expression after loading the input and prints its result")
+            .takes_value(true)
+            .required(false))
+        .arg(Arg::with_name("check")
+            .long("check")
+            .takes_value(false)
+            .help("Parses the input file, reporting any syntax errors without evaluating it")
+            .requires("INPUT")
+            .required(false))
         .arg(Arg::with_name("interactive")
             .short("i")
             .long("interactive")
@@ -39,43 +79,226 @@ fn create_app<'a>() -> ArgMatches<'a> {
         .get_matches()
 }
 
+/// Determines the exit code that the program should terminate with after
+/// producing the specified top-level result, or `None` if it should not exit.
+fn exit_code(res: &Expression) -> Option<i32> {
+    match res {
+        Error(_) => Some(1),
+        _ => None,
+    }
+}
+
+/// Determines the exit code that the program should terminate with after the
+/// specified result of a REPL session. Uncaught exceptions from the REPL only
+/// terminate the program with a failing exit code if the context has
+/// exit-on-error enabled.
+fn repl_exit_code(res: &Expression, ctx: &Context) -> Option<i32> {
+    if ctx.exit_on_error() {
+        exit_code(res)
+    } else {
+        None
+    }
+}
+
+/// Reports any exception in the specified top-level result, exiting the
+/// program with the specified exit code if one is present.
+fn check_result(res: &Expression, code: Option<i32>) {
+    if let Error(ex) = res {
+        print_stack_trace(ex);
+    }
+    if let Some(code) = code {
+        process::exit(code);
+    }
+}
+
+/// Runs an interactive REPL session, reporting any uncaught exception.
+fn run_session(ctx: &mut Context) {
+    let res = run_repl(ctx);
+    check_result(&res, repl_exit_code(&res, ctx));
+}
+
+/// Parses the specified input file without evaluating it, reporting any syntax
+/// error. Produces the exit code that the program should terminate with.
+fn check_input(input: &str) -> i32 {
+    match check_path(input) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("{}: {}", input, err);
+            1
+        }
+    }
+}
+
+/// An action to be performed after loading the standard library.
+#[derive(Debug, PartialEq)]
+enum Action<'a> {
+    /// Loads the specified input file.
+    Load(&'a str),
+
+    /// Evaluates the specified expression and prints its result.
+    Eval(&'a str),
+
+    /// Starts an interactive REPL session.
+    Repl,
+}
+
+/// Determines the actions to perform, in order, given the specified input
+/// file, expression to evaluate, and whether or not interactive mode is
+/// enabled. A REPL session is started if interactive mode is enabled or if
+/// there is nothing else to do.
+fn actions<'a>(
+    input: Option<&'a str>,
+    eval: Option<&'a str>,
+    interactive: bool,
+) -> Vec<Action<'a>> {
+    let mut actions = Vec::new();
+    if let Some(input) = input {
+        actions.push(Action::Load(input));
+    }
+    if let Some(expr) = eval {
+        actions.push(Action::Eval(expr));
+    }
+    if interactive || actions.is_empty() {
+        actions.push(Action::Repl);
+    }
+    actions
+}
+
 pub fn run() {
     let matches = create_app();
 
-    let lib_loc = matches
-        .value_of("lib-loc")
-        .map(ToString::to_string)
-        .unwrap_or_else(|| {
-            let home_path = rlisp_home();
-            let mut home_path = Path::new(&home_path).to_path_buf();
-            home_path.push("loader.rl");
-            home_path.into_os_string().into_string().unwrap()
-        });
+    if matches.is_present("check") {
+        let input = matches.value_of("INPUT").expect("input is required");
+        process::exit(check_input(input));
+    }
+
+    let lib_loc = match lib_location(
+        matches.value_of("lib-loc"),
+        env::var("RLISP_HOME").ok(),
+    ) {
+        Ok(lib_loc) => lib_loc,
+        Err(msg) => {
+            eprintln!("error: {}", msg);
+            process::exit(1);
+        }
+    };
 
     let mut ctx = init_context(env!("CARGO_PKG_VERSION"));
+    ctx.set_program_args(
+        matches
+            .values_of("program_args")
+            .map(|args| args.map(|arg| arg.into()).collect())
+            .unwrap_or_default(),
+    );
     let res = import(&[Str(lib_loc.into())], &mut ctx);
+    check_result(&res, exit_code(&res));
 
-    if let Error(ex) = res {
-        print_stack_trace(&ex);
-        return;
-    }
-
-    match matches.value_of("INPUT") {
-        Some(input) => {
-            // Load input file
-            ctx.remove("__FILE__");
-            let res = import(&[Str(input.into())], &mut ctx);
-            if let Error(ex) = res {
-                print_stack_trace(&ex);
-                return;
+    let actions = actions(
+        matches.value_of("INPUT"),
+        matches.value_of("eval"),
+        matches.is_present("interactive"),
+    );
+    for action in actions {
+        match action {
+            Action::Load(input) => {
+                ctx.remove("__FILE__");
+                let res = import(&[Str(input.into())], &mut ctx);
+                check_result(&res, exit_code(&res));
             }
-
-            if matches.is_present("interactive") {
-                run_repl(&mut ctx);
+            Action::Eval(expr) => {
+                let res = Parser::new(expr.chars()).parse_all().eval(&mut ctx);
+                check_result(&res, exit_code(&res));
+                println!("{}", res);
             }
+            Action::Repl => run_session(&mut ctx),
         }
-        None => {
-            run_repl(&mut ctx);
-        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlisp_interpreter::exception::Exception;
+    use std::{fs, rc::Rc};
+
+    #[test]
+    fn test_exit_code() {
+        let err = Error(Rc::new(Exception::undefined("x")));
+        assert_eq!(exit_code(&err), Some(1));
+        assert_eq!(exit_code(&Expression::default()), None);
+        assert_eq!(exit_code(&Num(1.0)), None);
+    }
+
+    #[test]
+    fn test_exit_on_error() {
+        let mut ctx = Context::new();
+        let err = Error(Rc::new(Exception::undefined("x")));
+        let ok = Expression::default();
+        assert_eq!(repl_exit_code(&err, &ctx), None);
+        assert_eq!(repl_exit_code(&ok, &ctx), None);
+
+        ctx.set_exit_on_error(true);
+        assert_eq!(repl_exit_code(&err, &ctx), Some(1));
+        assert_eq!(repl_exit_code(&ok, &ctx), None);
+    }
+
+    #[test]
+    fn test_actions() {
+        use self::Action::*;
+        assert_eq!(actions(None, None, false), vec![Repl]);
+        assert_eq!(actions(None, None, true), vec![Repl]);
+        assert_eq!(actions(Some("a.rl"), None, false), vec![Load("a.rl")]);
+        assert_eq!(actions(Some("a.rl"), None, true), vec![Load("a.rl"), Repl]);
+        assert_eq!(
+            actions(None, Some("(+ 1 2)"), false),
+            vec![Eval("(+ 1 2)")]
+        );
+        assert_eq!(
+            actions(None, Some("(+ 1 2)"), true),
+            vec![Eval("(+ 1 2)"), Repl]
+        );
+        assert_eq!(
+            actions(Some("a.rl"), Some("x"), false),
+            vec![Load("a.rl"), Eval("x")]
+        );
+    }
+
+    #[test]
+    fn test_lib_location() {
+        assert_eq!(
+            lib_location(Some("lib/loader.rl"), None),
+            Ok("lib/loader.rl".to_string())
+        );
+        assert_eq!(
+            lib_location(Some("lib/loader.rl"), Some("home".to_string())),
+            Ok("lib/loader.rl".to_string())
+        );
+        assert_eq!(
+            lib_location(None, Some("home".to_string())),
+            Ok(Path::new("home")
+                .join("loader.rl")
+                .to_string_lossy()
+                .into_owned())
+        );
+        let err = lib_location(None, None).unwrap_err();
+        assert!(err.contains("RLISP_HOME"));
+        assert!(err.contains("--lib"));
+    }
+
+    #[test]
+    fn test_check_input() {
+        let path =
+            env::temp_dir().join(format!("rlisp-check-{}.rl", process::id()));
+        let path = path.to_str().unwrap();
+
+        // Evaluating the file would exit the test process
+        fs::write(path, "(exit 3)\n(display \"unclosed)\n").unwrap();
+        assert_eq!(check_input(path), 1);
+
+        fs::write(path, "(exit 3)\n").unwrap();
+        assert_eq!(check_input(path), 0);
+
+        fs::remove_file(path).unwrap();
+        assert_eq!(check_input(path), 1);
     }
 }
@@ -2,6 +2,11 @@ pub extern crate rlisp_interpreter as interpreter;
 pub extern crate rlisp_intrinsics as intrinsics;
 pub extern crate rlisp_parser as parser;
 
+use interpreter::{
+    context::Context, exception::Exception, expression::Expression,
+};
+use parser::Parser;
+
 #[cfg(feature = "native")]
 pub mod app;
 
@@ -10,5 +15,45 @@ pub mod repl;
 pub mod prelude {
     #[cfg(feature = "native")]
     pub use crate::app::*;
+    pub use crate::eval_str;
     pub use crate::repl::*;
 }
+
+/// Parses and evaluates the specified program in the specified context,
+/// producing the value of its final expression or the first exception thrown.
+///
+/// # Examples
+/// ```
+/// use rlisp::{interpreter::expression::Expression, intrinsics::init_context};
+/// use rlisp::prelude::*;
+///
+/// let mut ctx = init_context("example");
+/// let res = eval_str("(+ 1 2 3)", &mut ctx);
+/// assert_eq!(res.ok(), Some(Expression::Num(6.0)));
+/// ```
+pub fn eval_str(src: &str, ctx: &mut Context) -> Result<Expression, Exception> {
+    Parser::new(src.chars()).parse_all().eval(ctx).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intrinsics::init_context;
+
+    #[test]
+    fn test_eval_str() {
+        let mut ctx = init_context("test");
+        assert_eq!(
+            eval_str("(+ 1 2 3)", &mut ctx).ok(),
+            Some(Expression::Num(6.0))
+        );
+        assert_eq!(
+            eval_str("(define x 5) (* x 2)", &mut ctx).ok(),
+            Some(Expression::Num(10.0))
+        );
+        let ex = eval_str("(undefined-fn)", &mut ctx).unwrap_err();
+        assert_eq!(ex.error_code(), 1);
+        let ex = eval_str("(+ 1", &mut ctx).unwrap_err();
+        assert!(ex.to_string().contains("line 1"));
+    }
+}
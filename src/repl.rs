@@ -2,25 +2,21 @@
 // use rlisp_core::prelude::*;
 // use rlisp_core::util::print_stack_trace;
 
-use rlisp_interpreter::{
-    context::Context, expression::Expression::*, util::print_stack_trace,
-};
+use rlisp_interpreter::{context::Context, expression::Expression};
 use rlisp_parser::Parser;
 
 const REPL: &str = r#"
     (interactive-start)
 "#;
 
-pub fn run_repl(ctx: &mut Context) {
+/// Runs an interactive REPL session in the specified context, producing the
+/// result of the session.
+pub fn run_repl(ctx: &mut Context) -> Expression {
     Parser::new(REPL.chars())
         .parse_expr()
         .map(|expr| expr.eval(ctx))
-        .map(|res| {
-            if let Error(ex) = res {
-                print_stack_trace(&ex);
-            }
-        })
         .unwrap_or_else(|| {
             println!("unknown error occurred");
-        });
+            Expression::default()
+        })
 }